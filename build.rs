@@ -0,0 +1,33 @@
+// generates the C header for the `rsp_*` functions in src/capi.rs into OUT_DIR.
+// the tracked include/rsplayer.h is only rewritten when RSPLAYER_UPDATE_HEADER is set,
+// a build that merely falls out of step with it warns instead.
+fn main() {
+    println!("cargo:rerun-if-changed=src/capi.rs");
+    println!("cargo:rerun-if-changed=src/error.rs");
+    println!("cargo:rerun-if-changed=src/movie_state.rs");
+    println!("cargo:rerun-if-changed=cbindgen.toml");
+    println!("cargo:rerun-if-changed=include/rsplayer.h");
+    println!("cargo:rerun-if-env-changed=RSPLAYER_UPDATE_HEADER");
+
+    let crate_dir = std::env::var("CARGO_MANIFEST_DIR").unwrap();
+    let out_dir = std::env::var("OUT_DIR").unwrap();
+    let config = cbindgen::Config::from_file(format!("{}/cbindgen.toml", crate_dir))
+        .expect("cbindgen.toml is not valid");
+
+    let bindings = cbindgen::Builder::new()
+        .with_crate(&crate_dir)
+        .with_config(config)
+        .generate()
+        .expect("unable to generate the C header from src/capi.rs");
+    let generated = format!("{}/rsplayer.h", out_dir);
+    bindings.write_to_file(&generated);
+
+    let tracked = format!("{}/include/rsplayer.h", crate_dir);
+    if std::env::var_os("RSPLAYER_UPDATE_HEADER").is_some() {
+        std::fs::copy(&generated, &tracked).expect("unable to write include/rsplayer.h");
+    } else if std::fs::read(&generated).ok() != std::fs::read(&tracked).ok() {
+        println!(
+            "cargo:warning=include/rsplayer.h differs from src/capi.rs, rebuild with RSPLAYER_UPDATE_HEADER=1 to regenerate it"
+        );
+    }
+}
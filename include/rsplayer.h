@@ -0,0 +1,233 @@
+#ifndef RSPLAYER_H
+#define RSPLAYER_H
+
+/* generated by cbindgen from src/capi.rs, do not edit */
+
+#include <stdint.h>
+#include <stddef.h>
+
+// what a movie does once the demuxer reaches the end of the file
+typedef enum EndBehavior {
+  // rewind and play again from the start
+  END_BEHAVIOR_LOOP,
+  // hold the last frame until the movie is seeked
+  END_BEHAVIOR_STOP_ON_LAST_FRAME,
+  // play backwards to the start, then forwards again
+  END_BEHAVIOR_PING_PONG,
+  // stop reading the movie, its threads wind down
+  END_BEHAVIOR_CLOSE,
+} EndBehavior;
+
+// C compatible status codes for the cdylib entry points.
+// 0 is success, 1 to 5, 7, 11, 12 and 14 map to a `MovieError` variant, 13 to a `FilterError`.
+typedef enum MovieStatus {
+  MOVIE_STATUS_OK = 0,
+  MOVIE_STATUS_OPEN_INPUT = 1,
+  MOVIE_STATUS_STREAM_INFO = 2,
+  MOVIE_STATUS_NO_VIDEO_STREAM = 3,
+  MOVIE_STATUS_CODEC_PARAMETERS = 4,
+  MOVIE_STATUS_CODEC_OPEN = 5,
+  MOVIE_STATUS_INVALID_ARGUMENT = 6,
+  MOVIE_STATUS_INVALID_VIDEO_STREAM = 7,
+  // the analyzer has not been started, or was stopped
+  MOVIE_STATUS_NOT_STARTED = 8,
+  // the call is only valid before the analyzer is started
+  MOVIE_STATUS_ALREADY_STARTED = 9,
+  // no frame is due yet, try again later
+  MOVIE_STATUS_NO_FRAME = 10,
+  MOVIE_STATUS_INVALID_SUBTITLE_STREAM = 11,
+  MOVIE_STATUS_UNSUPPORTED_DECODER = 12,
+  // the filter description could not be parsed or configured
+  MOVIE_STATUS_INVALID_FILTER = 13,
+  MOVIE_STATUS_INVALID_MOVIE_INDEX = 14,
+  // no event is waiting, try again later
+  MOVIE_STATUS_NO_EVENT = 15,
+  MOVIE_STATUS_NOT_PAUSED = 16,
+} MovieStatus;
+
+typedef enum RspCommandKind {
+  RSP_COMMAND_KIND_PAUSE,
+  RSP_COMMAND_KIND_RESUME,
+  RSP_COMMAND_KIND_TOGGLE_PAUSE,
+  RSP_COMMAND_KIND_STEP,
+  // uses `position` and `mode`
+  RSP_COMMAND_KIND_SEEK,
+  // uses `speed`
+  RSP_COMMAND_KIND_SET_SPEED,
+  // uses `movie_index`
+  RSP_COMMAND_KIND_SOLO_AUDIO,
+  // uses `movie_index` and `stream_index`
+  RSP_COMMAND_KIND_SELECT_VIDEO_STREAM,
+  // uses `movie_index` and `stream_index`, `UINT32_MAX` turns subtitles off
+  RSP_COMMAND_KIND_SELECT_SUBTITLE_STREAM,
+  // uses `movie_index` and `end_behavior`
+  RSP_COMMAND_KIND_SET_END_BEHAVIOR,
+  // uses `enabled`
+  RSP_COMMAND_KIND_SET_SYNCHRONIZED,
+  // uses `movie_index` and `offset`
+  RSP_COMMAND_KIND_SET_TIME_OFFSET,
+  // uses `enabled`
+  RSP_COMMAND_KIND_SET_METRICS,
+  RSP_COMMAND_KIND_STEP_BACK,
+  // uses `enabled`, 1 plays backwards
+  RSP_COMMAND_KIND_SET_REVERSE,
+} RspCommandKind;
+
+// what `RspEvent.kind` says happened, see `AnalyzerEvent`
+typedef enum RspEventKind {
+  // `command` has taken effect
+  RSP_EVENT_KIND_APPLIED,
+  // the movies took `command` and carry it out next
+  RSP_EVENT_KIND_QUEUED,
+  // `command` does not apply to this kind of player
+  RSP_EVENT_KIND_IGNORED,
+  // `command` was rejected, `reason` says why
+  RSP_EVENT_KIND_FAILED,
+  // movie `movie_index` reached the end of the file
+  RSP_EVENT_KIND_END_OF_STREAM,
+  RSP_EVENT_KIND_MOVIE_ADDED,
+  RSP_EVENT_KIND_MOVIE_REMOVED,
+  RSP_EVENT_KIND_MOVIE_REPLACED,
+  // the frames of movie `movie_index` are now `width` x `height` in `format`
+  RSP_EVENT_KIND_FORMAT_CHANGED,
+  // the control thread is done and every movie thread has been joined
+  RSP_EVENT_KIND_STOPPED,
+  // movie `movie_index` carried out the `Queued` `command`
+  RSP_EVENT_KIND_MOVIE_APPLIED,
+  // movie `movie_index` could not carry out the `Queued` `command`, `reason` says why
+  RSP_EVENT_KIND_MOVIE_FAILED,
+} RspEventKind;
+
+typedef enum SeekMode {
+  // land on the keyframe at or before the target
+  SEEK_MODE_KEYFRAME,
+  // decode from the keyframe and discard frames until the target
+  SEEK_MODE_ACCURATE,
+} SeekMode;
+
+// an analyzer and, once started, the channel to its control thread
+typedef struct RspAnalyzer RspAnalyzer;
+
+// planes of a decoded, filtered frame. the pointers stay valid until the
+// frame is passed to `rsp_frame_release`.
+typedef struct RspFrame {
+  int32_t width;
+  int32_t height;
+  // an `AVPixelFormat` value, yuv420p, nv12, nv21, rgb24, bgr24, rgba or bgra
+  int32_t format;
+  int64_t pts;
+  // dts of the packet the frame came from
+  int64_t pkt_dts;
+  // size in bytes of the packet the frame came from, -1 when unknown
+  int32_t pkt_size;
+  // 'I', 'P', 'B' and so on, '?' when unknown
+  char pict_type;
+  // 1 for a key frame
+  int32_t key_frame;
+  // `FF_DECODE_ERROR_*` bits, 0 for a clean frame
+  int32_t decode_error_flags;
+  // seconds to wait before showing this frame
+  double delay;
+  const uint8_t *data[4];
+  int32_t linesize[4];
+  // the underlying `AVFrame`, owned by the library
+  void *handle;
+} RspFrame;
+
+// an event from `rsp_poll_event`, only the fields named by `kind` are set
+typedef struct RspEvent {
+  // a `RspEventKind`
+  uint32_t kind;
+  // a `RspCommandKind`, `UINT32_MAX` for commands sent another way, such as `rsp_set_filter`
+  uint32_t command;
+  // `UINT32_MAX` when the event is not about one movie
+  uint32_t movie_index;
+  int32_t width;
+  int32_t height;
+  // an `AVPixelFormat` value
+  int32_t format;
+  // NUL terminated, cut short when it does not fit
+  char reason[256];
+} RspEvent;
+
+// a command for `rsp_send_command`, only the fields named by `kind` are read
+typedef struct RspCommand {
+  // a `RspCommandKind`
+  uint32_t kind;
+  double position;
+  // a `SeekMode`
+  uint32_t mode;
+  float speed;
+  uint32_t movie_index;
+  uint32_t stream_index;
+  // an `EndBehavior`
+  uint32_t end_behavior;
+  // seconds
+  double offset;
+  // 0 or 1
+  int32_t enabled;
+} RspCommand;
+
+// stop the analyzer if it is running and free every movie
+void rsp_analyzer_free(struct RspAnalyzer *analyzer);
+
+// create an empty analyzer, free it with `rsp_analyzer_free`
+struct RspAnalyzer *rsp_analyzer_new(void);
+
+// give a frame from `rsp_next_frame` back to the library
+void rsp_frame_release(struct RspFrame *frame);
+
+// 1 once the movie stopped at the end of the file, 0 while it is playing,
+// -1 for a null handle or a bad index
+int32_t rsp_has_ended(const struct RspAnalyzer *analyzer, uint32_t movie_index);
+
+// number of movies attached, or -1 for a null handle
+int32_t rsp_movie_count(const struct RspAnalyzer *analyzer);
+
+// fill `frame` with the next frame that is due for `movie_index`.
+// returns `NoFrame` when nothing is due yet, `frame` is untouched then.
+int32_t rsp_next_frame(const struct RspAnalyzer *analyzer, uint32_t movie_index, struct RspFrame *frame);
+
+// open a movie file and add it to the analyzer. only valid before `rsp_start`,
+// `rsp_replace_movie` swaps movies while it runs.
+int32_t rsp_open_movie(struct RspAnalyzer *analyzer, const char *filepath);
+
+// open a movie from `len` bytes at `data` and add it to the analyzer. only
+// valid before `rsp_start`. the bytes are copied, the caller keeps ownership of `data`.
+int32_t rsp_open_movie_from_memory(struct RspAnalyzer *analyzer, const uint8_t *data, size_t len);
+
+// fill `event` with the oldest event the analyzer reported and not handed out yet.
+// returns `NoEvent` when there is none, `event` is untouched then. the events
+// up to `Stopped` stay available after `rsp_stop`.
+int32_t rsp_poll_event(struct RspAnalyzer *analyzer, struct RspEvent *event);
+
+// stop and drop a movie, the movies after it move up one index
+int32_t rsp_remove_movie(struct RspAnalyzer *analyzer, uint32_t movie_index);
+
+// open a movie file in place of the one at `movie_index`, it takes over
+// that movie's position and audio
+int32_t rsp_replace_movie(struct RspAnalyzer *analyzer, uint32_t movie_index, const char *filepath);
+
+// forward a command to the control thread. returns `InvalidArgument` for a
+// `kind`, `mode` or `end_behavior` that is not one of the enum's values.
+int32_t rsp_send_command(struct RspAnalyzer *analyzer, const struct RspCommand *command);
+
+// run a movie's video through a libavfilter description such as
+// `"hqdn3d,unsharp,eq=contrast=1.2"`, NULL shows the frames as decoded.
+// works before and after `rsp_start`. returns `InvalidFilter` when the
+// description does not parse or configure, the movie keeps its filter then.
+int32_t rsp_set_filter(struct RspAnalyzer *analyzer, uint32_t movie_index, const char *description);
+
+// show a movie turned `rotation` degrees clockwise, a multiple of 90, and
+// mirrored when `flip` is non-zero. a negative `rotation` goes back to what
+// the stream's display matrix or `rotate` tag asks for. works before and
+// after `rsp_start`.
+int32_t rsp_set_orientation(struct RspAnalyzer *analyzer, uint32_t movie_index, int32_t rotation, int32_t flip);
+
+// start the packet, decode and control threads for every movie
+int32_t rsp_start(struct RspAnalyzer *analyzer);
+
+// stop and join every thread. movies stay attached.
+int32_t rsp_stop(struct RspAnalyzer *analyzer);
+
+#endif /* RSPLAYER_H */
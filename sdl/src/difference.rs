@@ -0,0 +1,152 @@
+use std::collections::VecDeque;
+
+use log::error;
+use rusty_ffmpeg::ffi;
+use sdl2::{
+    pixels::{Color, PixelFormatEnum},
+    rect::Rect,
+    render::{BlendMode, Canvas, TextureCreator},
+    video::{Window, WindowContext},
+};
+
+use rsplayer::difference::{difference_frames, Difference, DifferenceMode, DifferenceOptions};
+
+/// how much `PageUp` and `PageDown` move the threshold
+static THRESHOLD_STEP: u8 = 2;
+/// frames kept per movie while waiting for the other movie to show the same moment
+static KEPT_FRAMES: usize = 8;
+
+/// movie 0 against movie 1 in one pane. keeps the last frames each of them
+/// showed with their offset adjusted position, and works out the difference
+/// of the newest two that show the same moment.
+pub struct DifferenceView {
+    /// `None` while the movies are shown side by side
+    pub mode: Option<DifferenceMode>,
+    pub options: DifferenceOptions,
+    frames: [VecDeque<(f64, *mut ffi::AVFrame)>; 2],
+    difference: Option<Difference>,
+    stale: bool,
+}
+
+impl Default for DifferenceView {
+    fn default() -> Self {
+        DifferenceView {
+            mode: None,
+            options: DifferenceOptions::default(),
+            frames: [VecDeque::new(), VecDeque::new()],
+            difference: None,
+            stale: false,
+        }
+    }
+}
+
+impl DifferenceView {
+    pub fn is_enabled(&self) -> bool {
+        self.mode.is_some()
+    }
+
+    /// side by side, amplified, heatmap and back
+    pub fn next_mode(&mut self) {
+        self.mode = match self.mode {
+            None => Some(DifferenceMode::Amplified),
+            Some(DifferenceMode::Amplified) => Some(DifferenceMode::Heatmap),
+            Some(DifferenceMode::Heatmap) => None,
+        };
+        if let Some(mode) = self.mode {
+            self.options.mode = mode;
+            self.rerender();
+        }
+    }
+
+    pub fn raise_threshold(&mut self) {
+        self.options.threshold = self.options.threshold.saturating_add(THRESHOLD_STEP);
+        self.rerender();
+    }
+
+    pub fn lower_threshold(&mut self) {
+        self.options.threshold = self.options.threshold.saturating_sub(THRESHOLD_STEP);
+        self.rerender();
+    }
+
+    /// hold on to a copy of the frame a movie just showed at `position`,
+    /// see `AnalyzerState::frame_position`
+    pub unsafe fn keep(&mut self, movie_index: usize, frame: *const ffi::AVFrame, position: f64) {
+        if let Some(kept) = self.frames.get_mut(movie_index) {
+            if kept.len() >= KEPT_FRAMES {
+                let (_, mut oldest) = kept.pop_front().unwrap();
+                ffi::av_frame_free(&mut oldest);
+            }
+            kept.push_back((position, ffi::av_frame_clone(frame)));
+            self.stale = true;
+        }
+    }
+
+    /// recompute once both movies showed a frame of the same moment, their
+    /// positions at most `tolerance` apart. frames older than that pair go.
+    pub unsafe fn update(&mut self, tolerance: f64) {
+        if !self.stale {
+            return;
+        }
+        self.stale = false;
+        let [reference, distorted] = &self.frames;
+        let pair = reference.iter().enumerate().rev().find_map(|(i, (position, _))| {
+            let j = distorted.iter().rposition(|(other, _)| (position - other).abs() <= tolerance)?;
+            Some((i, j))
+        });
+        let (i, j) = match pair {
+            Some(pair) => pair,
+            None => return,
+        };
+        self.difference = difference_frames(self.frames[0][i].1, self.frames[1][j].1, &self.options)
+            .map_err(|e| error!("🚩 cannot compute the difference: {}", e))
+            .ok();
+        for (kept, newest) in self.frames.iter_mut().zip([i, j]) {
+            for (_, mut frame) in kept.drain(..newest) {
+                ffi::av_frame_free(&mut frame);
+            }
+        }
+    }
+
+    fn rerender(&mut self) {
+        if let Some(difference) = self.difference.as_mut() {
+            difference.render(&self.options);
+        }
+    }
+
+    pub fn difference(&self) -> Option<&Difference> {
+        self.difference.as_ref()
+    }
+
+    pub fn draw(&self, canvas: &mut Canvas<Window>, texture_creator: &TextureCreator<WindowContext>, dest: Rect) {
+        let difference = match (self.mode, self.difference.as_ref()) {
+            (Some(_), Some(difference)) if difference.width > 0 && difference.height > 0 => difference,
+            _ => return,
+        };
+        let mut texture = match texture_creator.create_texture_static(
+            Some(PixelFormatEnum::RGBA32),
+            difference.width as u32,
+            difference.height as u32,
+        ) {
+            Ok(texture) => texture,
+            Err(e) => {
+                error!("🚩 cannot create difference texture: {}", e);
+                return;
+            }
+        };
+        if texture.update(None, &difference.rgba, difference.width * 4).is_err() {
+            return;
+        }
+        canvas.set_blend_mode(BlendMode::None);
+        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        let _ = canvas.fill_rect(dest);
+        let _ = canvas.copy(&texture, None, Some(dest));
+    }
+}
+
+impl Drop for DifferenceView {
+    fn drop(&mut self) {
+        for (_, frame) in self.frames.iter_mut().flat_map(|kept| kept.iter_mut()) {
+            unsafe { ffi::av_frame_free(frame) };
+        }
+    }
+}
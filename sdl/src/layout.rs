@@ -0,0 +1,162 @@
+use sdl2::rect::Rect;
+
+/// gap around and between the movies
+static SPACING: u32 = 4;
+/// picture-in-picture insets are this fraction of the area's width
+static INSET_SCALE: f64 = 0.25;
+
+/// how the movies share the window
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Layout {
+    /// rows and columns, whichever shows the movies largest
+    Grid,
+    /// side by side
+    Row,
+    /// stacked
+    Column,
+    /// movie 0 fills the window, the others sit small in the bottom right corner
+    PictureInPicture,
+}
+
+impl Layout {
+    pub fn next(self) -> Layout {
+        match self {
+            Layout::Grid => Layout::Row,
+            Layout::Row => Layout::Column,
+            Layout::Column => Layout::PictureInPicture,
+            Layout::PictureInPicture => Layout::Grid,
+        }
+    }
+}
+
+/// the largest rect of `aspect` (width / height) centred in `area`
+pub fn fit(aspect: f64, area: Rect) -> Rect {
+    let aspect = if aspect.is_finite() && aspect > 0. { aspect } else { 16. / 9. };
+    let (mut w, mut h) = (area.width() as f64, area.width() as f64 / aspect);
+    if h > area.height() as f64 {
+        h = area.height() as f64;
+        w = h * aspect;
+    }
+    let (w, h) = ((w.round() as u32).max(1), (h.round() as u32).max(1));
+    Rect::new(
+        area.x() + (area.width().saturating_sub(w) / 2) as i32,
+        area.y() + (area.height().saturating_sub(h) / 2) as i32,
+        w,
+        h,
+    )
+}
+
+/// cell `index` of a `columns` x `rows` split of `area`
+fn cell(area: Rect, columns: u32, rows: u32, index: u32) -> Rect {
+    let w = area.width().saturating_sub(SPACING * (columns + 1)) / columns;
+    let h = area.height().saturating_sub(SPACING * (rows + 1)) / rows;
+    let (column, row) = (index % columns, index / columns);
+    Rect::new(
+        area.x() + (SPACING + column * (w + SPACING)) as i32,
+        area.y() + (SPACING + row * (h + SPACING)) as i32,
+        w.max(1),
+        h.max(1),
+    )
+}
+
+fn split(aspects: &[f64], area: Rect, columns: u32) -> Vec<Rect> {
+    let rows = (aspects.len() as u32 + columns - 1) / columns;
+    aspects
+        .iter()
+        .enumerate()
+        .map(|(i, aspect)| fit(*aspect, cell(area, columns, rows, i as u32)))
+        .collect()
+}
+
+/// the grid with the most movie area on screen
+fn grid(aspects: &[f64], area: Rect) -> Vec<Rect> {
+    (1..=aspects.len() as u32)
+        .map(|columns| split(aspects, area, columns))
+        .max_by_key(|rects| rects.iter().map(|r| r.width() as u64 * r.height() as u64).sum::<u64>())
+        .unwrap_or_default()
+}
+
+fn picture_in_picture(aspects: &[f64], area: Rect) -> Vec<Rect> {
+    let mut rects = vec![fit(aspects[0], area)];
+    // the insets stack up the right edge, smaller when there are many
+    let insets = aspects.len() as u32 - 1;
+    let inset_width = (area.width() as f64 * INSET_SCALE) as u32;
+    let inset_height = area.height().saturating_sub(SPACING * (insets + 1)) / insets.max(1);
+    let mut bottom = area.bottom() - SPACING as i32;
+    for aspect in &aspects[1..] {
+        let inset = fit(*aspect, Rect::new(0, 0, inset_width.max(1), inset_height.max(1)));
+        let y = bottom - inset.height() as i32;
+        rects.push(Rect::new(area.right() - (SPACING + inset.width()) as i32, y, inset.width(), inset.height()));
+        bottom = y - SPACING as i32;
+    }
+    rects
+}
+
+/// where each movie goes in `area`, one rect per entry of `aspects`
+/// (each movie's width / height), in the same order
+pub fn arrange(layout: Layout, aspects: &[f64], area: Rect) -> Vec<Rect> {
+    if aspects.is_empty() {
+        return vec![];
+    }
+    match layout {
+        Layout::Grid => grid(aspects, area),
+        Layout::Row => split(aspects, area, aspects.len() as u32),
+        Layout::Column => split(aspects, area, 1),
+        Layout::PictureInPicture => picture_in_picture(aspects, area),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static HD: f64 = 16. / 9.;
+
+    fn overlaps(rects: &[Rect]) -> bool {
+        rects.iter().enumerate().any(|(i, a)| rects[i + 1..].iter().any(|b| a.has_intersection(*b)))
+    }
+
+    #[test]
+    fn test_fit_keeps_the_aspect_ratio() {
+        assert_eq!(fit(HD, Rect::new(0, 0, 1280, 1280)), Rect::new(0, 280, 1280, 720));
+        assert_eq!(fit(HD, Rect::new(10, 0, 1920, 720)), Rect::new(330, 0, 1280, 720));
+    }
+
+    #[test]
+    fn test_every_layout_fits_any_number_of_movies() {
+        let area = Rect::new(0, 0, 1280, 720);
+        for layout in [Layout::Grid, Layout::Row, Layout::Column, Layout::PictureInPicture] {
+            for count in 1..=9 {
+                let rects = arrange(layout, &vec![HD; count], area);
+                assert_eq!(rects.len(), count);
+                assert!(rects.iter().all(|r| area.contains_rect(*r)), "{:?} {}", layout, count);
+                if layout != Layout::PictureInPicture {
+                    assert!(!overlaps(&rects), "{:?} {}", layout, count);
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_grid_picks_the_largest_split() {
+        let area = Rect::new(0, 0, 1280, 720);
+        // two HD movies show larger side by side, four in a 2x2 grid
+        let two = arrange(Layout::Grid, &[HD, HD], area);
+        assert_eq!(two[0].y(), two[1].y());
+        let four = arrange(Layout::Grid, &[HD; 4], area);
+        assert_eq!((four[0].y(), four[2].x()), (four[1].y(), four[0].x()));
+        // in a tall window they stack
+        let tall = arrange(Layout::Grid, &[HD, HD], Rect::new(0, 0, 720, 1280));
+        assert_eq!(tall[0].x(), tall[1].x());
+    }
+
+    #[test]
+    fn test_picture_in_picture_insets_sit_in_the_corner() {
+        let area = Rect::new(0, 0, 1280, 720);
+        let rects = arrange(Layout::PictureInPicture, &[HD, HD, HD], area);
+        assert_eq!(rects[0], area);
+        assert_eq!(rects[1].width(), 320);
+        assert_eq!(rects[1].bottom(), 720 - SPACING as i32);
+        assert!(rects[2].bottom() < rects[1].top());
+    }
+}
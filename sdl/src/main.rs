@@ -8,27 +8,43 @@ use std::{
     time::Duration,
 };
 
-use log::{debug, info};
+mod difference;
+mod layout;
+mod scopes;
+mod subtitles;
+mod timeline;
+
+use log::{debug, error, info};
 use ::rsplayer::app::start_analyzer;
 use rusty_ffmpeg::ffi::{self, av_frame_unref};
 
 use sdl2::{
-    event::Event,
+    event::{Event, WindowEvent},
     keyboard::Keycode,
     pixels::{Color, PixelFormatEnum},
-    render::{Canvas, Texture, TextureAccess},
-    sys::{SDL_LockTexture, SDL_UnlockTexture, SDL_UpdateYUVTexture},
-    video::Window,
+    rect::Rect,
+    render::{Canvas, Texture, TextureAccess, TextureCreator},
+    sys::{SDL_LockTexture, SDL_UnlockTexture, SDL_UpdateTexture, SDL_UpdateYUVTexture},
+    video::{Window, WindowContext},
     Error, Sdl,
 };
 
+use layout::Layout;
 use rsplayer::{
-    analyzer_state::AnalyzerContext,
-    app::{open_movie, play_movie},
-    movie_state::{self, FormatContextWrapper, FrameWrapper, MovieState},
+    analyzer_state::{self, AnalyzerContext},
+    app::{open_movie, open_movie_with_options, play_movie},
+    command::{AnalyzerEvent, Command, ControlChannel},
+    movie_state::{self, EndBehavior, FormatContextWrapper, FrameWrapper, MovieState, SeekMode},
+    open_options::{OpenOptions, StreamSelection},
     record_state::{FrameWrapper as RecordFrameWrapper, RecordState},
+    scopes::Scopes,
 };
 
+/// width of the waveform and parade scopes, in columns
+static SCOPE_COLUMNS: usize = 320;
+/// what `F` cycles the last movie through, movie 0 stays as the reference
+static FILTER_PRESETS: [Option<&str>; 5] = [None, Some("hqdn3d"), Some("unsharp"), Some("eq=contrast=1.2"), Some("edgedetect")];
+
 // static CANVAS: Option<Canvas<Window>> = None;
 pub struct SdlSubsystemCtx {
     sdl_ctx: Sdl,
@@ -99,11 +115,34 @@ fn main() {
 
         let filepath: std::ffi::CString =
             std::ffi::CString::new(args.get(1).unwrap_or(&default_file).as_str()).unwrap();
-        open_movie(&mut analyzer_ctx, filepath.as_ptr());
-        open_movie(&mut analyzer_ctx, filepath.as_ptr());
+        // an optional .srt file as the second argument shows under both movies
+        let mut options = OpenOptions::new().subtitle_stream(StreamSelection::Best);
+        if let Some(srt) = args.get(2) {
+            options = options.external_subtitles(srt);
+        }
+        for _ in 0..2 {
+            if let Err(e) = open_movie_with_options(&mut analyzer_ctx, filepath.as_ptr(), &options) {
+                error!("🚩 {}", e);
+                return;
+            }
+        }
+        // both copies show the same frame on every tick
+        analyzer_ctx.set_synchronized(true);
         // let tx = play_movie(&mut analyzer_ctx);
-        let tx = start_analyzer(&mut analyzer_ctx);
-        event_loop(&mut analyzer_ctx, &mut subsystem, tx);
+        let control = start_analyzer(&mut analyzer_ctx);
+        // SDL_AUDIODRIVER=dummy works when there is no sound card
+        let audio_device = match subsystem.sdl_ctx.audio().and_then(|audio| analyzer_ctx.open_audio(&audio)) {
+            Ok(device) => {
+                device.resume();
+                Some(device)
+            }
+            Err(e) => {
+                error!("🔊 unable to open audio device: {}", e);
+                None
+            }
+        };
+        event_loop(&mut analyzer_ctx, &mut subsystem, control, &filepath, &options);
+        drop(audio_device);
         AnalyzerContext::close(analyzer_ctx);
         // analyzer_ctx.close();
         // drop(analyzer_ctx);
@@ -112,15 +151,17 @@ fn main() {
 pub unsafe fn event_loop(
     analyzer_ctx: &mut AnalyzerContext,
     subsystem: &mut SdlSubsystemCtx,
-    tx: std::sync::mpsc::Sender<String>,
+    control: ControlChannel,
+    filepath: &std::ffi::CStr,
+    options: &OpenOptions,
 ) {
     subsystem.canvas.set_draw_color(Color::RGB(0, 255, 255));
     subsystem.canvas.clear();
     subsystem.canvas.present();
     let texture_creator = subsystem.canvas.texture_creator();
 
-    let textw: u32 = subsystem.canvas.viewport().width() as u32;
-    let texth: u32 = subsystem.canvas.viewport().height() as u32;
+    let mut textw: u32 = subsystem.canvas.viewport().width() as u32;
+    let mut texth: u32 = subsystem.canvas.viewport().height() as u32;
     let mut texture: Texture = texture_creator
         .create_texture(
             Some(PixelFormatEnum::IYUV),
@@ -130,24 +171,11 @@ pub unsafe fn event_loop(
         )
         .unwrap();
 
-    let mut movie_texture: Texture = texture_creator
-        .create_texture(
-            Some(PixelFormatEnum::IYUV),
-            TextureAccess::Target,
-            textw,
-            texth,
-        )
-        .unwrap();
-
-    let mut movie_texture2: Texture = texture_creator
-        .create_texture(
-            Some(PixelFormatEnum::IYUV),
-            TextureAccess::Target,
-            textw,
-            texth,
-        )
-        .unwrap();
-
+    // one texture per movie at the size of its frames, made when the first frame arrives
+    let mut movie_textures: Vec<Option<Texture>> = vec![];
+    // display aspect ratio of each movie, the layout keeps it
+    let mut movie_aspects: Vec<f64> = vec![];
+    let mut movie_layout = Layout::Grid;
 
     let mut draw_texture: Texture = texture_creator
         .create_texture(
@@ -167,6 +195,17 @@ pub unsafe fn event_loop(
         )
         .unwrap();
 
+    let ttf = sdl2::ttf::init()
+        .map_err(|e| error!("🚩 unable to init SDL_ttf, text subtitles are off: {}", e))
+        .ok();
+    let subtitle_renderer = subtitles::SubtitleRenderer::new(ttf.as_ref());
+    let mut scope_renderer = scopes::ScopeRenderer::default();
+    let scope_worker = scopes::ScopeWorker::new(SCOPE_COLUMNS);
+    // the scopes of the last frame each movie showed
+    let mut movie_scopes: Vec<Option<Scopes>> = vec![];
+    let mut difference_view = difference::DifferenceView::default();
+    let mut timeline_graph = timeline::TimelineGraph::default();
+
     let _ = sdl2::video::drivers().map(|d: &'static str| {
         eprintln!("driver {}", d);
     });
@@ -221,7 +260,7 @@ pub unsafe fn event_loop(
             match event {
                 Event::Quit { .. } => {
                     // keep_running.store(false, std::sync::atomic::Ordering::Relaxed);
-                    tx.send("quit".to_string()).unwrap();
+                    control.send(Command::Quit).unwrap();
                     break 'running;
                 },
                 Event::KeyDown{ .. } => {
@@ -230,11 +269,9 @@ pub unsafe fn event_loop(
                             Some(Keycode::R) => {
                                 match subsystem.is_recording {
                                     true => {
-                                        tx.send("Stop recording".to_string()).unwrap();
                                         record_tx = None;
                                     }
                                     false => {
-                                        tx.send("Start recording".to_string()).unwrap();
                                         // record_tx = the_record_state.start_recording_thread();
                                         let result = the_record_state.start_recording_thread();
                                         record_tx = Some(result.0);
@@ -244,13 +281,192 @@ pub unsafe fn event_loop(
                                 subsystem.is_recording = !subsystem.is_recording;
                             }
                             Some(Keycode::Space) => {
-                                tx.send("pause".to_string()).unwrap();
-                                analyzer_ctx.pause();
+                                control.send(Command::TogglePause).unwrap();
+                            }
+                            Some(Keycode::M) => {
+                                // cycle which movie is audible
+                                let next = analyzer_ctx.audible_movie().map_or(0, |i| i + 1);
+                                let next = next % (analyzer_ctx.movie_count().max(1) as usize);
+                                info!("🔊 audio from movie {}", next);
+                                control.send(Command::SoloAudio(next)).unwrap();
+                            }
+                            Some(Keycode::Left) | Some(Keycode::Right) => {
+                                let position = analyzer_ctx.movie(0).map_or(0., |m| m.position());
+                                let offset = if keycode == Some(Keycode::Left) { -5. } else { 5. };
+                                info!("seek to {:.2}s", (position + offset).max(0.));
+                                control.send(Command::Seek {
+                                    position: (position + offset).max(0.),
+                                    mode: SeekMode::Keyframe,
+                                }).unwrap();
+                            }
+                            Some(Keycode::Home) => {
+                                control.send(Command::Seek { position: 0., mode: SeekMode::Accurate }).unwrap();
+                            }
+                            Some(Keycode::E) => {
+                                // cycle what every movie does at the end of the file
+                                let behavior = match analyzer_ctx.movie(0).map(|m| m.end_behavior()) {
+                                    Some(EndBehavior::Loop) => EndBehavior::StopOnLastFrame,
+                                    Some(EndBehavior::StopOnLastFrame) => EndBehavior::PingPong,
+                                    Some(EndBehavior::PingPong) => EndBehavior::Close,
+                                    _ => EndBehavior::Loop,
+                                };
+                                info!("end behavior {:?}", behavior);
+                                for movie_index in 0..analyzer_ctx.movie_count() as usize {
+                                    control.send(Command::SetEndBehavior { movie_index, behavior }).unwrap();
+                                }
+                            }
+                            Some(Keycode::V) => {
+                                // cycle the first movie through its decodable video streams
+                                if let Some(movie) = analyzer_ctx.movie(0) {
+                                    let streams: Vec<_> = movie.video_streams().into_iter().filter(|s| s.decodable).collect();
+                                    if let Some(current) = streams.iter().position(|s| s.active) {
+                                        let next = &streams[(current + 1) % streams.len()];
+                                        info!("video stream {} {} {}x{}", next.index, next.codec_name, next.width, next.height);
+                                        control.send(Command::SelectVideoStream { movie_index: 0, stream_index: next.index }).unwrap();
+                                    }
+                                }
+                            }
+                            Some(Keycode::S) => {
+                                // cycle the first movie through its subtitle streams and off
+                                if let Some(movie) = analyzer_ctx.movie(0) {
+                                    let streams = movie.subtitle_streams();
+                                    let next = match streams.iter().position(|s| s.active) {
+                                        Some(current) => streams.get(current + 1),
+                                        None => streams.first(),
+                                    };
+                                    match next {
+                                        Some(s) => info!("subtitle stream {} {} {}", s.index, s.codec_name, s.language.as_deref().unwrap_or("")),
+                                        None => info!("subtitles off"),
+                                    }
+                                    control.send(Command::SelectSubtitleStream {
+                                        movie_index: 0,
+                                        stream_index: next.map(|s| s.index),
+                                    }).unwrap();
+                                }
+                            }
+                            Some(Keycode::L) => {
+                                let synchronized = !analyzer_ctx.is_synchronized();
+                                info!("synchronized playback {}", synchronized);
+                                control.send(Command::SetSynchronized(synchronized)).unwrap();
+                            }
+                            Some(Keycode::LeftBracket) | Some(Keycode::RightBracket) => {
+                                // nudge the second movie by one frame against the first
+                                if let Some(movie) = analyzer_ctx.movie(1) {
+                                    let rate = *movie.video_frame_rate.lock().unwrap();
+                                    let frame = rate.den as f64 / rate.num.max(1) as f64;
+                                    let step = if keycode == Some(Keycode::LeftBracket) { -frame } else { frame };
+                                    let offset = movie.time_offset() + step;
+                                    info!("movie 1 offset {:.3}s", offset);
+                                    control.send(Command::SetTimeOffset { movie_index: 1, offset }).unwrap();
+                                }
+                            }
+                            Some(Keycode::Minus) | Some(Keycode::Equals) | Some(Keycode::Num0) => {
+                                // halve, double or reset the playback speed
+                                let speed = match keycode {
+                                    Some(Keycode::Minus) => analyzer_ctx.speed() / 2.,
+                                    Some(Keycode::Equals) => analyzer_ctx.speed() * 2.,
+                                    _ => 1.,
+                                };
+                                let speed = speed.clamp(analyzer_state::MIN_SPEED, analyzer_state::MAX_SPEED);
+                                info!("speed {}x", speed);
+                                control.send(Command::SetSpeed(speed)).unwrap();
+                            }
+                            Some(Keycode::P) => {
+                                // measure against movie 0, the history is written out when measuring stops
+                                let enabled = !analyzer_ctx.metrics_enabled();
+                                control.send(Command::SetMetrics(enabled)).unwrap();
+                                if enabled {
+                                    info!("measuring PSNR and SSIM against movie 0");
+                                } else {
+                                    let history = analyzer_ctx.metrics_history();
+                                    info!("measured {} frames, writing metrics.csv and metrics.json", history.frames.len());
+                                    if let Err(e) = history.write_csv(std::path::Path::new("metrics.csv"))
+                                        .and_then(|_| history.write_json(std::path::Path::new("metrics.json"))) {
+                                        error!("🚩 cannot write metrics: {}", e);
+                                    }
+                                }
+                            }
+                            Some(Keycode::W) => {
+                                // cycle the scope panes under the movies
+                                scope_renderer.view = scope_renderer.view.next();
+                                info!("scopes {:?}", scope_renderer.view);
+                                movie_scopes.clear();
+                            }
+                            Some(Keycode::T) => {
+                                // packet sizes around the current position over the top of each movie
+                                timeline_graph.enabled = !timeline_graph.enabled;
+                                if let Some(movie) = analyzer_ctx.movie(0) {
+                                    let stream_index = movie.video_stream_index().max(0) as usize;
+                                    let bitrate = movie.packet_timeline.lock().unwrap().bitrate_at(stream_index, movie.position(), 1.);
+                                    info!("packet timeline {}, movie 0 at {:.0} kbit/s", timeline_graph.enabled, bitrate / 1000.);
+                                }
+                            }
+                            Some(Keycode::D) => {
+                                // side by side, amplified difference, heatmap
+                                difference_view.next_mode();
+                                info!("difference view {:?}", difference_view.mode);
+                            }
+                            Some(Keycode::PageUp) | Some(Keycode::PageDown) => {
+                                match keycode {
+                                    Some(Keycode::PageUp) => difference_view.raise_threshold(),
+                                    _ => difference_view.lower_threshold(),
+                                }
+                                let significant = difference_view.difference().map_or(0, |d| d.significant);
+                                info!("difference threshold {}, {} pixels over it", difference_view.options.threshold, significant);
                             }
                             Some(Keycode::Period) => {
                                 info!("analyzer step");
-                                tx.send("step".to_string()).unwrap();
-                                analyzer_ctx.step();
+                                control.send(Command::Step).unwrap();
+                            }
+                            Some(Keycode::Comma) => {
+                                info!("analyzer step back");
+                                control.send(Command::StepBack).unwrap();
+                            }
+                            Some(Keycode::B) => {
+                                let reverse = !analyzer_ctx.is_reversed();
+                                info!("play {}", if reverse { "backwards" } else { "forwards" });
+                                control.send(Command::SetReverse(reverse)).unwrap();
+                            }
+                            Some(Keycode::F) => {
+                                let movie_index = analyzer_ctx.movie_count().max(1) as usize - 1;
+                                if let Some(movie) = analyzer_ctx.movie(movie_index) {
+                                    let current = movie.filter_description();
+                                    let next = FILTER_PRESETS.iter().position(|p| p.map(String::from) == current).map_or(0, |i| i + 1);
+                                    let description = FILTER_PRESETS[next % FILTER_PRESETS.len()];
+                                    info!("movie {} filter {}", movie_index, description.unwrap_or("off"));
+                                    control.send(Command::SetFilter { movie_index, description: description.map(String::from) }).unwrap();
+                                }
+                            }
+                            Some(Keycode::O) => {
+                                // a quarter turn clockwise, back to the stream's own orientation after a full turn
+                                let movie_index = analyzer_ctx.movie_count().max(1) as usize - 1;
+                                if let Some(movie) = analyzer_ctx.movie(movie_index) {
+                                    let next = movie.orientation().rotated(90);
+                                    let orientation = if next == movie.stream_orientation() { None } else { Some(next) };
+                                    info!("movie {} turned {}°", movie_index, next.rotation);
+                                    control.send(Command::SetOrientation { movie_index, orientation }).unwrap();
+                                }
+                            }
+                            Some(Keycode::G) => {
+                                // grid, row, column, picture-in-picture
+                                movie_layout = movie_layout.next();
+                                info!("layout {:?}", movie_layout);
+                            }
+                            Some(Keycode::Insert) => {
+                                // another copy of the movie joins at the current position
+                                match open_movie_with_options(analyzer_ctx, filepath.as_ptr(), options) {
+                                    Ok(_) => info!("now showing {} movies", analyzer_ctx.movie_count()),
+                                    Err(e) => error!("🚩 {}", e),
+                                }
+                            }
+                            Some(Keycode::Delete) => {
+                                // drop the last movie, one always stays
+                                let count = analyzer_ctx.movie_count() as usize;
+                                if count > 1 {
+                                    if let Err(e) = analyzer_ctx.remove_movie(count - 1) {
+                                        error!("🚩 {}", e);
+                                    }
+                                }
                             }
                             Some(Keycode::Q) | Some(Keycode::Escape) => {
                                 record_tx = None;
@@ -258,16 +474,48 @@ pub unsafe fn event_loop(
                                 if let Some(record_handle) = record_handle {
                                     record_handle.join().unwrap();
                                 }
-                                tx.send("quit".to_string()).unwrap();
+                                control.send(Command::Quit).unwrap();
                                 break 'running;
                             }
                             _ => {}
                         }
                     }
                 }
+                Event::Window { win_event: WindowEvent::SizeChanged(..), .. } => {
+                    // the composite follows the window so the layout is worked out in window pixels
+                    let viewport = subsystem.canvas.viewport();
+                    match (
+                        texture_creator.create_texture_target(Some(PixelFormatEnum::IYUV), viewport.width(), viewport.height()),
+                        texture_creator.create_texture_target(Some(PixelFormatEnum::IYUV), viewport.width(), viewport.height()),
+                    ) {
+                        (Ok(composite_texture), Ok(ui)) => {
+                            (texture, ui_texture) = (composite_texture, ui);
+                            (textw, texth) = (viewport.width(), viewport.height());
+                        }
+                        (Err(e), _) | (_, Err(e)) => error!("🚩 cannot resize the composite: {}", e),
+                    }
+                }
                 _ => {}
             }
         }
+        for event in control.poll_events() {
+            match event {
+                AnalyzerEvent::EndOfStream { movie_index } => info!("movie {} reached the end", movie_index),
+                AnalyzerEvent::Failed { command, reason } => error!("🚩 {:?} failed: {}", command, reason),
+                AnalyzerEvent::MovieFailed { movie_index, command, reason } => {
+                    error!("🚩 {:?} failed for movie {}: {}", command, movie_index, reason)
+                }
+                // the movie's texture follows the new frame size on its own
+                AnalyzerEvent::FormatChanged { movie_index, from, to } => info!("movie {} changed from {} to {}", movie_index, from, to),
+                AnalyzerEvent::MovieRemoved { movie_index } => {
+                    // the movies after it moved up, so do their textures
+                    remove_slot(&mut movie_textures, movie_index);
+                    remove_slot(&mut movie_aspects, movie_index);
+                    remove_slot(&mut movie_scopes, movie_index);
+                }
+                _ => debug!("analyzer event: {:?}", event),
+            }
+        }
         // The rest of the game loop goes here...
         draw_ui(
             &mut subsystem.canvas,
@@ -281,23 +529,49 @@ pub unsafe fn event_loop(
         //     continue;
         // }
 
-        if analyzer_ctx.is_paused() == false || analyzer_ctx.force_render == false {
+        let movie_count = analyzer_ctx.movie_count() as usize;
+        movie_textures.resize_with(movie_count, || None);
+        movie_aspects.resize(movie_count, 16. / 9.);
+        for (index, scopes) in scope_worker.finished() {
+            if scope_renderer.is_enabled() && index < movie_count {
+                movie_scopes.resize(movie_count, None);
+                movie_scopes[index] = Some(scopes);
+            }
+        }
+        if analyzer_ctx.is_paused() == false || analyzer_ctx.force_render() == false {
         let mut frame_remaining = 1./60.;
         let mut nearest_frame = -1.;
         for index in 0..analyzer_ctx.movie_count() {
-            if let (remaining, Some(mut dest_frame)) = analyzer_ctx.dequeue_frame(index as _) {
+            if let (remaining, Some((mut dest_frame, _))) = analyzer_ctx.dequeue_frame(index as _) {
                 if nearest_frame < remaining {
                     nearest_frame = remaining;
                 }
-                if index == 0 {
-                    frame_to_texture(dest_frame.as_mut().unwrap(), &mut movie_texture).unwrap_or_default();
-                } else {
-                    frame_to_texture(dest_frame.as_mut().unwrap(), &mut movie_texture2).unwrap_or_default();
+                if scope_renderer.is_enabled() {
+                    scope_worker.submit(index as usize, dest_frame);
+                }
+                if difference_view.is_enabled() {
+                    if let Some(position) = analyzer_ctx.frame_position(index as usize, &*dest_frame) {
+                        difference_view.keep(index as usize, dest_frame, position);
+                    }
+                }
+                let frame = dest_frame.as_mut().unwrap();
+                movie_aspects[index as usize] = display_aspect(frame);
+                let slot = &mut movie_textures[index as usize];
+                let format = texture_format(frame.format).unwrap_or(PixelFormatEnum::IYUV);
+                if slot.as_ref().map(|t| (t.query().width, t.query().height, t.query().format)) != Some((frame.width as u32, frame.height as u32, format)) {
+                    *slot = texture_creator
+                        .create_texture_streaming(Some(format), frame.width as u32, frame.height as u32)
+                        .map_err(|e| error!("🚩 cannot create a texture for movie {}: {}", index, e))
+                        .ok();
+                }
+                if let Some(movie_texture) = slot.as_mut() {
+                    frame_to_texture(frame, movie_texture).unwrap_or_else(|e| error!("🚩 movie {}: {}", index, e));
                 }
                 ffi::av_frame_unref(dest_frame as *mut _);
                 ffi::av_frame_free(&mut dest_frame as *mut *mut _);
             };
         };
+        difference_view.update(analyzer_ctx.pairing_tolerance());
 
         if nearest_frame < 0. {
             ::std::thread::sleep(std::time::Duration::from_secs_f64(frame_remaining));
@@ -325,34 +599,84 @@ pub unsafe fn event_loop(
         //     };
         // });
 
-        composite(&mut subsystem.canvas, &mut texture, &mut movie_texture, Some(sdl2::sys::SDL_Rect{
-            x: 0,
-            y: 720/4,
-            w: 1280/2,
-            h: 720/2,
-        }));
-        composite(&mut subsystem.canvas, &mut texture, &mut movie_texture2, Some(sdl2::sys::SDL_Rect{
-            x: 1280/2,
-            y: 720/4,
-            w: 1280/2,
-            h: 720/2,
-        }));
+        let movie_rects = layout::arrange(movie_layout, &movie_aspects, Rect::new(0, 0, textw, texth));
+        clear_texture(&mut subsystem.canvas, &mut texture);
+        for (movie_texture, rect) in movie_textures.iter_mut().zip(movie_rects.iter()) {
+            if let Some(movie_texture) = movie_texture {
+                composite(&mut subsystem.canvas, &mut texture, movie_texture, Some(*rect.raw()));
+            }
+        }
         composite(&mut subsystem.canvas, &mut texture, &mut ui_texture, None);
         blit_texture(&mut subsystem.canvas, &mut texture).unwrap_or_default();
+
+        // subtitles go straight on the canvas, the composite texture is YUV
+        let viewport = subsystem.canvas.viewport();
+        if difference_view.is_enabled() {
+            // one pane over the whole window instead of the movies
+            let aspect = movie_aspects.first().copied().unwrap_or(16. / 9.);
+            let pane = layout::fit(aspect, Rect::new(0, 0, textw, texth));
+            difference_view.draw(&mut subsystem.canvas, &texture_creator, viewport_rect(pane, viewport, (textw, texth)));
+        }
+        for (movie, rect) in analyzer_ctx.movies().iter().zip(movie_rects.iter()) {
+            let active = movie.active_subtitles();
+            if active.is_empty() || difference_view.is_enabled() {
+                continue;
+            }
+            let dest = viewport_rect(*rect, viewport, (textw, texth));
+            subtitle_renderer.draw(&mut subsystem.canvas, &texture_creator, &active, dest);
+        }
+        // a scope pane over the bottom third of each movie
+        for (scopes, rect) in movie_scopes.iter().zip(movie_rects.iter()) {
+            if let Some(scopes) = scopes {
+                let pane = Rect::new(rect.x(), rect.bottom() - rect.height() as i32 / 3, rect.width(), rect.height() / 3);
+                let dest = viewport_rect(pane, viewport, (textw, texth));
+                scope_renderer.draw(&mut subsystem.canvas, &texture_creator, scopes, dest);
+            }
+        }
+        for (movie, rect) in analyzer_ctx.movies().iter().zip(movie_rects.iter()) {
+            let pane = Rect::new(rect.x(), rect.y(), rect.width(), rect.height() / 4);
+            timeline_graph.draw(&mut subsystem.canvas, movie, viewport_rect(pane, viewport, (textw, texth)));
+        }
         // blit_texture(&mut subsystem.canvas, &mut ui_texture).unwrap_or_default();
 
         // last_clock = ffi::av_gettime_relative();
         subsystem.canvas.present();
-        analyzer_ctx.force_render = false;
+        analyzer_ctx.clear_force_render();
 
         screen_cap(subsystem, &mut record_tx, i);
         // ::std::thread::yield_now();
     }
-    drop(tx);
+    drop(control);
     drop(record_tx);
     // ffi::av_free(dest_frame.opaque);
 }
 
+/// where a rect of the composite texture ends up, the texture is stretched over the window
+fn viewport_rect(rect: Rect, viewport: Rect, (textw, texth): (u32, u32)) -> Rect {
+    Rect::new(
+        rect.x() * viewport.width() as i32 / textw as i32,
+        rect.y() * viewport.height() as i32 / texth as i32,
+        rect.width() * viewport.width() / textw,
+        rect.height() * viewport.height() / texth,
+    )
+}
+
+/// width over height as the frame is meant to be shown, its sample aspect ratio applied
+fn display_aspect(frame: &ffi::AVFrame) -> f64 {
+    let sar = match frame.sample_aspect_ratio {
+        ffi::AVRational { num, den } if num > 0 && den > 0 => num as f64 / den as f64,
+        _ => 1.,
+    };
+    frame.width as f64 * sar / frame.height.max(1) as f64
+}
+
+/// forget what was kept for a movie that was removed
+fn remove_slot<T>(slots: &mut Vec<T>, movie_index: usize) {
+    if movie_index < slots.len() {
+        slots.remove(movie_index);
+    }
+}
+
 fn record_frame(
     frame: &mut ffi::AVFrame,
     tx: &Option<std::sync::mpsc::SyncSender<RecordFrameWrapper>>,
@@ -364,21 +688,106 @@ fn record_frame(
     Ok(())
 }
 
+/// the texture format that shows frames of an `AVPixelFormat` as they are,
+/// one for each of the filter graph's `SINK_PIX_FMTS`
+fn texture_format(format: i32) -> Option<PixelFormatEnum> {
+    match format {
+        ffi::AVPixelFormat_AV_PIX_FMT_YUV420P => Some(PixelFormatEnum::IYUV),
+        // sdl2 0.35 has no NV12 or NV21 textures, `frame_to_texture` splits their chroma
+        ffi::AVPixelFormat_AV_PIX_FMT_NV12 | ffi::AVPixelFormat_AV_PIX_FMT_NV21 => Some(PixelFormatEnum::IYUV),
+        ffi::AVPixelFormat_AV_PIX_FMT_RGB24 => Some(PixelFormatEnum::RGB24),
+        ffi::AVPixelFormat_AV_PIX_FMT_BGR24 => Some(PixelFormatEnum::BGR24),
+        // ffmpeg names packed formats by byte order, SDL by the 32 bit word
+        ffi::AVPixelFormat_AV_PIX_FMT_RGBA => Some(PixelFormatEnum::RGBA32),
+        ffi::AVPixelFormat_AV_PIX_FMT_BGRA => Some(PixelFormatEnum::BGRA32),
+        _ => None,
+    }
+}
+
+/// upload a frame to a texture made with its `texture_format`
 fn frame_to_texture(movie_frame: &mut ffi::AVFrame, texture: &mut Texture) -> Result<(), String> {
-    unsafe {
-        set_sdl_yuv_conversion_mode(movie_frame);
-        SDL_UpdateYUVTexture(
-            texture.raw(),
-            ::std::ptr::null(),
-            movie_frame.data[0],
-            movie_frame.linesize[0],
-            movie_frame.data[1],
-            movie_frame.linesize[1],
-            movie_frame.data[2],
-            movie_frame.linesize[2],
-        );
+    // vflip hands out frames bottom up with negative linesizes, SDL wants them top down
+    let mut copy = std::ptr::null_mut();
+    let frame = match movie_frame.linesize.iter().any(|l| *l < 0) {
+        false => &*movie_frame,
+        true => unsafe {
+            copy = ffi::av_frame_alloc();
+            ((*copy).width, (*copy).height, (*copy).format) = (movie_frame.width, movie_frame.height, movie_frame.format);
+            if ffi::av_frame_get_buffer(copy, 0) < 0
+                || ffi::av_frame_copy(copy, movie_frame) < 0
+                || ffi::av_frame_copy_props(copy, movie_frame) < 0
+            {
+                ffi::av_frame_free(&mut copy);
+                return Err(String::from("cannot copy a bottom up frame"));
+            }
+            &*copy
+        },
     };
-    Ok(())
+    let format = texture_format(frame.format);
+    let result = unsafe {
+        set_sdl_yuv_conversion_mode(frame);
+        match format {
+            Some(PixelFormatEnum::IYUV) if frame.format != ffi::AVPixelFormat_AV_PIX_FMT_YUV420P => {
+                let (u, v, pitch) = split_chroma(frame);
+                SDL_UpdateYUVTexture(
+                    texture.raw(),
+                    ::std::ptr::null(),
+                    frame.data[0],
+                    frame.linesize[0],
+                    u.as_ptr(),
+                    pitch,
+                    v.as_ptr(),
+                    pitch,
+                )
+            }
+            Some(PixelFormatEnum::IYUV) => SDL_UpdateYUVTexture(
+                texture.raw(),
+                ::std::ptr::null(),
+                frame.data[0],
+                frame.linesize[0],
+                frame.data[1],
+                frame.linesize[1],
+                frame.data[2],
+                frame.linesize[2],
+            ),
+            Some(_) => SDL_UpdateTexture(texture.raw(), ::std::ptr::null(), frame.data[0] as *const _, frame.linesize[0]),
+            None => -1,
+        }
+    };
+    if !copy.is_null() {
+        unsafe { ffi::av_frame_free(&mut copy) };
+    }
+    match (format, result) {
+        (None, _) => Err(format!("no texture format for pixel format {}", frame_format_name(movie_frame.format))),
+        (_, 0) => Ok(()),
+        _ => Err(sdl2::get_error()),
+    }
+}
+
+/// the U and V planes of an NV12 or NV21 frame and their pitch
+unsafe fn split_chroma(frame: &ffi::AVFrame) -> (Vec<u8>, Vec<u8>, i32) {
+    let (width, height) = (((frame.width + 1) / 2) as usize, ((frame.height + 1) / 2) as usize);
+    let (mut u, mut v) = (Vec::with_capacity(width * height), Vec::with_capacity(width * height));
+    for y in 0..height {
+        let row = std::slice::from_raw_parts(frame.data[1].offset(y as isize * frame.linesize[1] as isize), width * 2);
+        for pair in row.chunks_exact(2) {
+            let (first, second) = match frame.format == ffi::AVPixelFormat_AV_PIX_FMT_NV21 {
+                true => (&mut v, &mut u),
+                false => (&mut u, &mut v),
+            };
+            first.push(pair[0]);
+            second.push(pair[1]);
+        }
+    }
+    (u, v, width as i32)
+}
+
+fn frame_format_name(format: i32) -> String {
+    let name = unsafe { ffi::av_get_pix_fmt_name(format) };
+    match name.is_null() {
+        true => format.to_string(),
+        false => unsafe { std::ffi::CStr::from_ptr(name) }.to_string_lossy().into_owned(),
+    }
 }
 
 fn texture_to_texture(
@@ -430,7 +839,9 @@ unsafe fn set_sdl_yuv_conversion_mode(frame: *const ffi::AVFrame) {
     if !frame.is_null()
         && ((*frame).format == ffi::AVPixelFormat_AV_PIX_FMT_YUV420P
             || (*frame).format == ffi::AVPixelFormat_AV_PIX_FMT_YUV422P
-            || (*frame).format == ffi::AVPixelFormat_AV_PIX_FMT_UYVY422)
+            || (*frame).format == ffi::AVPixelFormat_AV_PIX_FMT_UYVY422
+            || (*frame).format == ffi::AVPixelFormat_AV_PIX_FMT_NV12
+            || (*frame).format == ffi::AVPixelFormat_AV_PIX_FMT_NV21)
     {
         if (*frame).color_range == ffi::AVColorRange_AVCOL_RANGE_JPEG {
             mode = sdl2::sys::SDL_YUV_CONVERSION_MODE::SDL_YUV_CONVERSION_JPEG;
@@ -645,6 +1056,13 @@ unsafe fn draw_ui(renderer: &mut Canvas<Window>, tex2: &mut Texture, is_recordin
     sdl2::sys::SDL_SetRenderTarget(renderer.raw(), std::ptr::null_mut());
 }
 
+unsafe fn clear_texture(renderer: &mut Canvas<Window>, tex: &mut Texture) {
+    sdl2::sys::SDL_SetRenderTarget(renderer.raw(), tex.raw());
+    sdl2::sys::SDL_SetRenderDrawColor(renderer.raw(), 0, 0, 0, 255);
+    sdl2::sys::SDL_RenderClear(renderer.raw());
+    sdl2::sys::SDL_SetRenderTarget(renderer.raw(), std::ptr::null_mut());
+}
+
 unsafe fn composite(renderer: &mut Canvas<Window>, tex: &mut Texture, tex2: &mut Texture, dest_rec: Option<sdl2::sys::SDL_Rect>) {
     sdl2::sys::SDL_SetRenderTarget(renderer.raw(), tex.raw());
     let dest_rect = sdl2::sys::SDL_Rect {
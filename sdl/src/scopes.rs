@@ -0,0 +1,258 @@
+use std::{
+    sync::mpsc::{sync_channel, Receiver, SyncSender, TryIter, TrySendError},
+    thread::JoinHandle,
+};
+
+use log::error;
+use rusty_ffmpeg::ffi;
+use sdl2::{
+    pixels::{Color, PixelFormatEnum},
+    rect::Rect,
+    render::{BlendMode, Canvas, TextureCreator},
+    video::{Window, WindowContext},
+};
+
+use rsplayer::{
+    movie_state::FrameWrapper,
+    scopes::{compute_scopes, Scopes, Waveform, LEVELS},
+};
+
+/// rows of the histogram pane
+static HISTOGRAM_HEIGHT: usize = 128;
+
+/// which scope the panes under the movies show
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScopeView {
+    Off,
+    Waveform,
+    Parade,
+    Vectorscope,
+    Histogram,
+}
+
+impl ScopeView {
+    pub fn next(self) -> ScopeView {
+        match self {
+            ScopeView::Off => ScopeView::Waveform,
+            ScopeView::Waveform => ScopeView::Parade,
+            ScopeView::Parade => ScopeView::Vectorscope,
+            ScopeView::Vectorscope => ScopeView::Histogram,
+            ScopeView::Histogram => ScopeView::Off,
+        }
+    }
+}
+
+/// works the scopes out on a thread of its own so the UI never waits for them.
+/// a frame handed over while the last one is still being measured is skipped.
+pub struct ScopeWorker {
+    frames: Option<SyncSender<(usize, FrameWrapper)>>,
+    scopes: Receiver<(usize, Scopes)>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl ScopeWorker {
+    /// the waveforms and parade are `columns` wide
+    pub fn new(columns: usize) -> ScopeWorker {
+        let (frame_tx, frame_rx) = sync_channel::<(usize, FrameWrapper)>(1);
+        let (scopes_tx, scopes_rx) = sync_channel(8);
+        let thread = std::thread::spawn(move || {
+            for (movie_index, mut frame) in frame_rx {
+                let scopes = unsafe { compute_scopes(frame.ptr, columns) };
+                unsafe { ffi::av_frame_free(&mut frame.ptr) };
+                match scopes {
+                    Ok(scopes) => {
+                        if scopes_tx.send((movie_index, scopes)).is_err() {
+                            break;
+                        }
+                    }
+                    Err(e) => error!("🚩 cannot compute scopes: {}", e),
+                }
+            }
+        });
+        ScopeWorker { frames: Some(frame_tx), scopes: scopes_rx, thread: Some(thread) }
+    }
+
+    /// measure a copy of the frame a movie just showed, unless the worker is busy
+    pub unsafe fn submit(&self, movie_index: usize, frame: *const ffi::AVFrame) {
+        let copy = FrameWrapper { ptr: ffi::av_frame_clone(frame) };
+        if let Some(Err(TrySendError::Full((_, mut copy)) | TrySendError::Disconnected((_, mut copy)))) =
+            self.frames.as_ref().map(|frames| frames.try_send((movie_index, copy)))
+        {
+            ffi::av_frame_free(&mut copy.ptr);
+        }
+    }
+
+    /// the scopes finished since the last call, with the movie they belong to
+    pub fn finished(&self) -> TryIter<'_, (usize, Scopes)> {
+        self.scopes.try_iter()
+    }
+}
+
+impl Drop for ScopeWorker {
+    fn drop(&mut self) {
+        // closing the channel ends the thread once it is done with its frame
+        self.frames.take();
+        while self.scopes.try_recv().is_ok() {}
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+/// an RGBA32 picture of a scope, drawn stretched over its pane
+struct ScopeImage {
+    width: usize,
+    height: usize,
+    rgba: Vec<u8>,
+}
+
+impl ScopeImage {
+    fn new(width: usize, height: usize) -> ScopeImage {
+        ScopeImage { width, height, rgba: vec![0; width * height * 4] }
+    }
+
+    /// adds to what is there so overlapping traces mix
+    fn add(&mut self, x: usize, y: usize, [r, g, b]: [u8; 3]) {
+        let i = (y * self.width + x) * 4;
+        let pixel = &mut self.rgba[i..i + 4];
+        pixel[0] = pixel[0].saturating_add(r);
+        pixel[1] = pixel[1].saturating_add(g);
+        pixel[2] = pixel[2].saturating_add(b);
+        pixel[3] = 255;
+    }
+}
+
+/// counts are shown on a log scale, a single sample is still visible
+fn intensity(count: u32, max: u32) -> f32 {
+    match count {
+        0 => 0.,
+        _ => 0.25 + 0.75 * (count as f32).ln_1p() / (max.max(1) as f32).ln_1p(),
+    }
+}
+
+fn tint([r, g, b]: [u8; 3], amount: f32) -> [u8; 3] {
+    [r, g, b].map(|c| (c as f32 * amount) as u8)
+}
+
+fn draw_waveform(image: &mut ScopeImage, waveform: &Waveform, x_offset: usize, colour: [u8; 3]) {
+    let max = waveform.max();
+    for level in 0..waveform.levels {
+        for column in 0..waveform.columns {
+            let count = waveform.get(column, level);
+            if count > 0 {
+                image.add(x_offset + column, waveform.levels - 1 - level, tint(colour, intensity(count, max)));
+            }
+        }
+    }
+}
+
+fn waveform_image(scopes: &Scopes) -> ScopeImage {
+    let mut image = ScopeImage::new(scopes.luma.columns, scopes.luma.levels);
+    draw_waveform(&mut image, &scopes.luma, 0, [120, 255, 120]);
+    image
+}
+
+fn parade_image(scopes: &Scopes) -> ScopeImage {
+    let columns = scopes.luma.columns;
+    let mut image = ScopeImage::new(columns * 3, scopes.luma.levels);
+    let colours = [[255, 60, 60], [60, 255, 60], [80, 80, 255]];
+    for (i, (waveform, colour)) in scopes.parade.iter().zip(colours).enumerate() {
+        draw_waveform(&mut image, waveform, i * columns, colour);
+    }
+    image
+}
+
+fn vectorscope_image(scopes: &Scopes) -> ScopeImage {
+    let mut image = ScopeImage::new(LEVELS, LEVELS);
+    // cross hair through neutral grey
+    for i in 0..LEVELS {
+        image.add(i, LEVELS / 2, [40, 40, 40]);
+        image.add(LEVELS / 2, i, [40, 40, 40]);
+    }
+    let max = scopes.vectorscope.max();
+    for v in 0..LEVELS {
+        for u in 0..LEVELS {
+            let count = scopes.vectorscope.get(u as u8, v as u8);
+            if count > 0 {
+                // V grows upwards like on a hardware scope
+                image.add(u, LEVELS - 1 - v, tint([255, 255, 255], intensity(count, max)));
+            }
+        }
+    }
+    image
+}
+
+fn histogram_image(scopes: &Scopes) -> ScopeImage {
+    let histogram = &scopes.histogram;
+    let mut image = ScopeImage::new(histogram.y.len(), HISTOGRAM_HEIGHT);
+    let max = histogram.max().max(1) as usize;
+    let planes = [(&histogram.y, [160, 160, 160]), (&histogram.u, [40, 40, 200]), (&histogram.v, [200, 40, 40])];
+    for (counts, colour) in planes {
+        for (level, &count) in counts.iter().enumerate() {
+            let bar = count as usize * HISTOGRAM_HEIGHT / max;
+            for y in HISTOGRAM_HEIGHT - bar..HISTOGRAM_HEIGHT {
+                image.add(level, y, colour);
+            }
+        }
+    }
+    image
+}
+
+/// draws the scopes of a movie into a pane on the canvas
+pub struct ScopeRenderer {
+    pub view: ScopeView,
+}
+
+impl Default for ScopeRenderer {
+    fn default() -> Self {
+        ScopeRenderer { view: ScopeView::Off }
+    }
+}
+
+impl ScopeRenderer {
+    pub fn is_enabled(&self) -> bool {
+        self.view != ScopeView::Off
+    }
+
+    pub fn draw(
+        &self,
+        canvas: &mut Canvas<Window>,
+        texture_creator: &TextureCreator<WindowContext>,
+        scopes: &Scopes,
+        dest: Rect,
+    ) {
+        let image = match self.view {
+            ScopeView::Off => return,
+            ScopeView::Waveform => waveform_image(scopes),
+            ScopeView::Parade => parade_image(scopes),
+            ScopeView::Vectorscope => vectorscope_image(scopes),
+            ScopeView::Histogram => histogram_image(scopes),
+        };
+        let mut texture = match texture_creator.create_texture_static(
+            Some(PixelFormatEnum::RGBA32),
+            image.width as u32,
+            image.height as u32,
+        ) {
+            Ok(texture) => texture,
+            Err(e) => {
+                error!("🚩 cannot create scope texture: {}", e);
+                return;
+            }
+        };
+        if texture.update(None, &image.rgba, image.width * 4).is_err() {
+            return;
+        }
+        // the vectorscope stays square in the middle of the pane
+        let target = match self.view {
+            ScopeView::Vectorscope => {
+                let side = dest.width().min(dest.height());
+                Rect::new(dest.x() + (dest.width() - side) as i32 / 2, dest.y(), side, side)
+            }
+            _ => dest,
+        };
+        canvas.set_blend_mode(BlendMode::None);
+        canvas.set_draw_color(Color::RGB(0, 0, 0));
+        let _ = canvas.fill_rect(dest);
+        let _ = canvas.copy(&texture, None, Some(target));
+    }
+}
@@ -0,0 +1,126 @@
+use log::{error, info};
+use sdl2::{
+    pixels::{Color, PixelFormatEnum},
+    rect::Rect,
+    render::{BlendMode, Canvas, TextureCreator},
+    ttf::{Font, Sdl2TtfContext},
+    video::{Window, WindowContext},
+};
+
+use rsplayer::subtitle::{Subtitle, SubtitleBitmap, SubtitleContent};
+
+static DEFAULT_FONT: &str = "/usr/share/fonts/truetype/dejavu/DejaVuSans.ttf";
+static FONT_SIZE: u16 = 24;
+/// space between the subtitles and the bottom of the movie
+static MARGIN: i32 = 12;
+
+/// draws the subtitles of a movie over its rect on the canvas
+pub struct SubtitleRenderer<'ttf> {
+    /// text subtitles are skipped without a font, bitmaps still show
+    font: Option<Font<'ttf, 'static>>,
+}
+
+impl<'ttf> SubtitleRenderer<'ttf> {
+    /// the font comes from RSPLAYER_SUBTITLE_FONT or the DejaVu default
+    pub fn new(ttf: Option<&'ttf Sdl2TtfContext>) -> SubtitleRenderer<'ttf> {
+        let path = std::env::var("RSPLAYER_SUBTITLE_FONT").unwrap_or(DEFAULT_FONT.to_string());
+        let font = ttf.and_then(|ttf| match ttf.load_font(&path, FONT_SIZE) {
+            Ok(font) => {
+                info!("subtitle font {}", path);
+                Some(font)
+            }
+            Err(e) => {
+                error!("🚩 cannot load subtitle font {}: {}", path, e);
+                None
+            }
+        });
+        SubtitleRenderer { font }
+    }
+
+    pub fn draw(
+        &self,
+        canvas: &mut Canvas<Window>,
+        texture_creator: &TextureCreator<WindowContext>,
+        subtitles: &[Subtitle],
+        dest: Rect,
+    ) {
+        // text is stacked upwards from the bottom of the movie
+        let mut bottom = dest.bottom() - MARGIN;
+        for subtitle in subtitles {
+            match &subtitle.content {
+                SubtitleContent::Text(text) => {
+                    if let Some(height) = self.draw_text(canvas, texture_creator, text, dest, bottom) {
+                        bottom -= height;
+                    }
+                }
+                SubtitleContent::Bitmap { rects, canvas: size } => {
+                    rects.iter().for_each(|rect| draw_bitmap(canvas, texture_creator, rect, *size, dest));
+                }
+            }
+        }
+    }
+
+    /// returns the height used
+    fn draw_text(
+        &self,
+        canvas: &mut Canvas<Window>,
+        texture_creator: &TextureCreator<WindowContext>,
+        text: &str,
+        dest: Rect,
+        bottom: i32,
+    ) -> Option<i32> {
+        let font = self.font.as_ref()?;
+        let surface = font
+            .render(text)
+            .blended_wrapped(Color::RGB(255, 255, 255), dest.width() * 9 / 10)
+            .map_err(|e| error!("🚩 cannot render subtitle: {}", e))
+            .ok()?;
+        let texture = texture_creator.create_texture_from_surface(&surface).ok()?;
+        let (w, h) = (surface.width(), surface.height());
+        let target = Rect::new(dest.x() + (dest.width() as i32 - w as i32) / 2, bottom - h as i32, w, h);
+
+        // a dark box keeps the text readable on bright pictures
+        canvas.set_blend_mode(BlendMode::Blend);
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, 160));
+        let _ = canvas.fill_rect(Rect::new(target.x() - 6, target.y() - 2, w + 12, h + 4));
+        let _ = canvas.copy(&texture, None, Some(target));
+        Some(h as i32 + 4)
+    }
+}
+
+/// scale a bitmap rect from the subtitle's picture size to `dest`
+fn draw_bitmap(
+    canvas: &mut Canvas<Window>,
+    texture_creator: &TextureCreator<WindowContext>,
+    bitmap: &SubtitleBitmap,
+    (canvas_width, canvas_height): (i32, i32),
+    dest: Rect,
+) {
+    if bitmap.width <= 0 || bitmap.height <= 0 || canvas_width <= 0 || canvas_height <= 0 {
+        return;
+    }
+    let mut texture = match texture_creator.create_texture_static(
+        Some(PixelFormatEnum::RGBA32),
+        bitmap.width as u32,
+        bitmap.height as u32,
+    ) {
+        Ok(texture) => texture,
+        Err(e) => {
+            error!("🚩 cannot create subtitle texture: {}", e);
+            return;
+        }
+    };
+    if texture.update(None, &bitmap.rgba, bitmap.width as usize * 4).is_err() {
+        return;
+    }
+    texture.set_blend_mode(BlendMode::Blend);
+    let scale_x = dest.width() as f64 / canvas_width as f64;
+    let scale_y = dest.height() as f64 / canvas_height as f64;
+    let target = Rect::new(
+        dest.x() + (bitmap.x as f64 * scale_x) as i32,
+        dest.y() + (bitmap.y as f64 * scale_y) as i32,
+        (bitmap.width as f64 * scale_x).max(1.) as u32,
+        (bitmap.height as f64 * scale_y).max(1.) as u32,
+    );
+    let _ = canvas.copy(&texture, None, Some(target));
+}
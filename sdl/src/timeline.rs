@@ -0,0 +1,62 @@
+use sdl2::{
+    pixels::Color,
+    rect::Rect,
+    render::{BlendMode, Canvas},
+    video::Window,
+};
+
+use rsplayer::{movie_state::MovieState, timeline::PacketRecord};
+
+/// seconds of packets shown either side of the current position
+static SPAN: f64 = 2.;
+
+fn bar_colour(packet: &PacketRecord) -> Color {
+    match (packet.pict_type, packet.keyframe) {
+        (Some('I'), _) | (None, true) => Color::RGB(230, 60, 60),
+        (Some('P'), _) => Color::RGB(60, 200, 60),
+        (Some('B'), _) => Color::RGB(70, 110, 240),
+        _ => Color::RGB(150, 150, 150),
+    }
+}
+
+/// video packet sizes around the current position as bars, coloured by picture type
+#[derive(Default)]
+pub struct TimelineGraph {
+    pub enabled: bool,
+}
+
+impl TimelineGraph {
+    pub fn draw(&self, canvas: &mut Canvas<Window>, movie: &MovieState, dest: Rect) {
+        let stream_index = movie.video_stream_index();
+        if !self.enabled || stream_index < 0 {
+            return;
+        }
+        let position = movie.position();
+        let packets = movie
+            .packet_timeline
+            .lock()
+            .unwrap()
+            .packets_between(stream_index as usize, position - SPAN, position + SPAN);
+
+        canvas.set_blend_mode(BlendMode::Blend);
+        canvas.set_draw_color(Color::RGBA(0, 0, 0, 160));
+        let _ = canvas.fill_rect(dest);
+        let largest = packets.iter().map(|p| p.size).max().unwrap_or(0).max(1);
+        let spacing = match (packets.first(), packets.last()) {
+            (Some(first), Some(last)) if packets.len() > 1 => (last.time - first.time) / (packets.len() - 1) as f64,
+            _ => 0.,
+        };
+        let scale = dest.width() as f64 / (2. * SPAN);
+        let bar_width = ((spacing * scale) as u32).saturating_sub(1).max(1);
+        for packet in &packets {
+            let x = dest.x() + ((packet.time - position + SPAN) * scale) as i32;
+            let height = ((packet.size * dest.height() as usize / largest) as u32).max(1);
+            canvas.set_draw_color(bar_colour(packet));
+            let _ = canvas.fill_rect(Rect::new(x, dest.bottom() - height as i32, bar_width, height));
+        }
+        // where playback is
+        canvas.set_draw_color(Color::RGB(255, 255, 255));
+        let middle = dest.x() + dest.width() as i32 / 2;
+        let _ = canvas.draw_line((middle, dest.y()), (middle, dest.bottom() - 1));
+    }
+}
@@ -1,12 +1,23 @@
 #![allow(unused_variables, dead_code, unused)]
-use ::std::{ops::Deref, slice::Iter, thread::JoinHandle};
+use ::std::{ops::Deref, slice::Iter, sync::{atomic::{AtomicBool, Ordering}, mpsc::Sender, Arc, Mutex, RwLock}, thread::JoinHandle};
 
-use log::debug;
+use log::{debug, error};
 use ::log::info;
 use rusty_ffmpeg::ffi;
 use ::rusty_ffmpeg::ffi::{AVDurationEstimationMethod_AVFMT_DURATION_FROM_BITRATE, AV_NOPTS_VALUE};
 
-use crate::movie_state::{self, FrameWrapper, MovieState};
+use crate::app::start_movie_threads;
+use crate::audio::{AudioMixer, AudioSources, AUDIO_CHANNELS, AUDIO_SAMPLE_RATE};
+use crate::command::{AnalyzerEvent, Command};
+use crate::error::MovieError;
+use crate::metrics::{MetricsHistory, MetricsRecorder};
+use crate::frame_info::FrameInfo;
+use crate::movie_state::{self, FilteredFrame, MovieState, SeekMode};
+
+/// slowest playback `set_speed` accepts
+pub const MIN_SPEED: f32 = 0.1;
+/// fastest playback `set_speed` accepts, the decoders are usually the limit well before
+pub const MAX_SPEED: f32 = 16.;
 
 #[derive(Default, Debug)]
 pub struct Clock {
@@ -17,42 +28,308 @@ pub struct Clock {
     paused: bool,
 }
 
-pub struct AnalyzerContext {
-    pub movie_list: Vec<MovieState>,
+/// the shared presentation clock while movies play in sync.
+/// positions are seconds from the start of the movies, after their time offsets.
+pub struct SyncState {
+    /// `av_gettime_relative` when `anchor_position` was on screen
+    anchor_time: i64,
+    /// NAN until every movie has a frame queued
+    anchor_position: f64,
+    /// what the last tick presented
+    position: f64,
+    /// the filtered frames of the last tick, handed out by `dequeue_frame`
+    frames: Vec<Option<FilteredFrame>>,
+    taken: Vec<bool>,
+    delay: f64,
+}
+
+impl SyncState {
+    fn new() -> SyncState {
+        SyncState {
+            anchor_time: 0,
+            anchor_position: f64::NAN,
+            position: 0.,
+            frames: vec![],
+            taken: vec![],
+            delay: 0.,
+        }
+    }
+
+    /// start over from whatever is queued next
+    fn reset(&mut self) {
+        self.anchor_position = f64::NAN;
+        self.free_frames();
+    }
+
+    fn free_frames(&mut self) {
+        self.frames.iter_mut().filter_map(|f| f.take()).for_each(|mut f| unsafe {
+            ffi::av_frame_free(&mut f.frame.ptr);
+        });
+        self.taken.clear();
+    }
+}
+
+impl Drop for SyncState {
+    fn drop(&mut self) {
+        self.free_frames();
+    }
+}
+
+/// what the UI thread and the control thread share. every field is behind a
+/// lock or an atomic, both only ever hold a `&AnalyzerState`.
+pub struct AnalyzerState {
+    /// shared with each movie's threads, which keep their movie alive until they exit.
+    /// taken for writing when movies are added or removed, see `movies`.
+    movie_list: RwLock<Vec<Arc<MovieState>>>,
+    /// what the audio device mixes, follows `movie_list`
+    audio_sources: Arc<AudioSources>,
     pub paused: std::sync::atomic::AtomicBool,
-    pub clock: Clock,
-    pub force_render: bool,
+    pub clock: Mutex<Clock>,
+    pub force_render: AtomicBool,
+    /// present every movie from one clock instead of letting each run on its own
+    pub synchronized: AtomicBool,
+    pub sync_state: Mutex<SyncState>,
+    /// PSNR and SSIM of every movie against movie 0, see `set_metrics_enabled`
+    pub metrics: Mutex<MetricsRecorder>,
+    events: Mutex<Option<Sender<AnalyzerEvent>>>,
+}
+
+/// the analyzer as the UI thread owns it. everything but adding and removing
+/// movies and the control thread goes through `AnalyzerState`.
+pub struct AnalyzerContext {
+    /// the control thread holds another reference while the analyzer runs
+    state: Arc<AnalyzerState>,
     thread_handle: Option<JoinHandle<()>>,
+    /// the control thread's end of the `ControlChannel`, `join` sends `Quit` on it
+    commands: Option<Sender<Command>>,
+}
+
+impl Deref for AnalyzerContext {
+    type Target = AnalyzerState;
+
+    fn deref(&self) -> &AnalyzerState {
+        &self.state
+    }
 }
 
 impl AnalyzerContext {
     pub fn new() -> AnalyzerContext {
         AnalyzerContext {
-            movie_list: vec![],
-            paused: std::sync::atomic::AtomicBool::new(true),
-            clock: Clock{ paused: false, pts: AV_NOPTS_VALUE, speed: 1.0, ..Default::default() },
-            force_render: true,
+            state: Arc::new(AnalyzerState {
+                movie_list: RwLock::new(vec![]),
+                audio_sources: Arc::new(AudioSources::default()),
+                paused: std::sync::atomic::AtomicBool::new(true),
+                clock: Mutex::new(Clock{ paused: false, pts: AV_NOPTS_VALUE, speed: 1.0, ..Default::default() }),
+                force_render: AtomicBool::new(true),
+                synchronized: AtomicBool::new(false),
+                sync_state: Mutex::new(SyncState::new()),
+                metrics: Mutex::new(MetricsRecorder::new()),
+                events: Mutex::new(None),
+            }),
             thread_handle: None,
+            commands: None,
         }
     }
 }
 
 impl AnalyzerContext {
-    pub fn add_movie_state(&mut self, mut movie: MovieState) {
-        movie.pause();
-        self.movie_list.push(movie);
+    /// add a movie and return its index. while the analyzer is running the
+    /// movie gets its threads right away and starts where movie 0 is.
+    pub fn add_movie(&mut self, movie: MovieState) -> usize {
+        movie.set_paused(self.is_paused());
+        // only the first movie is audible by default
+        movie.set_muted(self.movie_count() > 0);
+        movie.set_speed(self.speed());
+        if self.is_running() {
+            if let Some(position) = self.movie(0).map(|m| m.position()) {
+                movie.seek(position, SeekMode::Accurate);
+            }
+        }
+        let movie = Arc::new(movie);
+        let movie_index = {
+            let mut movies = self.movie_list.write().unwrap();
+            movie.movie_index.store(movies.len(), Ordering::Relaxed);
+            movies.push(Arc::clone(&movie));
+            movies.len() - 1
+        };
+        self.audio_sources.add(Arc::clone(&movie.audio_samples));
+        self.start_threads(&movie);
+        self.movies_changed();
+        info!("📽  added movie {}", movie_index);
+        self.emit(AnalyzerEvent::MovieAdded { movie_index });
+        movie_index
+    }
+
+    /// stop a movie's threads and drop it, the movies after it move up one index.
+    /// the others keep playing.
+    pub fn remove_movie(&mut self, movie_index: usize) -> Result<(), MovieError> {
+        let movie = {
+            let mut movies = self.movie_list.write().unwrap();
+            if movie_index >= movies.len() {
+                return Err(MovieError::InvalidMovieIndex(movie_index));
+            }
+            let movie = movies.remove(movie_index);
+            movies.iter().enumerate().skip(movie_index).for_each(|(index, m)| {
+                m.movie_index.store(index, Ordering::Relaxed);
+            });
+            movie
+        };
+        movie.stop_threads();
+        self.audio_sources.remove(&movie.audio_samples);
+        if !movie.is_muted() && self.audible_movie().is_none() && self.movie_count() > 0 {
+            self.solo_audio(0);
+        }
+        self.movies_changed();
+        drop(movie);
+        info!("📽  removed movie {}", movie_index);
+        self.emit(AnalyzerEvent::MovieRemoved { movie_index });
+        Ok(())
+    }
+
+    /// swap the movie at `movie_index` for another one, which takes over its
+    /// position and audio. the others keep playing.
+    pub fn replace_movie(&mut self, movie_index: usize, movie: MovieState) -> Result<(), MovieError> {
+        // only this thread adds and removes movies, the index stays valid
+        let old = self.movie(movie_index).ok_or(MovieError::InvalidMovieIndex(movie_index))?;
+        old.stop_threads();
+        movie.set_paused(self.is_paused());
+        movie.set_muted(old.is_muted());
+        movie.set_speed(self.speed());
+        movie.set_end_behavior(old.end_behavior());
+        if self.is_running() {
+            movie.seek(old.position(), SeekMode::Accurate);
+        }
+        movie.movie_index.store(movie_index, Ordering::Relaxed);
+        let movie = Arc::new(movie);
+        self.audio_sources.remove(&old.audio_samples);
+        self.audio_sources.add(Arc::clone(&movie.audio_samples));
+        self.movie_list.write().unwrap()[movie_index] = Arc::clone(&movie);
+        self.start_threads(&movie);
+        self.movies_changed();
+        drop(old);
+        info!("📽  replaced movie {}", movie_index);
+        self.emit(AnalyzerEvent::MovieReplaced { movie_index });
+        Ok(())
+    }
+
+    /// true from `start_analyzer` until the control thread is joined
+    pub fn is_running(&self) -> bool {
+        self.thread_handle.is_some()
+    }
+
+    fn start_threads(&self, movie: &Arc<MovieState>) {
+        if !self.is_running() {
+            return;
+        }
+        if let Some(events) = self.events.lock().unwrap().clone() {
+            unsafe { start_movie_threads(movie, events) };
+        }
+    }
+
+    /// what the control thread works on
+    pub(crate) fn state(&self) -> Arc<AnalyzerState> {
+        Arc::clone(&self.state)
+    }
+
+    pub fn close(mut this: Self) {
+        info!("closing analyzer...");
+        this.join();
+        // the control thread let go of the state, the movies are dropped with the last reference
+        this.movie_list.write().unwrap().clear();
+    }
+
+    /// stop the control thread, and with it every movie thread, and wait for them.
+    /// the movie threads are stopped here when the control thread panicked.
+    pub fn join(&mut self) {
+        if let Some(commands) = self.commands.take() {
+            // fails when the caller sent `Quit` already
+            let _ = commands.send(Command::Quit);
+        }
+        if let Some(handle) = self.thread_handle.take() {
+            if handle.join().is_err() {
+                error!("🦀🦀 the control thread panicked");
+                self.stop_movie_threads();
+            }
+        }
+    }
+
+    pub fn set_control_thread(&mut self, handle: JoinHandle<()>, commands: Sender<Command>) {
+        self.thread_handle = Some(handle);
+        self.commands = Some(commands);
+    }
+}
+
+impl AnalyzerState {
+    /// the movies as they are now. a movie removed meanwhile stays valid
+    /// for as long as the copy is held.
+    pub fn movies(&self) -> Vec<Arc<MovieState>> {
+        self.movie_list.read().unwrap().clone()
+    }
+
+    pub fn movie(&self, movie_index: usize) -> Option<Arc<MovieState>> {
+        self.movie_list.read().unwrap().get(movie_index).cloned()
+    }
+
+    /// stop the threads of every movie, the movies stay attached
+    pub fn stop_movie_threads(&self) {
+        self.movies().iter().for_each(|movie| movie.stop_threads());
+    }
+
+    /// the per-movie bookkeeping is by index, start it over
+    fn movies_changed(&self) {
+        self.sync_state.lock().unwrap().reset();
+        self.metrics.lock().unwrap().reset();
+        self.force_render.store(true, Ordering::Relaxed);
+    }
+
+    /// open an SDL playback device that mixes the audio of all movies.
+    /// keep the returned device alive and call `resume()` on it to start playback.
+    /// works with the "dummy" and "disk" SDL audio drivers for headless runs.
+    pub fn open_audio(&self, audio: &sdl2::AudioSubsystem) -> Result<sdl2::audio::AudioDevice<AudioMixer>, String> {
+        let desired = sdl2::audio::AudioSpecDesired {
+            freq: Some(AUDIO_SAMPLE_RATE),
+            channels: Some(AUDIO_CHANNELS as u8),
+            samples: Some(1024),
+        };
+        let sources = Arc::clone(&self.audio_sources);
+        audio.open_playback(None, &desired, |spec| {
+            info!("🔊 opened audio device: {} Hz, {} channels", spec.freq, spec.channels);
+            AudioMixer::new(sources)
+        })
+    }
+
+    /// make `movie_index` the only audible movie
+    pub fn solo_audio(&self, movie_index: usize) {
+        self.movies().iter().enumerate().for_each(|(index, movie)| {
+            movie.set_muted(index != movie_index);
+        });
+    }
+
+    /// the first movie that is not muted
+    pub fn audible_movie(&self) -> Option<usize> {
+        self.movies().iter().position(|movie| !movie.is_muted())
     }
 
     pub fn movie_count(&self) -> u8 {
-        self.movie_list.len() as u8
+        self.movie_list.read().unwrap().len() as u8
     }
 
-    pub fn dequeue_frame(&mut self, movie_index: u8) -> (f64, Option<*mut ffi::AVFrame>) {
-        if self.movie_list.len() == 0 {
-            return (0., None);
+    /// the next filtered frame of a movie that is due, with the seconds to wait
+    /// before showing it and what the decoder said about it. the caller frees the frame.
+    pub fn dequeue_frame(&self, movie_index: u8) -> (f64, Option<(*mut ffi::AVFrame, FrameInfo)>) {
+        let movie_state = match self.movie(movie_index as usize) {
+            Some(movie_state) => movie_state,
+            None => return (0., None),
+        };
+        if self.is_synchronized() {
+            let (delay, frame) = self.dequeue_synchronized_frame(movie_index as usize);
+            if let Some((frame, _)) = frame.as_ref() {
+                self.record_metrics(movie_index as usize, *frame);
+            }
+            return (delay, frame);
         }
         let mut frame_delay = 0.;
-        if let (delay, Some(pts)) = self.peek_movie_state_packet(movie_index as _) {
+        if let (delay, Some(pts)) = self.peek_movie_state_packet(&movie_state) {
             if pts != 0 && self.is_paused() {
                 return (0., None);
             }
@@ -60,172 +337,561 @@ impl AnalyzerContext {
         } else {
             return (0., None)
         }
-        let mut dest_frame = unsafe {
-            ffi::av_frame_alloc()
-            .as_mut()
-            .expect("failed to allocated memory for AVFrame")
-        };
+        let filtered = movie_state.dequeue_filtered_frame();
+        self.report_format_change(movie_index as usize);
+        match filtered {
+            Some(filtered) => {
+                self.record_metrics(movie_index as usize, filtered.frame.ptr);
+                (frame_delay as _, Some((filtered.frame.ptr, filtered.info)))
+            }
+            None => (0., None),
+        }
 
-        let movie_state: &MovieState = self.movie_list.get(movie_index as usize).unwrap();
-        unsafe {
-        if let Some(mut frame) = movie_state.dequeue_frame() {
-                
-            let mut in_vfilter = movie_state.in_vfilter.lock().unwrap();
-            let mut out_vfilter = movie_state.out_vfilter.lock().unwrap();
-            let mut vgraph = movie_state.vgraph.lock().unwrap();
-
-            if in_vfilter.is_null() || out_vfilter.is_null() {
-                let format_context = &movie_state.format_context;
-                let rotation = 0;
-                // let rotation = get_orientation_metadata_value(
-                //     (*format_context).lock().unwrap().ptr,
-                // );
-                crate::filter::init_filter(
-                    rotation,
-                    &mut vgraph.ptr,
-                    &mut out_vfilter.ptr,
-                    &mut in_vfilter.ptr,
-                    (
-                        frame.ptr.as_ref().unwrap().width,
-                        frame.ptr.as_ref().unwrap().height,
-                    ),
-                    frame.ptr.as_ref().unwrap().format,
-                );
-            }
-            ffi::av_buffersrc_add_frame(in_vfilter.ptr, frame.ptr);
-            ffi::av_buffersink_get_frame_flags(out_vfilter.ptr, dest_frame, 0);
-            unsafe { ffi::av_frame_free(&mut frame as *mut _ as *mut _) };
-            return (frame_delay as _, Some(dest_frame));
-        }
-        }
-        unsafe { ffi::av_frame_free(&mut dest_frame as *mut _ as *mut _) };
-        (0., None)
-
-    }
-
-    fn peek_movie_state_packet(&mut self, movie_index: usize) -> (f64, Option<i64>) {
-        let movie_state = self.movie_list.get_mut(movie_index).unwrap();
-        // for (index, movie_state) in self.movie_list.iter_mut().enumerate() {
-            if movie_state.step {
-                movie_state.step = false;
-                return (0. as _, Some(0));
+    }
+
+    /// in sync mode one tick picks the frames of every movie. the tick runs when
+    /// the caller comes back for a movie it already got this tick's frame for.
+    fn dequeue_synchronized_frame(&self, movie_index: usize) -> (f64, Option<(*mut ffi::AVFrame, FrameInfo)>) {
+        let needs_tick = self.sync_state.lock().unwrap().taken.get(movie_index).copied().unwrap_or(true);
+        if needs_tick {
+            self.sync_tick();
+        }
+        let mut sync = self.sync_state.lock().unwrap();
+        if let Some(taken) = sync.taken.get_mut(movie_index) {
+            *taken = true;
+        }
+        let delay = sync.delay;
+        match sync.frames.get_mut(movie_index).and_then(|f| f.take()) {
+            Some(decoded) => (delay, Some((decoded.frame.ptr, decoded.info))),
+            None => (0., None),
+        }
+    }
+
+    /// advance the shared clock and take the frame due at its position from every movie.
+    /// frames skipped because the clock moved past them are skipped in every movie,
+    /// and a movie whose decoder falls behind holds the clock for all of them.
+    fn sync_tick(&self) {
+        let movies = self.movies();
+        let now = unsafe { ffi::av_gettime_relative() };
+        let speed = self.clock.lock().unwrap().speed.max(MIN_SPEED) as f64;
+        let paused = self.is_paused();
+        let mut sync = self.sync_state.lock().unwrap();
+        sync.free_frames();
+        sync.frames.resize_with(movies.len(), || None);
+        sync.taken = vec![false; movies.len()];
+
+        for movie in movies.iter() {
+            if movie.resync.swap(false, Ordering::Relaxed) {
+                movie.last_pts.store(ffi::AV_NOPTS_VALUE, Ordering::Relaxed);
+                movie.last_pts_time.store(0.);
+                movie.last_display_time.store(0.);
+                sync.anchor_position = f64::NAN;
             }
-            let mut current_clock = unsafe {ffi::av_gettime_relative()};
-            let mut retry_count = 0;
-            'retry: loop {
-            if let Some(pts) = movie_state.peek_frame_pts() {
-                let frame_rate = movie_state.video_frame_rate;
-
-                let mut delay: f64 = 0.;
-                unsafe {
-                let mut last_clock = self.clock.last_updated;
-                if self.clock.pts == ffi::AV_NOPTS_VALUE {
-                    last_clock = 0;
+        }
+        if movies.iter().any(|movie| movie.is_seeking()) {
+            return;
+        }
+        // movies that stopped at their end keep showing their last frame
+        let live: Vec<usize> = (0..movies.len())
+            .filter(|i| {
+                let movie = &movies[*i];
+                !movie.is_closed() && !(movie.has_ended() && movie.peek_frame_pts().is_none())
+            })
+            .collect();
+        if live.is_empty() {
+            return;
+        }
+        let next_position = |movie: &MovieState| {
+            movie.peek_frame_pts().map(|pts| movie.pts_to_position(pts) - movie.time_offset())
+        };
+
+        // a looping movie starts over, the clock follows it
+        for i in live.iter() {
+            let movie = &movies[*i];
+            if let Some(pts) = movie.peek_frame_pts() {
+                let last_pts = movie.last_pts.load(Ordering::Relaxed);
+                if last_pts != ffi::AV_NOPTS_VALUE && pts < last_pts {
+                    movie.last_pts.store(ffi::AV_NOPTS_VALUE, Ordering::Relaxed);
+                    sync.anchor_position = movie.pts_to_position(pts) - movie.time_offset();
+                    sync.anchor_time = now;
                 }
-                let delta = current_clock - last_clock;
+            }
+        }
 
+        let queued: Vec<Option<f64>> = live.iter().map(|i| next_position(&movies[*i])).collect();
+        let all_queued = queued.iter().all(|p| p.is_some());
+        let first_queued = queued.iter().flatten().copied().fold(f64::INFINITY, f64::min);
+        let stepping = all_queued && live.iter().any(|i| movies[*i].step.load(Ordering::Relaxed));
+        if paused && !stepping {
+            return;
+        }
+        if sync.anchor_position.is_nan() {
+            if !all_queued {
+                return;
+            }
+            sync.anchor_position = first_queued;
+            sync.anchor_time = now;
+            sync.position = first_queued;
+        }
 
-                // we looped, this pts is less than last displayed pts
-                if pts < movie_state.last_pts && movie_state.last_pts != ffi::AV_NOPTS_VALUE {
-                    movie_state.last_pts = ffi::AV_NOPTS_VALUE;
-                    // reset the master clock down ?
-                    // self.clock.pts = movie_state.last_pts;
-                }
+        let target = if stepping {
+            live.iter().for_each(|i| movies[*i].step.store(false, Ordering::Relaxed));
+            sync.anchor_position = first_queued;
+            sync.anchor_time = now;
+            first_queued
+        } else {
+            sync.anchor_position + (now - sync.anchor_time) as f64 / 1_000_000. * speed
+        };
 
-                if movie_state.last_pts != ffi::AV_NOPTS_VALUE {
-                    let time_base = (*(movie_state.video_stream.lock().unwrap()).ptr).time_base;
-                    let pts_time = pts as f64  * time_base.num as f64 / time_base.den as f64;
-                    let movie_delta_time = ((current_clock as f64) / 1_000_000.) - ((self.clock.last_updated as f64) / 1_000_000.);
-                    if pts_time - movie_state.last_pts_time >  movie_delta_time {
-                        delay = pts_time - movie_state.last_pts_time - movie_delta_time;
-                        // info!("{}, {}, {}", movie_delta_time, pts_time - movie_state.last_pts_time, delay);
-                    }
+        // hold everyone on the last presented position until the late decoder catches up
+        let late = live.iter().zip(queued.iter())
+            .any(|(i, next)| next.is_none() && !movies[*i].has_ended());
+        if late && !stepping {
+            sync.anchor_position = sync.position;
+            sync.anchor_time = now;
+            return;
+        }
 
-                    movie_state.last_pts_time = pts_time;
+        for i in live.iter() {
+            let movie = &movies[*i];
+            let mut shown: Option<FilteredFrame> = None;
+            while let Some(position) = next_position(&*movie) {
+                if position > target + 0.0001 {
+                    break;
                 }
+                // a seek or stream switch may have emptied the queue since it was peeked
+                let next = match movie.dequeue_filtered_frame() {
+                    Some(next) => next,
+                    None => break,
+                };
+                if let Some(mut skipped) = shown.replace(next) {
+                    unsafe { ffi::av_frame_free(&mut skipped.frame.ptr) };
                 }
-                if delay > 0.0001 {
-                    // TODO: check other movie_states to see if any other frame is ready for display
-                    // ::std::thread::sleep(std::time::Duration::from_secs_f64((delay - 0.0001).max(0.)));
-                    // return (0, None);
-                    // continue;
+            }
+            if let Some(filtered) = shown {
+                movie.last_pts.store(filtered.pts, Ordering::Relaxed);
+                movie.last_pts_time.store(movie.pts_to_position(filtered.pts));
+                movie.last_display_time.store(now as f64 / 1_000_000.);
+                sync.frames[*i] = Some(filtered);
+            }
+        }
+        sync.position = target;
+        for i in live.iter() {
+            self.report_format_change(*i);
+        }
+
+        let next_due = live.iter()
+            .filter_map(|i| next_position(&movies[*i]))
+            .fold(f64::INFINITY, f64::min);
+        sync.delay = match next_due.is_finite() {
+            true => ((next_due - target) / speed).clamp(0., 0.1),
+            false => 1. / 60.,
+        };
+    }
+
+    /// tell the caller when a movie's frames changed format on the way into the filter graph
+    fn report_format_change(&self, movie_index: usize) {
+        if let Some((from, to)) = self.movie(movie_index).and_then(|movie| movie.take_format_change()) {
+            self.emit(AnalyzerEvent::FormatChanged { movie_index, from, to });
+        }
+    }
+
+    /// hand a presented frame to the metrics recorder, frames pair up by
+    /// their offset adjusted position within half a frame of movie 0
+    fn record_metrics(&self, movie_index: usize, frame: *const ffi::AVFrame) {
+        let mut metrics = self.metrics.lock().unwrap();
+        if !metrics.enabled {
+            return;
+        }
+        if let Some(position) = unsafe { frame.as_ref() }.and_then(|f| self.frame_position(movie_index, f)) {
+            unsafe { metrics.present(movie_index, frame, position, self.pairing_tolerance()) };
+        }
+    }
+
+    /// where a frame a movie presented sits once its time offset is taken off,
+    /// frames of different movies showing the same moment have the same position
+    pub fn frame_position(&self, movie_index: usize, frame: &ffi::AVFrame) -> Option<f64> {
+        let movie = self.movie(movie_index)?;
+        Some(movie.pts_to_position(frame.pts) - movie.time_offset())
+    }
+
+    /// half a frame of movie 0, how far apart the positions of two frames may be
+    /// for them to count as the same moment
+    pub fn pairing_tolerance(&self) -> f64 {
+        let frame_rate = match self.movie(0) {
+            Some(reference) => *reference.video_frame_rate.lock().unwrap(),
+            None => return 0.001,
+        };
+        match frame_rate.num > 0 {
+            true => 0.5 * frame_rate.den as f64 / frame_rate.num as f64,
+            false => 0.001,
+        }
+    }
+
+    /// start or stop measuring. the history is kept when measuring stops.
+    pub fn set_metrics_enabled(&self, enabled: bool) {
+        let mut metrics = self.metrics.lock().unwrap();
+        metrics.enabled = enabled;
+        metrics.reset();
+    }
+
+    pub fn metrics_enabled(&self) -> bool {
+        self.metrics.lock().unwrap().enabled
+    }
+
+    /// a copy of everything measured so far, waits for the pairs still being measured
+    pub fn metrics_history(&self) -> MetricsHistory {
+        self.metrics.lock().unwrap().history()
+    }
+
+    pub fn clear_metrics(&self) {
+        self.metrics.lock().unwrap().clear_history();
+    }
+
+    pub fn is_synchronized(&self) -> bool {
+        self.synchronized.load(Ordering::Relaxed)
+    }
+
+    /// switch between the shared clock and every movie running on its own
+    pub fn set_synchronized(&self, synchronized: bool) {
+        self.synchronized.store(synchronized, Ordering::Relaxed);
+        self.sync_state.lock().unwrap().reset();
+        self.movies().iter().for_each(|movie| movie.resync.store(true, Ordering::Relaxed));
+        self.force_render.store(true, Ordering::Relaxed);
+    }
+
+    /// shift one movie against the others. the movie is seeked to its new
+    /// position so it does not have to decode its way there.
+    pub fn set_time_offset(&self, movie_index: usize, offset: f64) -> Result<(), MovieError> {
+        let movie = self.movie(movie_index).ok_or(MovieError::InvalidMovieIndex(movie_index))?;
+        let position = match self.is_synchronized() {
+            true => self.sync_state.lock().unwrap().position,
+            false => movie.position() - movie.time_offset(),
+        };
+        movie.set_time_offset(offset);
+        movie.seek((position + offset).max(0.), SeekMode::Accurate);
+        if self.is_paused() {
+            movie.step_force();
+        }
+        self.sync_state.lock().unwrap().reset();
+        self.force_render.store(true, Ordering::Relaxed);
+        Ok(())
+    }
+
+    fn peek_movie_state_packet(&self, movie_state: &MovieState) -> (f64, Option<i64>) {
+            if movie_state.resync.swap(false, std::sync::atomic::Ordering::Relaxed) {
+                movie_state.last_pts.store(ffi::AV_NOPTS_VALUE, Ordering::Relaxed);
+                movie_state.last_pts_time.store(0.);
+                movie_state.last_display_time.store(0.);
+            }
+            // whatever is queued now is from before the seek
+            if movie_state.is_seeking() {
+                return (0., None);
+            }
+            if movie_state.step.load(Ordering::Relaxed) && movie_state.peek_frame_pts().is_some() {
+                movie_state.step.store(false, Ordering::Relaxed);
+                // remembered so a step back knows where it starts from
+                movie_state.last_pts.store(movie_state.peek_frame_pts().unwrap(), Ordering::Relaxed);
+                return (0. as _, Some(0));
+            }
+            if self.paused.load(Ordering::Relaxed) {
+                return (0., None);
+            }
+            let mut clock = self.clock.lock().unwrap();
+            let speed = clock.speed.max(MIN_SPEED) as f64;
+            let current_clock = unsafe { ffi::av_gettime_relative() };
+            let now = current_clock as f64 / 1_000_000.;
+            let mut pts = match movie_state.peek_frame_pts() {
+                Some(pts) => pts,
+                None => return (0., None),
+            };
+
+            // we looped, this pts is less than last displayed pts
+            let mut last_pts = movie_state.last_pts.load(Ordering::Relaxed);
+            if pts < last_pts && last_pts != ffi::AV_NOPTS_VALUE {
+                last_pts = ffi::AV_NOPTS_VALUE;
+            }
+
+            // last_pts_time and last_display_time anchor the movie: the position
+            // shown at that wall clock time. the movie has moved on by the time
+            // since then, times the speed.
+            let target = if last_pts == ffi::AV_NOPTS_VALUE {
+                let position = movie_state.pts_to_position(pts);
+                movie_state.last_pts_time.store(position);
+                movie_state.last_display_time.store(now);
+                position
+            } else {
+                let target = movie_state.last_pts_time.load() + (now - movie_state.last_display_time.load()) * speed;
+                if movie_state.pts_to_position(pts) > target + 0.001 {
+                    return (0., None);
                 }
-                let time_base = unsafe {(*(movie_state.video_stream.lock().unwrap()).ptr).time_base};
-                let pts_time = pts as f64  * time_base.num as f64 / time_base.den as f64;
-
-                if (movie_state.last_display_time + pts_time as f64 - 0.001) < (current_clock as f64 / 1_000_000.)
-                    && ! self.paused.load(::std::sync::atomic::Ordering::Relaxed)
-                    && movie_state.last_pts != ffi::AV_NOPTS_VALUE {
-                    // frame drop
-                    // info!("frame drop {}", delay);
-                    // TODO: don't update the last clock somehow.  otherwise the movies can
-                    // TODO: get out of sync with eacher but remain relatively correct with the deltas
-                    // movie_state.last_pts      = pts; //ffi::AV_NOPTS_VALUE;
-                    // movie_state.last_pts_time = pts_time;
-                    let mut f = movie_state.dequeue_frame().unwrap();
-                    unsafe { ffi::av_frame_free(&mut f.ptr as *mut _ as *mut _) };
-
-                        return (0., None);
-                    retry_count += 1;
-                    if retry_count == 5 {
-                        return (0., None);
+                // frame drop: when the frame after this one is due as well, skip this one.
+                // playing fast most frames are skipped this way.
+                while let Some(next) = movie_state.peek_frame_pts_at(1) {
+                    if next < pts || movie_state.pts_to_position(next) > target + 0.001 {
+                        break;
+                    }
+                    if let Some(mut skipped) = movie_state.dequeue_filtered_frame() {
+                        unsafe { ffi::av_frame_free(&mut skipped.frame.ptr) };
                     }
-                    continue 'retry;
-               }
+                    pts = next;
+                }
+                target
+            };
+
+            // sleep until the next queued frame is due, in wall clock time
+            let delay = match movie_state.peek_frame_pts_at(1) {
+                Some(next) if next > pts => ((movie_state.pts_to_position(next) - target) / speed).clamp(0., 0.1),
+                _ => 0.,
+            };
 
-               movie_state.last_pts      = pts;
-               movie_state.last_display_time = current_clock as f64 / 1_000_000.;
-               // next frame's delay is based on only the latest pts
-               if self.clock.pts < movie_state.last_pts {
-                   self.clock.pts = movie_state.last_pts;
-               }
-               self.clock.last_updated = current_clock;
-               return (delay as _, Some(pts));
-               }
+            movie_state.last_pts.store(pts, Ordering::Relaxed);
+            // next frame's delay is based on only the latest pts
+            if clock.pts < pts {
+                clock.pts = pts;
             }
-        (0., None)
+            clock.last_updated = current_clock;
+            (delay, Some(pts))
     }
 
-    pub fn step(&mut self) {
+    pub fn step(&self) {
         if ! self.paused.load(std::sync::atomic::Ordering::Relaxed) {
             return;
         }
-        self.movie_list.iter_mut().for_each(|movie| {
+        self.movies().iter().for_each(|movie| {
             movie.step_force();
         });
-        self.force_render = true;
+        self.force_render.store(true, Ordering::Relaxed);
     }
 
-    pub fn pause(&mut self) {
-        let state = self.paused.load(std::sync::atomic::Ordering::Relaxed);
-        self.paused.store(!state, std::sync::atomic::Ordering::Relaxed);
+    /// show the previous frame of every movie, fails with `NotPaused` while playing.
+    /// in sync the movies step back to the frame before the one movie 0 shows.
+    pub fn step_back(&self) -> Result<(), MovieError> {
+        self.queue_step_back(None).map(|_| ())
+    }
 
-        self.movie_list.iter_mut().for_each(|movie| {
-            movie.pause();
+    /// `step_back`, every movie reports `report` back once it stepped.
+    /// returns how many movies were asked to step back.
+    fn queue_step_back(&self, report: Option<&Command>) -> Result<usize, MovieError> {
+        if !self.is_paused() {
+            return Err(MovieError::NotPaused);
+        }
+        let reference = self.movie(0).and_then(|movie| {
+            let pts = movie.displayed_pts()?;
+            let rate = *movie.video_frame_rate.lock().unwrap();
+            let frame_duration = rate.den as f64 / rate.num.max(1) as f64;
+            Some(movie.pts_to_position(pts) - movie.time_offset() - frame_duration / 2.)
         });
+        let mut queued = 0;
+        self.movies().iter().for_each(|movie| {
+            let before_pts = match (self.is_synchronized(), reference) {
+                (true, Some(position)) => movie.position_to_pts(position + movie.time_offset()) + 1,
+                _ => match movie.displayed_pts() {
+                    Some(pts) => pts,
+                    None => return,
+                },
+            };
+            if movie.step_back(before_pts, report.cloned()).is_ok() {
+                queued += 1;
+            }
+        });
+        self.sync_state.lock().unwrap().reset();
+        self.force_render.store(true, Ordering::Relaxed);
+        Ok(queued)
     }
 
-    pub fn is_paused(&self) -> bool{
-        self.paused.load(std::sync::atomic::Ordering::Relaxed)
+    /// play every movie backwards from the frame it shows, or forwards again.
+    /// backwards the movies hold their first frame once they get there.
+    pub fn set_reverse(&self, reverse: bool) {
+        self.queue_reverse(reverse, None);
     }
 
-    pub fn close(mut this: Self) {
-        info!("closing analyzer...");
-        this.thread_handle.unwrap().join().unwrap();
-        // if let Some(handle) = this.thread_handle.as_ref() {
-        //     handle.join().unwrap();
-        // }
-        while let Some(movie) = this.movie_list.pop() {
-            drop(movie);
+    /// `set_reverse`, every movie that turns around reports `report` back once it did.
+    /// returns how many movies turned around.
+    fn queue_reverse(&self, reverse: bool, report: Option<&Command>) -> usize {
+        let mut queued = 0;
+        self.movies().iter().for_each(|movie| {
+            if movie.is_playing_backwards() == reverse {
+                return;
+            }
+            let from_pts = movie.displayed_pts().unwrap_or(movie.position_to_pts(0.));
+            match reverse {
+                true => movie.play_backwards(from_pts, report.cloned()),
+                false => movie.play_forwards(from_pts, report.cloned()),
+            }
+            queued += 1;
+        });
+        self.sync_state.lock().unwrap().reset();
+        self.metrics.lock().unwrap().reset();
+        self.force_render.store(true, Ordering::Relaxed);
+        queued
+    }
+
+    pub fn is_reversed(&self) -> bool {
+        self.movie(0).map_or(false, |movie| movie.is_playing_backwards())
+    }
+
+    /// jump every movie to `position` seconds from its start, plus its time offset
+    pub fn seek(&self, position: f64, mode: SeekMode) {
+        self.queue_seek(position, mode, None);
+    }
+
+    /// `seek`, every movie reports `report` back once it seeked.
+    /// returns how many movies were asked to seek.
+    fn queue_seek(&self, position: f64, mode: SeekMode, report: Option<&Command>) -> usize {
+        let paused = self.is_paused();
+        let movies = self.movies();
+        movies.iter().for_each(|movie| {
+            let pts = movie.position_to_pts((position + movie.time_offset()).max(0.));
+            movie.seek_pts(pts, mode, report.cloned());
+            // show the frame we land on even while paused
+            if paused {
+                movie.step_force();
+            }
+        });
+        {
+            let mut clock = self.clock.lock().unwrap();
+            clock.pts = AV_NOPTS_VALUE;
+            clock.last_updated = 0;
         }
+        self.sync_state.lock().unwrap().reset();
+        self.metrics.lock().unwrap().reset();
+        self.force_render.store(true, Ordering::Relaxed);
+        movies.len()
     }
 
-    pub fn movie_list_iter(&self) -> Iter<MovieState> {
-        return self.movie_list.iter();
+    /// play at `speed` times the normal rate, clamped to `MIN_SPEED`..`MAX_SPEED`.
+    /// every movie picks up from the frame it is showing, audio keeps its pitch.
+    pub fn set_speed(&self, speed: f32) -> Result<f32, MovieError> {
+        if !(speed > 0.) || !speed.is_finite() {
+            return Err(MovieError::InvalidSpeed);
+        }
+        let speed = speed.clamp(MIN_SPEED, MAX_SPEED);
+        self.clock.lock().unwrap().speed = speed;
+        self.movies().iter().for_each(|movie| movie.set_speed(speed));
+        Ok(speed)
     }
 
-    pub fn set_thread_handle(&mut self, handle: JoinHandle<()>) {
-        self.thread_handle = Some(handle);
+    pub fn speed(&self) -> f32 {
+        self.clock.lock().unwrap().speed
+    }
+
+    pub fn pause(&self) {
+        self.set_paused(!self.is_paused());
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+
+        self.movies().iter().for_each(|movie| {
+            movie.set_paused(paused);
+        });
+    }
+
+    /// true once after something asked for the current frame to be redrawn
+    pub fn force_render(&self) -> bool {
+        self.force_render.load(Ordering::Relaxed)
+    }
+
+    pub fn clear_force_render(&self) {
+        self.force_render.store(false, Ordering::Relaxed);
+    }
+
+    /// run a control command. called from the control thread.
+    pub fn apply_command(&self, command: &Command) -> AnalyzerEvent {
+        // movies that carry the command out on their packet thread and report back
+        let mut queued = 0;
+        match command {
+            Command::Pause => self.set_paused(true),
+            Command::Resume => self.set_paused(false),
+            Command::TogglePause => self.pause(),
+            Command::Step => self.step(),
+            Command::StepBack => match self.queue_step_back(Some(command)) {
+                Ok(movies) => queued = movies,
+                Err(e) => return AnalyzerEvent::Failed { command: command.clone(), reason: e.to_string() },
+            },
+            Command::SetReverse(reverse) => queued = self.queue_reverse(*reverse, Some(command)),
+            Command::Seek { position, mode } => queued = self.queue_seek(*position, *mode, Some(command)),
+            Command::SetSpeed(speed) => {
+                if let Err(e) = self.set_speed(*speed) {
+                    return AnalyzerEvent::Failed { command: command.clone(), reason: e.to_string() };
+                }
+            },
+            Command::SoloAudio(movie_index) => self.solo_audio(*movie_index),
+            Command::SelectVideoStream { movie_index, stream_index } => {
+                let result = match self.movie(*movie_index) {
+                    // selecting the stream already decoded leaves nothing to carry out
+                    Some(movie) if movie.video_stream_index() == *stream_index as i64 => Ok(()),
+                    Some(movie) => {
+                        queued = 1;
+                        movie.select_video_stream(*stream_index, Some(command.clone())).map_err(|e| e.to_string())
+                    },
+                    None => Err(format!("no movie at index {}", movie_index)),
+                };
+                if let Err(reason) = result {
+                    return AnalyzerEvent::Failed { command: command.clone(), reason };
+                }
+            },
+            Command::SelectSubtitleStream { movie_index, stream_index } => {
+                let result = match self.movie(*movie_index) {
+                    Some(movie) => movie.select_subtitle_stream(*stream_index).map_err(|e| e.to_string()),
+                    None => Err(format!("no movie at index {}", movie_index)),
+                };
+                if let Err(reason) = result {
+                    return AnalyzerEvent::Failed { command: command.clone(), reason };
+                }
+            },
+            Command::SetSynchronized(synchronized) => self.set_synchronized(*synchronized),
+            Command::SetMetrics(enabled) => self.set_metrics_enabled(*enabled),
+            Command::SetTimeOffset { movie_index, offset } => {
+                if let Err(e) = self.set_time_offset(*movie_index, *offset) {
+                    return AnalyzerEvent::Failed { command: command.clone(), reason: e.to_string() };
+                }
+            },
+            Command::SetEndBehavior { movie_index, behavior } => match self.movie(*movie_index) {
+                Some(movie) => movie.set_end_behavior(*behavior),
+                None => return AnalyzerEvent::Failed {
+                    command: command.clone(),
+                    reason: format!("no movie at index {}", movie_index),
+                },
+            },
+            Command::SetFilter { movie_index, description } => {
+                let result = match self.movie(*movie_index) {
+                    Some(movie) => movie.set_filter_description(description.as_deref()).map_err(|e| e.to_string()),
+                    None => Err(format!("no movie at index {}", movie_index)),
+                };
+                if let Err(reason) = result {
+                    return AnalyzerEvent::Failed { command: command.clone(), reason };
+                }
+            },
+            Command::SetOrientation { movie_index, orientation } => match self.movie(*movie_index) {
+                Some(movie) => movie.set_orientation(*orientation),
+                None => {
+                    let reason = format!("no movie at index {}", movie_index);
+                    return AnalyzerEvent::Failed { command: command.clone(), reason };
+                },
+            },
+            Command::Quit => {},
+        }
+        match queued {
+            0 => AnalyzerEvent::Applied(command.clone()),
+            _ => AnalyzerEvent::Queued(command.clone()),
+        }
+    }
+
+    pub fn set_event_sender(&self, events: Sender<AnalyzerEvent>) {
+        *self.events.lock().unwrap() = Some(events);
+    }
+
+    /// notify whoever holds the `ControlChannel`
+    pub fn emit(&self, event: AnalyzerEvent) {
+        if let Some(events) = self.events.lock().unwrap().as_ref() {
+            let _ = events.send(event);
+        }
+    }
+
+    pub fn is_paused(&self) -> bool{
+        self.paused.load(std::sync::atomic::Ordering::Relaxed)
     }
 }
 
@@ -1,24 +1,41 @@
 use std::ffi::CStr;
 use std::ffi::CString;
+use std::io::Cursor;
+use std::io::Read;
+use std::io::Seek;
 use std::ptr;
-use std::ptr::NonNull;
-use std::sync::mpsc::Sender;
 use std::sync::Arc;
 use std::sync::Mutex;
-use ::std::thread::JoinHandle;
+use std::sync::mpsc::Sender;
 use log::debug;
+use log::error;
 use log::info;
 use rusty_ffmpeg::ffi;
 
 
 use crate::analyzer_state::AnalyzerContext;
+use crate::audio::audio_decode_thread;
+use crate::command::AnalyzerEvent;
+use crate::command::Command;
+use crate::command::ControlChannel;
+use crate::custom_io::CustomIo;
 use crate::decode_thread::decode_thread;
+use crate::error::AvError;
+use crate::error::MovieError;
+use crate::error::MovieStatus;
+use crate::frame_info::FrameInfo;
+use crate::movie_state::movie_state_enqueue_audio_packet;
 use crate::movie_state::movie_state_enqueue_packet;
+use crate::movie_state::open_video_codec;
+use crate::movie_state::EndBehavior;
 use crate::movie_state::CodecContextWrapper;
 use crate::movie_state::MovieState;
+use crate::movie_state::PlaybackRequest;
+use crate::open_options::Dictionary;
+use crate::open_options::OpenOptions;
+use crate::open_options::OpenReport;
+use crate::open_options::StreamSelection;
 
-static mut DECODE_THREADS: Vec<Box<JoinHandle<()>>> = vec![];
-static mut PACKET_THREADS: Vec<Box<JoinHandle<()>>> = vec![];
 
 // #[cfg_attr(target_os="linux", path="platform/sdl.rs")]
 // mod platform;
@@ -32,6 +49,7 @@ pub unsafe extern "C" fn drop_movie_state(movie_state: *mut MovieState) {
     drop(Box::<MovieState>::from_raw(movie_state));
 }
 
+#[no_mangle]
 pub unsafe extern "C" fn drop_analyzer_state(analyzer_ctx: *mut AnalyzerContext) {
     let a = Box::<AnalyzerContext>::from_raw(analyzer_ctx);
     // AnalyzerContext::close(a.deref().deref());
@@ -44,25 +62,106 @@ pub unsafe extern "C" fn drop_analyzer_state(analyzer_ctx: *mut AnalyzerContext)
 pub unsafe extern "C" fn a_function_from_rust() -> i32 {
     42
 }
-#[no_mangle]
-#[allow(improper_ctypes_definitions)]
-pub unsafe extern "C" fn open_movie(analyzer_context: &mut AnalyzerContext, filepath: *const libc::c_char) {
+/// open a movie file and attach it to the analyzer.
+/// streams without a decoder (data, timecode tracks) are skipped.
+/// the first decodable video stream is shown, see `open_movie_with_options`.
+pub unsafe fn open_movie(analyzer_context: &mut AnalyzerContext, filepath: *const libc::c_char) -> Result<(), MovieError> {
+    open_movie_with_options(analyzer_context, filepath, &OpenOptions::default()).map(|_| ())
+}
+
+/// open a movie file and attach it to the analyzer, decoding the video stream
+/// picked by `options.video_stream`. the report lists the options ffmpeg did not use.
+/// the movie starts playing right away when the analyzer is running.
+pub unsafe fn open_movie_with_options(
+    analyzer_context: &mut AnalyzerContext,
+    filepath: *const libc::c_char,
+    options: &OpenOptions,
+) -> Result<OpenReport, MovieError> {
+    let (movie, report) = load_movie(filepath, options)?;
+    analyzer_context.add_movie(movie);
+    Ok(report)
+}
+
+/// open a movie file without attaching it to an analyzer, for
+/// `AnalyzerContext::add_movie` or `AnalyzerContext::replace_movie`
+pub unsafe fn load_movie(
+    filepath: *const libc::c_char,
+    options: &OpenOptions,
+) -> Result<(MovieState, OpenReport), MovieError> {
     // let filepath: CString = CString::new(src).unwrap();
     let mut format_ctx = ffi::avformat_alloc_context();
 
     let format     = ptr::null_mut();
-    let dict       = ptr::null_mut();
-    if {
-        ffi::avformat_open_input(&mut format_ctx, filepath, format, dict)
-    } != 0 {
-        panic!("🚩 cannot open file")
+    let mut dict   = Dictionary::from_pairs(&options.format_options);
+    let ret = ffi::avformat_open_input(&mut format_ctx, filepath, format, &mut dict.ptr);
+    if ret != 0 {
+        // avformat_open_input frees the context on failure
+        return Err(MovieError::OpenInput(AvError::from_code(ret)));
     }
+    prepare_movie(MovieState::new(), format_ctx, filepath, options, dict.keys())
+}
+
+/// open a movie from any seekable rust reader and attach it to the analyzer.
+/// the reader is moved into the movie state and dropped with it.
+pub unsafe fn open_movie_from_reader<R: Read + Seek + Send + 'static>(
+    analyzer_context: &mut AnalyzerContext,
+    reader: R,
+    options: &OpenOptions,
+) -> Result<OpenReport, MovieError> {
+    let (movie, report) = load_movie_from_reader(reader, options)?;
+    analyzer_context.add_movie(movie);
+    Ok(report)
+}
 
-    if ffi::avformat_find_stream_info(format_ctx, ptr::null_mut()) < 0 {
-        panic!("ERROR could not get the stream info");
+/// open a movie from a seekable rust reader without attaching it to an analyzer
+pub unsafe fn load_movie_from_reader<R: Read + Seek + Send + 'static>(
+    reader: R,
+    options: &OpenOptions,
+) -> Result<(MovieState, OpenReport), MovieError> {
+    let custom_io = CustomIo::new(Box::new(reader)).map_err(MovieError::OpenInput)?;
+    let mut format_ctx = ffi::avformat_alloc_context();
+    (*format_ctx).pb = custom_io.ptr;
+    (*format_ctx).flags |= ffi::AVFMT_FLAG_CUSTOM_IO as i32;
+
+    let mut dict = Dictionary::from_pairs(&options.format_options);
+    let ret = ffi::avformat_open_input(&mut format_ctx, ptr::null(), ptr::null_mut(), &mut dict.ptr);
+    if ret != 0 {
+        // the format context is gone, custom_io frees the avio context on drop
+        return Err(MovieError::OpenInput(AvError::from_code(ret)));
     }
     let mut video_state = MovieState::new();
+    video_state.custom_io = Some(custom_io);
+    let name = CString::new("memory").unwrap();
+    prepare_movie(video_state, format_ctx, name.as_ptr(), options, dict.keys())
+}
+
+/// open a movie held in memory and attach it to the analyzer.
+/// the bytes are copied, so the slice does not have to outlive the movie.
+pub unsafe fn open_movie_from_bytes(
+    analyzer_context: &mut AnalyzerContext,
+    data: &[u8],
+    options: &OpenOptions,
+) -> Result<OpenReport, MovieError> {
+    open_movie_from_reader(analyzer_context, Cursor::new(data.to_vec()), options)
+}
+
+/// probe the streams of an opened input and set up the decoders.
+/// `video_state` takes ownership of `format_ctx`.
+unsafe fn prepare_movie(
+    mut video_state: MovieState,
+    format_ctx: *mut ffi::AVFormatContext,
+    filepath: *const libc::c_char,
+    options: &OpenOptions,
+    unused_format_options: Vec<String>,
+) -> Result<(MovieState, OpenReport), MovieError> {
+    // from here on the movie state owns the format context and frees it on drop
     video_state.set_format_context(format_ctx.as_mut().unwrap());
+    video_state.set_end_behavior(options.end_behavior);
+
+    let ret = ffi::avformat_find_stream_info(format_ctx, ptr::null_mut());
+    if ret < 0 {
+        return Err(MovieError::StreamInfo(AvError::from_code(ret)));
+    }
 
     ffi::av_dump_format(video_state.format_context.lock().unwrap().ptr, 0, filepath, 0);
 
@@ -70,9 +169,8 @@ pub unsafe extern "C" fn open_movie(analyzer_context: &mut AnalyzerContext, file
         let format_ctx = video_state.format_context.lock().unwrap();
         std::slice::from_raw_parts(format_ctx.as_ref().unwrap().streams, format_ctx.as_ref().unwrap().nb_streams as usize)
     };
-    let mut codec_ptr: *const ffi::AVCodec = ptr::null_mut();
-    let mut codec_parameters_ptr: *const ffi::AVCodecParameters = ptr::null_mut();
-    let mut video_stream_index = None;
+    let video_stream_index = find_video_stream(format_ctx, streams, options.video_stream)?;
+    info!("📽  decoding video stream {}", video_stream_index);
     let mut time_base_den:i32 = 10000;
     let mut time_base_num:i32 = 10000;
 
@@ -87,36 +185,39 @@ pub unsafe extern "C" fn open_movie(analyzer_context: &mut AnalyzerContext, file
             (*stream).time_base.num, (*stream).time_base.den
         );
 
-        let local_codec_params = (*stream).codecpar.as_ref()
-            .expect("ERROR: unable to dereference codec parameters");
-        let local_codec = ffi::avcodec_find_decoder(local_codec_params.codec_id).as_ref()
-            .expect("ERROR unsupported codec!");
+        let local_codec_params = match (*stream).codecpar.as_ref() {
+            Some(params) => params,
+            None => {
+                info!("stream {} has no codec parameters, skipping", i);
+                continue;
+            }
+        };
+        let local_codec = match ffi::avcodec_find_decoder(local_codec_params.codec_id).as_ref() {
+            Some(codec) => codec,
+            None => {
+                info!("stream {} has no decoder for codec id {}, skipping", i, local_codec_params.codec_id);
+                continue;
+            }
+        };
 
         match local_codec_params.codec_type {
             ffi::AVMediaType_AVMEDIA_TYPE_VIDEO => {
 
-                if video_stream_index.is_none() {
-
-                    video_stream_index = Some(i);
+                if i == video_stream_index {
                     video_state.video_stream.lock().unwrap().ptr = stream;
-                    video_state.video_stream_idx = i as i64;
-                    video_state.video_ctx = Mutex::new(
-                        CodecContextWrapper{ptr: ffi::avcodec_alloc_context3(local_codec)}
-                    ); //.as_mut().unwrap();
-                    codec_ptr = local_codec;
-                    codec_parameters_ptr = local_codec_params;
+                    video_state.video_stream_idx.store(i as i64, std::sync::atomic::Ordering::Relaxed);
                     time_base_den = (*stream).time_base.den;
                     time_base_num = (*stream).time_base.num;
-                    video_state.video_frame_rate = ffi::av_guess_frame_rate(
+                    *video_state.video_frame_rate.lock().unwrap() = ffi::av_guess_frame_rate(
                         format_ctx.as_mut().unwrap(),
-                        video_state.video_stream.lock().unwrap().ptr,
+                        stream,
                         ::std::ptr::null_mut(),
                     );
                 }
 
                 println!(
-                    "Video Codec: resolution {} x {}",
-                    local_codec_params.width, local_codec_params.height
+                    "Video Codec: stream {} resolution {} x {}",
+                    i, local_codec_params.width, local_codec_params.height
                 );
                 unsafe {
                     println!(
@@ -129,16 +230,61 @@ pub unsafe extern "C" fn open_movie(analyzer_context: &mut AnalyzerContext, file
                     );
                 }
             },
+            ffi::AVMediaType_AVMEDIA_TYPE_AUDIO => {
+                if video_state.audio_stream_idx < 0 {
+                    match open_audio_codec(stream, local_codec) {
+                        Ok(audio_ctx) => {
+                            video_state.audio_stream_idx = i as i64;
+                            video_state.audio_stream.lock().unwrap().ptr = stream;
+                            video_state.audio_ctx = Mutex::new(CodecContextWrapper{ptr: audio_ctx});
+                            println!(
+                                "Audio Codec: {} Hz, {} channels",
+                                local_codec_params.sample_rate, local_codec_params.ch_layout.nb_channels
+                            );
+                        },
+                        Err(e) => info!("🔊 skipping audio stream {}: {}", i, e),
+                    }
+                }
+            },
             _ => {}
         }
     }
-    if unsafe { ffi::avcodec_parameters_to_context((video_state.video_ctx.lock().unwrap()).ptr, codec_parameters_ptr) } < 0 {
-        panic!("failed to copy codec params to codec context");
+    let orientation = video_state.stream_orientation();
+    if !orientation.is_upright() {
+        info!("📽  stream {} is shown turned {}° clockwise{}", video_stream_index, orientation.rotation, if orientation.flip { " and mirrored" } else { "" });
+    }
+    let (video_ctx, unused_decoder_options) = open_video_codec(streams[video_stream_index], &options.decoder)?;
+    video_state.video_ctx = Mutex::new(CodecContextWrapper{ptr: video_ctx});
+    *video_state.decoder_options.lock().unwrap() = options.decoder.clone();
+    let report = OpenReport {
+        video_stream: video_stream_index,
+        decoder: CStr::from_ptr((*(*video_ctx).codec).name).to_string_lossy().into_owned(),
+        unused_format_options,
+        unused_decoder_options,
+    };
+    info!("📽  video decoder {} with {} threads", report.decoder, (*video_ctx).thread_count);
+    if !report.unused_format_options.is_empty() {
+        info!("📽  demuxer options not used: {:?}", report.unused_format_options);
+    }
+    if !report.unused_decoder_options.is_empty() {
+        info!("📽  decoder options not used: {:?}", report.unused_decoder_options);
+    }
+
+    // a movie without its subtitles still plays
+    if let Some(selection) = options.subtitle_stream {
+        match find_subtitle_stream(format_ctx, streams, selection, video_stream_index) {
+            Some(index) => match video_state.select_subtitle_stream(Some(index)) {
+                Ok(_) => info!("📽  decoding subtitle stream {}", index),
+                Err(e) => error!("🚩 {}", e),
+            },
+            None => info!("📽  no subtitle stream for {:?}", selection),
+        }
     }
-    if ffi::avcodec_open2((video_state.video_ctx.lock().unwrap()).ptr, codec_ptr, ptr::null_mut()) < 0 {
-        panic!("failed to open codec through avcodec_open2");
+    if let Some(path) = &options.external_subtitles {
+        if let Err(e) = video_state.load_external_subtitles(path) {
+            error!("🚩 cannot read subtitles from {}: {}", path.display(), e);
+        }
     }
-    analyzer_context.add_movie_state(video_state);
 
     // let format_ctx = video_state.format_context.lock().unwrap();
     let mut dur_s = format_ctx.as_ref().unwrap().duration / 10000;
@@ -153,199 +299,357 @@ pub unsafe extern "C" fn open_movie(analyzer_context: &mut AnalyzerContext, file
         "format {}, duration {:0>3}:{:0>2}, time_base {}/{}",
         format_name, dur_min, dur_s / 100 , time_base_num, time_base_den
     );
+    Ok((video_state, report))
 }
 
-#[repr(C)]
-struct Storage<'m> {
-    // ptr: *mut ffi::AVFormatContext,
-    ptr: &'m MovieState
+/// the container index of the video stream to decode
+unsafe fn find_video_stream(
+    format_ctx: *mut ffi::AVFormatContext,
+    streams: &[*mut ffi::AVStream],
+    selection: StreamSelection,
+) -> Result<usize, MovieError> {
+    let is_decodable_video = |stream: *mut ffi::AVStream| match (*stream).codecpar.as_ref() {
+        Some(params) => params.codec_type == ffi::AVMediaType_AVMEDIA_TYPE_VIDEO
+            && !ffi::avcodec_find_decoder(params.codec_id).is_null(),
+        None => false,
+    };
+    match selection {
+        StreamSelection::First => streams
+            .iter()
+            .position(|stream| is_decodable_video(*stream))
+            .ok_or(MovieError::NoVideoStream),
+        StreamSelection::Index(index) => match streams.get(index) {
+            Some(stream) if is_decodable_video(*stream) => Ok(index),
+            _ => Err(MovieError::InvalidVideoStream(index)),
+        },
+        StreamSelection::Best => {
+            // asking for the decoder leaves out streams nothing can decode
+            let mut decoder: *const ffi::AVCodec = ptr::null();
+            let ret = ffi::av_find_best_stream(
+                format_ctx,
+                ffi::AVMediaType_AVMEDIA_TYPE_VIDEO,
+                -1,
+                -1,
+                &mut decoder,
+                0,
+            );
+            if ret < 0 {
+                return Err(MovieError::NoVideoStream);
+            }
+            match streams.get(ret as usize) {
+                Some(stream) if is_decodable_video(*stream) => Ok(ret as usize),
+                _ => Err(MovieError::NoVideoStream),
+            }
+        }
+    }
 }
-unsafe impl Send for Storage<'_>{}
 
-/// play all movies attached to the analyzer
-/// this is unsafe because it mutates a single static vec without a mutex
-/// this should only be called from the calling app's main UI thread
-#[no_mangle]
-#[allow(improper_ctypes_definitions)]
-pub unsafe extern "C" fn start_analyzer(analyzer_ptr: *mut AnalyzerContext) -> Sender<String> {
-    let analyzer_ctx = analyzer_ptr.as_mut().unwrap();
+/// the container index of the subtitle stream to decode, if there is one
+unsafe fn find_subtitle_stream(
+    format_ctx: *mut ffi::AVFormatContext,
+    streams: &[*mut ffi::AVStream],
+    selection: StreamSelection,
+    video_stream_index: usize,
+) -> Option<usize> {
+    let is_decodable_subtitle = |stream: *mut ffi::AVStream| match (*stream).codecpar.as_ref() {
+        Some(params) => params.codec_type == ffi::AVMediaType_AVMEDIA_TYPE_SUBTITLE
+            && !ffi::avcodec_find_decoder(params.codec_id).is_null(),
+        None => false,
+    };
+    match selection {
+        StreamSelection::First => streams.iter().position(|stream| is_decodable_subtitle(*stream)),
+        StreamSelection::Index(index) => streams.get(index).filter(|s| is_decodable_subtitle(**s)).map(|_| index),
+        StreamSelection::Best => {
+            let ret = ffi::av_find_best_stream(
+                format_ctx,
+                ffi::AVMediaType_AVMEDIA_TYPE_SUBTITLE,
+                -1,
+                video_stream_index as i32,
+                ptr::null_mut(),
+                0,
+            );
+            (ret >= 0).then(|| ret as usize)
+        }
+    }
+}
 
-    let (tx, rx) = std::sync::mpsc::channel::<String>();
-    let keep_running = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(true));
+unsafe fn open_audio_codec(stream: *mut ffi::AVStream, codec: *const ffi::AVCodec) -> Result<*mut ffi::AVCodecContext, AvError> {
+    let mut audio_ctx = ffi::avcodec_alloc_context3(codec);
+    let ret = ffi::avcodec_parameters_to_context(audio_ctx, (*stream).codecpar);
+    if ret < 0 {
+        ffi::avcodec_free_context(&mut audio_ctx);
+        return Err(AvError::from_code(ret));
+    }
+    (*audio_ctx).pkt_timebase = (*stream).time_base;
+    let ret = ffi::avcodec_open2(audio_ctx, codec, ptr::null_mut());
+    if ret < 0 {
+        ffi::avcodec_free_context(&mut audio_ctx);
+        return Err(AvError::from_code(ret));
+    }
+    Ok(audio_ctx)
+}
 
-    for movie_state in analyzer_ctx.movie_list.iter_mut() {
-        let movie_state_arc  = std::sync::Arc::new(movie_state);
-        let movie_state1     = std::sync::Arc::clone(&movie_state_arc);
+/// C entry point for `open_movie`, returns a `MovieStatus` code instead of a `Result`
+#[no_mangle]
+pub unsafe extern "C" fn open_movie_status(analyzer_context: *mut AnalyzerContext, filepath: *const libc::c_char) -> MovieStatus {
+    let analyzer_context = match analyzer_context.as_mut() {
+        Some(ctx) => ctx,
+        None => return MovieStatus::InvalidArgument,
+    };
+    if filepath.is_null() {
+        return MovieStatus::InvalidArgument;
+    }
+    let result = open_movie(analyzer_context, filepath);
+    if let Err(e) = &result {
+        error!("🚩 {}", e);
+    }
+    MovieStatus::from(&result)
+}
 
-        let keep_running2  = std::sync::Arc::clone(&keep_running);
-        PACKET_THREADS.push(
-            Box::new(std::thread::spawn(move || packet_thread_spawner(
-                std::sync::Arc::clone(&keep_running2),
-                movie_state1.video_stream_idx,
-                movie_state1,
-            )))
-        );
+/// play all movies attached to the analyzer, movies added later with
+/// `AnalyzerContext::add_movie` get their threads right away.
+/// the control thread shares the analyzer's state until `Quit`.
+/// this is unsafe because it dereferences `analyzer_ptr`
+/// this should only be called from the calling app's main UI thread
+/// C callers use `rsp_start` instead
+pub unsafe fn start_analyzer(analyzer_ptr: *mut AnalyzerContext) -> ControlChannel {
+    let analyzer_ctx = analyzer_ptr.as_mut().unwrap();
 
-        let keep_running3  = std::sync::Arc::clone(&keep_running);
-        let movie_state2   = std::sync::Arc::clone(&movie_state_arc);
-        DECODE_THREADS.push(
-            Box::new(std::thread::spawn(move || {
-                decode_thread(movie_state2, keep_running3)
-            }))
-        );
+    let (tx, rx) = std::sync::mpsc::channel::<Command>();
+    let (event_tx, event_rx) = std::sync::mpsc::channel::<AnalyzerEvent>();
+
+    for (movie_index, movie_state) in analyzer_ctx.movies().iter().enumerate() {
+        movie_state.movie_index.store(movie_index, std::sync::atomic::Ordering::Relaxed);
+        start_movie_threads(movie_state, event_tx.clone());
     }
 
+    analyzer_ctx.set_event_sender(event_tx.clone());
+    let analyzer = analyzer_ctx.state();
     let decoder_handle = std::thread::spawn(move || {
-        // when all tx refs are dropped, this rx will close
-        for msg in rx {
-            debug!("🦀🦀 received message: {}", msg);
-            if msg == "quit" {
+        // runs until `Quit`, which `AnalyzerContext::join` sends if the caller did not
+        for command in rx {
+            debug!("🦀🦀 received command: {:?}", command);
+            if command == Command::Quit {
                break;
             }
+            let event = analyzer.apply_command(&command);
+            let _ = event_tx.send(event);
         }
         info!("🦀🦀 done");
-        keep_running.store(false, std::sync::atomic::Ordering::Relaxed);
-
-        while DECODE_THREADS.len() > 0 {
-            let cur_thread = DECODE_THREADS.remove(0);
-            let _ = cur_thread.join();
-        }
-        while PACKET_THREADS.len() > 0 {
-            let cur_thread = PACKET_THREADS.remove(0);
-            cur_thread.join().unwrap();
-        }
+        analyzer.stop_movie_threads();
+        let _ = event_tx.send(AnalyzerEvent::Stopped);
     });
-    let analyzer_ctx = analyzer_ptr.as_mut().unwrap();
-    analyzer_ctx.set_thread_handle(decoder_handle);
-    tx
+    analyzer_ctx.set_control_thread(decoder_handle, tx.clone());
+    ControlChannel { commands: tx, events: event_rx }
 }
 
-
-/// play a single movie statej
-/// this is unsafe because it mutates a single static vec without a mutex
-/// this should only be called from the calling app's main UI thread
-#[no_mangle]
-#[allow(improper_ctypes_definitions)]
-pub unsafe extern "C" fn play_movie(movie_state: *mut MovieState) -> Sender<String> {
-
-    let (tx, rx) = std::sync::mpsc::channel::<String>();
-    let keep_running = std::sync::Arc::new(std::sync::atomic::AtomicBool::new(true));
-    let movie_state_arc    = std::sync::Arc::new(movie_state.as_mut().unwrap());
-    let movie_state1   = std::sync::Arc::clone(&movie_state_arc);
+/// start the packet, decode and audio threads of a movie. each holds on to
+/// the movie until `MovieState::stop_threads` makes it return.
+pub(crate) unsafe fn start_movie_threads(movie_state_arc: &Arc<MovieState>, events: Sender<AnalyzerEvent>) {
+    movie_state_arc.keep_running.store(true, std::sync::atomic::Ordering::Relaxed);
+    let keep_running = std::sync::Arc::clone(&movie_state_arc.keep_running);
+    let mut threads = vec![];
 
     let keep_running2  = std::sync::Arc::clone(&keep_running);
+    let movie_state1   = std::sync::Arc::clone(movie_state_arc);
+    threads.push(std::thread::spawn(move || packet_thread_spawner(keep_running2, movie_state1, events)));
 
-    PACKET_THREADS.push(
-        Box::new(std::thread::spawn(move || packet_thread_spawner(
-            std::sync::Arc::clone(&keep_running2),
-            movie_state1.video_stream_idx,
-            movie_state1,
-        )))
-    );
+    let keep_running3  = std::sync::Arc::clone(&keep_running);
+    let movie_state2   = std::sync::Arc::clone(movie_state_arc);
+    threads.push(std::thread::spawn(move || decode_thread(movie_state2, keep_running3)));
 
+    if movie_state_arc.has_audio() {
+        let keep_running4  = std::sync::Arc::clone(&keep_running);
+        let movie_state3   = std::sync::Arc::clone(movie_state_arc);
+        threads.push(std::thread::spawn(move || audio_decode_thread(movie_state3, keep_running4)));
+    }
+    movie_state_arc.threads.lock().unwrap().extend(threads);
+}
 
-    let keep_running3  = std::sync::Arc::clone(&keep_running);
-    let movie_state2   = std::sync::Arc::clone(&movie_state_arc);
-    DECODE_THREADS.push(
-        Box::new(std::thread::spawn(move || {
-            decode_thread(movie_state2, keep_running3)
-        }))
-    );
+/// play a single movie statej
+/// its threads are kept in the movie and joined when `Command::Quit` arrives
+pub unsafe fn play_movie(movie_state_arc: Arc<MovieState>) -> ControlChannel {
 
+    let (tx, rx) = std::sync::mpsc::channel::<Command>();
+    let (event_tx, event_rx) = std::sync::mpsc::channel::<AnalyzerEvent>();
+    start_movie_threads(&movie_state_arc, event_tx.clone());
 
     std::thread::spawn(move || {
+        let movie = movie_state_arc;
         // when all tx refs are dropped, this rx will close
-        for msg in rx {
-            debug!("🦀🦀 received message: {}", msg);
-            if msg == "quit" {
-               break;
-            }
+        for command in rx {
+            debug!("🦀🦀 received command: {:?}", command);
+            let event = match command {
+                Command::Quit => break,
+                Command::Pause => { movie.set_paused(true); AnalyzerEvent::Applied(command) },
+                Command::Resume => { movie.set_paused(false); AnalyzerEvent::Applied(command) },
+                Command::TogglePause => { movie.pause(); AnalyzerEvent::Applied(command) },
+                Command::Step => { movie.step(); AnalyzerEvent::Applied(command) },
+                Command::Seek { position, mode } => {
+                    movie.seek_pts(movie.position_to_pts(position), mode, Some(command.clone()));
+                    AnalyzerEvent::Queued(command)
+                },
+                Command::SetEndBehavior { behavior, .. } => { movie.set_end_behavior(behavior); AnalyzerEvent::Applied(command) },
+                Command::SelectVideoStream { stream_index, .. } if movie.video_stream_index() == stream_index as i64 => {
+                    AnalyzerEvent::Applied(command)
+                },
+                Command::SelectVideoStream { stream_index, .. } => match movie.select_video_stream(stream_index, Some(command.clone())) {
+                    Ok(_) => AnalyzerEvent::Queued(command),
+                    Err(e) => AnalyzerEvent::Failed { command, reason: e.to_string() },
+                },
+                Command::SelectSubtitleStream { stream_index, .. } => match movie.select_subtitle_stream(stream_index) {
+                    Ok(_) => AnalyzerEvent::Applied(command),
+                    Err(e) => AnalyzerEvent::Failed { command, reason: e.to_string() },
+                },
+                Command::SetFilter { ref description, .. } => match movie.set_filter_description(description.as_deref()) {
+                    Ok(_) => AnalyzerEvent::Applied(command),
+                    Err(e) => AnalyzerEvent::Failed { command, reason: e.to_string() },
+                },
+                Command::SetOrientation { orientation, .. } => { movie.set_orientation(orientation); AnalyzerEvent::Applied(command) },
+                _ => AnalyzerEvent::Ignored(command),
+            };
+            let _ = event_tx.send(event);
         }
         info!("🦀🦀 done");
-        keep_running.store(false, std::sync::atomic::Ordering::Relaxed);
-
-        for (index, _) in DECODE_THREADS.iter().enumerate() {
-            let cur_thread = DECODE_THREADS.remove(index);
-            let _ = cur_thread.join();
-        }
-        for (index, _) in PACKET_THREADS.iter().enumerate() {
-            let cur_thread = PACKET_THREADS.remove(index);
-            cur_thread.join().unwrap();
-        }
+        movie.stop_threads();
+        let _ = event_tx.send(AnalyzerEvent::Stopped);
     });
-    tx
+    ControlChannel { commands: tx, events: event_rx }
 }
 
-
-#[allow(dead_code)]
-unsafe fn get_orientation_metadata_value(format_ctx: *mut ffi::AVFormatContext) -> i32 {
-    let key_name = CString::new("rotate").unwrap();
-	let tag: *mut ffi::AVDictionaryEntry = ffi::av_dict_get(
-        (*format_ctx).metadata,
-        key_name.as_ptr() as *const _,
-        std::ptr::null(),
-        0
-    );
-	if !tag.is_null() {
-		return libc::atoi((*tag).value);
-	}
-    info!(" 🔄 got no rotation tag.");
-    // let streams = NonNull::<ffi::AVStream>::new((*format_ctx).streams as *mut _).unwrap();
-    info!(" 🔄 nb_streams ptr is {:?}", (*format_ctx).nb_streams);
-    let mut rotation = 0.;
-    for i in 0..(*format_ctx).nb_streams as usize {
-        unsafe {
-            let mut _ptr = NonNull::new((*format_ctx).streams as *mut _).unwrap();
-            let stream_ptr = ((*format_ctx).streams as *mut *mut ffi::AVStream).add(i);
-            // let s = Box::<ffi::AVStream>::from_raw(*_ptr.as_ptr());
-            let s = Box::<ffi::AVStream>::from_raw(*stream_ptr);
-            info!(" 🔄 streams nb_side_data is {:?}",s.nb_side_data);
-            if !s.side_data.is_null() {
-                let _display_matrix = ffi::av_stream_get_side_data(
-                    Box::into_raw(s) as *const _,
-                    ffi::AVPacketSideDataType_AV_PKT_DATA_DISPLAYMATRIX,
-                    std::ptr::null_mut()
-                );
-                info!(" 🔄 displaymatrix is {:?}", _display_matrix);
-                rotation = -ffi::av_display_rotation_get(_display_matrix as *const i32);
-                info!(" 🔄 rotation is {:?}", rotation);
-            } else {
-                // consume the box
-                let unptr = Box::into_raw(s);
-                std::ptr::drop_in_place(unptr);
-            }
-            return rotation as i32;
-        }
-    }
-    0
+/// tell the caller how a queued command went in this movie, `report` is None
+/// for requests the movie made itself
+fn report_queued(events: &Sender<AnalyzerEvent>, movie_state: &MovieState, report: Option<Command>, result: Result<(), String>) {
+    let command = match report {
+        Some(command) => command,
+        None => return,
+    };
+    let movie_index = movie_state.movie_index.load(std::sync::atomic::Ordering::Relaxed);
+    let event = match result {
+        Ok(()) => AnalyzerEvent::MovieApplied { movie_index, command },
+        Err(reason) => AnalyzerEvent::MovieFailed { movie_index, command, reason },
+    };
+    let _ = events.send(event);
 }
 
 fn packet_thread_spawner(
     keep_running: std::sync::Arc<std::sync::atomic::AtomicBool>,
-    video_stream_idx: i64,
-    movie_state: Arc<&mut MovieState>
+    movie_state: Arc<MovieState>,
+    events: Sender<AnalyzerEvent>,
 ) {
+    // the end of the GOP to decode next while playing backwards
+    let mut reverse_end: Option<i64> = None;
+    let mut at_end = false;
     loop {
         if !keep_running.load(std::sync::atomic::Ordering::Relaxed) {
             break;
         }
         unsafe {
+            if let Some(mut request) = movie_state.take_video_stream_request() {
+                let report = request.report.take();
+                let result = movie_state.execute_video_stream_switch(request).map_err(|e| e.to_string());
+                if let Err(e) = &result {
+                    error!("📽📽  failed to switch video stream: {}", e);
+                }
+                report_queued(&events, &movie_state, report, result);
+                at_end = false;
+                reverse_end = None;
+            }
+            if let Some(mut request) = movie_state.take_seek_request() {
+                debug!("📽📽  seeking to {} ({:?})", request.pts, request.mode);
+                let report = request.report.take();
+                let result = movie_state.execute_seek(request).map_err(|e| e.to_string());
+                if let Err(e) = &result {
+                    error!("📽📽  failed to seek: {}", e);
+                }
+                report_queued(&events, &movie_state, report, result);
+                at_end = false;
+                reverse_end = None;
+            }
+            if let Some(request) = movie_state.take_playback_request() {
+                debug!("📽📽  {:?}", request);
+                match request {
+                    PlaybackRequest::Reverse { from_pts, report } => {
+                        movie_state.start_reverse(from_pts);
+                        reverse_end = Some(from_pts);
+                        report_queued(&events, &movie_state, report, Ok(()));
+                    },
+                    PlaybackRequest::StepBack { before_pts, report } => {
+                        let result = movie_state.execute_step_back(before_pts).map_err(|e| e.to_string());
+                        if let Err(e) = &result {
+                            error!("📽📽  failed to step back: {}", e);
+                        }
+                        report_queued(&events, &movie_state, report, result);
+                        reverse_end = None;
+                    },
+                }
+                at_end = false;
+            }
+            if at_end {
+                ::std::thread::sleep(::std::time::Duration::from_millis(10));
+                continue;
+            }
+            if let Some(end_pts) = reverse_end {
+                reverse_end = play_gop_backwards(&movie_state, end_pts, &keep_running);
+                if reverse_end.is_none() && movie_state.is_playing_backwards() && !movie_state.is_seeking() {
+                    // asked to play backwards, hold the first frame
+                    debug!("📽📽  reached the start");
+                    movie_state.ended.store(true, std::sync::atomic::Ordering::Relaxed);
+                    at_end = true;
+                } else if reverse_end.is_none() && !movie_state.is_seeking() {
+                    debug!("📽📽  reached the start, playing forwards");
+                    if let Err(e) = movie_state.rewind() {
+                        error!("📽📽  failed to rewind: {}", e);
+                    }
+                }
+                continue;
+            }
             let packet = ffi::av_packet_alloc().as_mut()
                 .expect("failed to allocated memory for AVPacket");
             let response = ffi::av_read_frame(movie_state.format_context.lock().unwrap().ptr, packet);
             if response == ffi::AVERROR_EOF {
-                println!("{}", String::from(
-                    "EOF",
-                ));
-                let seek_ret = ffi::av_seek_frame(movie_state.format_context.lock().unwrap().ptr, movie_state.video_stream_idx as i32, 0, ffi::AVSEEK_FLAG_BACKWARD as i32);
-                if seek_ret < 0 {
-                    eprintln!("📽📽  failed to seek backwards: ");
-                    keep_running.store(false, std::sync::atomic::Ordering::Relaxed);
-                    return;
+                ffi::av_packet_free(&mut (packet as *mut _) as *mut *mut _);
+                // the index changes when movies before this one are removed
+                let movie_index = movie_state.movie_index.load(std::sync::atomic::Ordering::Relaxed);
+                info!("📽📽  end of movie {}", movie_index);
+                enqueue_drain_markers(&movie_state, &keep_running);
+                let _ = events.send(AnalyzerEvent::EndOfStream { movie_index });
+
+                match movie_state.end_behavior() {
+                    EndBehavior::Loop => {
+                        if let Err(e) = movie_state.rewind() {
+                            error!("📽📽  failed to seek backwards: {}", e);
+                            keep_running.store(false, std::sync::atomic::Ordering::Relaxed);
+                            return;
+                        }
+                    },
+                    EndBehavior::StopOnLastFrame => {
+                        movie_state.ended.store(true, std::sync::atomic::Ordering::Relaxed);
+                        at_end = true;
+                    },
+                    EndBehavior::PingPong => {
+                        // let the decode thread hand over the last frames before taking the decoder
+                        if !wait_until_drained(&movie_state.video_drained, &keep_running) {
+                            return;
+                        }
+                        let end_pts = movie_state.last_decoded_pts.load(std::sync::atomic::Ordering::Relaxed);
+                        if end_pts == ffi::AV_NOPTS_VALUE {
+                            let _ = movie_state.rewind();
+                        } else {
+                            movie_state.reverse_origin.store(end_pts, std::sync::atomic::Ordering::Relaxed);
+                            reverse_end = Some(end_pts);
+                        }
+                    },
+                    EndBehavior::Close => {
+                        // let the decode threads hand over their last frames, then stop them too
+                        wait_until_drained(&movie_state.video_drained, &keep_running);
+                        wait_until_drained(&movie_state.audio_drained, &keep_running);
+                        movie_state.ended.store(true, std::sync::atomic::Ordering::Relaxed);
+                        movie_state.closed.store(true, std::sync::atomic::Ordering::Relaxed);
+                        keep_running.store(false, std::sync::atomic::Ordering::Relaxed);
+                        return;
+                    },
                 }
-                eprintln!("rewind to {}",
-                    seek_ret
-                );
                 continue;
             }
 
@@ -358,16 +662,29 @@ fn packet_thread_spawner(
                 return;
                 // break 'running;
             }
+            movie_state.record_packet(packet);
             {
-                if video_stream_idx == packet.stream_index as i64 {
+                if movie_state.video_stream_index() == packet.stream_index as i64 {
                     while let Err(_) = movie_state_enqueue_packet(&movie_state.videoqueue, packet) {
                         // ::std::thread::yield_now();
                         ::std::thread::sleep(::std::time::Duration::from_millis(70));
-                        if !keep_running.load(std::sync::atomic::Ordering::Relaxed) {
+                        if !keep_running.load(std::sync::atomic::Ordering::Relaxed) || movie_state.is_seeking() {
+                            ffi::av_packet_free(&mut (packet as *mut _) as *mut *mut _);
                             break;
                         }
                     }
                     // ::std::thread::sleep(Duration::from_millis(33));
+                } else if movie_state.audio_stream_idx == packet.stream_index as i64 {
+                    while let Err(_) = movie_state_enqueue_audio_packet(&movie_state.audioqueue, packet) {
+                        ::std::thread::sleep(::std::time::Duration::from_millis(10));
+                        if !keep_running.load(std::sync::atomic::Ordering::Relaxed) || movie_state.is_seeking() {
+                            ffi::av_packet_free(&mut (packet as *mut _) as *mut *mut _);
+                            break;
+                        }
+                    }
+                } else if movie_state.subtitle_stream_index() == Some(packet.stream_index as usize) {
+                    movie_state.decode_subtitle_packet(packet);
+                    ffi::av_packet_free(&mut (packet as *mut _) as *mut *mut _);
                 } else {
                     ffi::av_packet_unref(packet);
                 }
@@ -377,11 +694,162 @@ fn packet_thread_spawner(
     };
 }
 
+/// queue a NULL packet for every decoder so they give up their buffered frames.
+/// `video_drained` and `audio_drained` are set again once they did, or right
+/// away for a marker that could not be queued.
+unsafe fn enqueue_drain_markers(movie_state: &MovieState, keep_running: &std::sync::atomic::AtomicBool) {
+    movie_state.video_drained.store(false, std::sync::atomic::Ordering::Relaxed);
+    movie_state.audio_drained.store(!movie_state.has_audio(), std::sync::atomic::Ordering::Relaxed);
+    while let Err(_) = movie_state_enqueue_packet(&movie_state.videoqueue, ptr::null_mut()) {
+        ::std::thread::sleep(::std::time::Duration::from_millis(10));
+        if !keep_running.load(std::sync::atomic::Ordering::Relaxed) || movie_state.is_seeking() {
+            movie_state.video_drained.store(true, std::sync::atomic::Ordering::Relaxed);
+            movie_state.audio_drained.store(true, std::sync::atomic::Ordering::Relaxed);
+            return;
+        }
+    }
+    if movie_state.has_audio() {
+        while let Err(_) = movie_state_enqueue_audio_packet(&movie_state.audioqueue, ptr::null_mut()) {
+            ::std::thread::sleep(::std::time::Duration::from_millis(10));
+            if !keep_running.load(std::sync::atomic::Ordering::Relaxed) || movie_state.is_seeking() {
+                movie_state.audio_drained.store(true, std::sync::atomic::Ordering::Relaxed);
+                return;
+            }
+        }
+    }
+}
+
+/// wait for a decode thread to set `drained`, false when the movie is stopped first
+fn wait_until_drained(drained: &std::sync::atomic::AtomicBool, keep_running: &std::sync::atomic::AtomicBool) -> bool {
+    while !drained.load(std::sync::atomic::Ordering::Relaxed) {
+        if !keep_running.load(std::sync::atomic::Ordering::Relaxed) {
+            return false;
+        }
+        ::std::thread::sleep(::std::time::Duration::from_millis(10));
+    }
+    true
+}
+
+/// decode the GOP before `end_pts` and queue it newest first. returns where the
+/// next GOP ends, or None at the start of the movie or when interrupted.
+unsafe fn play_gop_backwards(
+    movie_state: &MovieState,
+    end_pts: i64,
+    keep_running: &std::sync::atomic::AtomicBool,
+) -> Option<i64> {
+    let frames = match movie_state.decode_gop_before(end_pts) {
+        Ok(frames) => frames,
+        Err(e) => {
+            error!("📽📽  failed to decode backwards: {}", e);
+            return None;
+        }
+    };
+    let gop_start = (*frames.last()?.ptr).pts;
+    let origin = movie_state.reverse_origin.load(std::sync::atomic::Ordering::Relaxed);
+    let mut frames = frames.into_iter();
+    while let Some(frame) = frames.next() {
+        let info = FrameInfo::from_frame(frame.ptr);
+        // mirrored so the presentation clock keeps counting up
+        (*frame.ptr).pts = 2 * origin - (*frame.ptr).pts;
+        while let Err(_) = movie_state.enqueue_frame(frame.ptr, info.clone()) {
+            ::std::thread::sleep(::std::time::Duration::from_millis(10));
+            if !keep_running.load(std::sync::atomic::Ordering::Relaxed) || movie_state.is_seeking() {
+                let mut frame = frame;
+                ffi::av_frame_free(&mut frame.ptr);
+                frames.for_each(|mut f| ffi::av_frame_free(&mut f.ptr));
+                return None;
+            }
+        }
+    }
+    Some(gop_start)
+}
+
 #[cfg(test)]
 mod tests {
     use ::std::{thread::sleep, time::Duration};
 
     use super::*;
+    use crate::difference::{difference_frames, DifferenceOptions};
+    use crate::movie_state::SeekMode;
+
+    const TEST_FILE: &str = "test_vid.mp4";
+    const FRAME_TIMEOUT: Duration = Duration::from_secs(5);
+
+    /// a frame taken off the analyzer, freed when dropped
+    struct Shown {
+        frame: *mut ffi::AVFrame,
+        info: FrameInfo,
+    }
+
+    impl std::ops::Deref for Shown {
+        type Target = ffi::AVFrame;
+
+        fn deref(&self) -> &ffi::AVFrame {
+            unsafe { &*self.frame }
+        }
+    }
+
+    impl Drop for Shown {
+        fn drop(&mut self) {
+            unsafe { ffi::av_frame_free(&mut self.frame) };
+        }
+    }
+
+    /// an analyzer with every file opened, not started yet
+    fn opened(files: &[&str]) -> AnalyzerContext {
+        let mut analyzer_ctx = AnalyzerContext::new();
+        for file in files {
+            let filepath = std::ffi::CString::new(*file).unwrap();
+            unsafe { open_movie(&mut analyzer_ctx, filepath.as_ptr()).unwrap() };
+        }
+        analyzer_ctx
+    }
+
+    /// start the analyzer and resume playback
+    fn play(analyzer_ctx: &mut AnalyzerContext) -> ControlChannel {
+        let control = unsafe { start_analyzer(analyzer_ctx) };
+        control.send(Command::Resume).unwrap();
+        control
+    }
+
+    fn playing(files: &[&str]) -> (AnalyzerContext, ControlChannel) {
+        let mut analyzer_ctx = opened(files);
+        let control = play(&mut analyzer_ctx);
+        (analyzer_ctx, control)
+    }
+
+    fn stop(analyzer_ctx: AnalyzerContext, control: ControlChannel) {
+        control.send(Command::Quit).unwrap();
+        AnalyzerContext::close(analyzer_ctx);
+    }
+
+    /// the frame this movie presents right now, if any
+    fn take_frame(analyzer_ctx: &AnalyzerContext, movie_index: u8) -> Option<Shown> {
+        analyzer_ctx.dequeue_frame(movie_index).1.map(|(frame, info)| Shown { frame, info })
+    }
+
+    /// wait up to `timeout` for the next frame this movie presents
+    fn next_frame(analyzer_ctx: &AnalyzerContext, movie_index: u8, timeout: Duration) -> Option<Shown> {
+        let started = std::time::Instant::now();
+        while started.elapsed() < timeout {
+            if let Some(shown) = take_frame(analyzer_ctx, movie_index) {
+                return Some(shown);
+            }
+            sleep(Duration::from_millis(5));
+        }
+        None
+    }
+
+    /// the events that arrived until one matched `wanted`, or `timeout` passed
+    fn events_until(control: &ControlChannel, timeout: Duration, wanted: impl Fn(&AnalyzerEvent) -> bool) -> Vec<AnalyzerEvent> {
+        let started = std::time::Instant::now();
+        let mut events = vec![];
+        while !events.iter().any(&wanted) && started.elapsed() < timeout {
+            events.extend(control.poll_events());
+            sleep(Duration::from_millis(5));
+        }
+        events
+    }
 
     #[test]
     fn test_add_two_movies_to_analyzer() {
@@ -389,8 +857,8 @@ mod tests {
         let mut analyzer_ctx = AnalyzerContext::new();
         let filepath: std::ffi::CString = std::ffi::CString::new(default_file).unwrap();
         unsafe {
-            open_movie(&mut analyzer_ctx, filepath.as_ptr());
-            open_movie(&mut analyzer_ctx, filepath.as_ptr());
+            open_movie(&mut analyzer_ctx, filepath.as_ptr()).unwrap();
+            open_movie(&mut analyzer_ctx, filepath.as_ptr()).unwrap();
         }
 
         assert_eq!(analyzer_ctx.movie_count(), 2);
@@ -400,20 +868,547 @@ mod tests {
         // assert_eq!(analyzer_ctx.movie_count(), 0)
     }
 
+    #[test]
+    fn test_open_missing_file_returns_error() {
+        let mut analyzer_ctx = AnalyzerContext::new();
+        let filepath: std::ffi::CString = std::ffi::CString::new("does_not_exist.mp4").unwrap();
+        let result = unsafe { open_movie(&mut analyzer_ctx, filepath.as_ptr()) };
+
+        assert!(matches!(result, Err(MovieError::OpenInput(_))));
+        assert_eq!(MovieStatus::from(&result), MovieStatus::OpenInput);
+        assert_eq!(analyzer_ctx.movie_count(), 0);
+    }
+
+    #[test]
+    fn test_open_with_video_stream_selection() {
+        let mut analyzer_ctx = AnalyzerContext::new();
+        let filepath: std::ffi::CString = std::ffi::CString::new("test_vid.mp4").unwrap();
+        unsafe {
+            open_movie_with_options(&mut analyzer_ctx, filepath.as_ptr(), &OpenOptions::new().video_stream(StreamSelection::Best)).unwrap();
+            let result = open_movie_with_options(&mut analyzer_ctx, filepath.as_ptr(), &OpenOptions::new().video_stream(StreamSelection::Index(99)));
+            assert_eq!(result, Err(MovieError::InvalidVideoStream(99)));
+        }
+        assert_eq!(analyzer_ctx.movie_count(), 1);
+        let movie = analyzer_ctx.movie(0).unwrap();
+        let streams = movie.video_streams();
+        assert!(streams.iter().any(|s| s.active && s.index as i64 == movie.video_stream_index()));
+        assert_eq!(movie.select_video_stream(99, None), Err(MovieError::InvalidVideoStream(99)));
+        AnalyzerContext::close(analyzer_ctx);
+    }
+
+    #[test]
+    fn test_open_movie_from_bytes() {
+        let data = std::fs::read("test_vid.mp4").unwrap();
+        let mut analyzer_ctx = AnalyzerContext::new();
+        unsafe {
+            open_movie_from_bytes(&mut analyzer_ctx, &data, &OpenOptions::default()).unwrap();
+            open_movie_from_reader(&mut analyzer_ctx, std::fs::File::open("test_vid.mp4").unwrap(), &OpenOptions::default()).unwrap();
+            let result = open_movie_from_bytes(&mut analyzer_ctx, &data[..64], &OpenOptions::default());
+            assert!(result.is_err());
+        }
+        assert_eq!(analyzer_ctx.movie_count(), 2);
+        AnalyzerContext::close(analyzer_ctx);
+    }
+
+    #[test]
+    fn test_open_with_decoder_options_reports_unused() {
+        let mut analyzer_ctx = AnalyzerContext::new();
+        let filepath: std::ffi::CString = std::ffi::CString::new("test_vid.mp4").unwrap();
+        let options = OpenOptions::new()
+            .format_option("probesize", "5000000")
+            .format_option("not_a_demuxer_option", "1")
+            .decoder_name("h264")
+            .decoder_option("skip_loop_filter", "all")
+            .decoder_option("not_a_decoder_option", "1")
+            .threads(2);
+        let report = unsafe { open_movie_with_options(&mut analyzer_ctx, filepath.as_ptr(), &options).unwrap() };
+        assert_eq!(report.decoder, "h264");
+        assert_eq!(report.unused_format_options, vec!["not_a_demuxer_option".to_string()]);
+        assert_eq!(report.unused_decoder_options, vec!["not_a_decoder_option".to_string()]);
+
+        let result = unsafe {
+            open_movie_with_options(&mut analyzer_ctx, filepath.as_ptr(), &OpenOptions::new().decoder_name("mpeg2video"))
+        };
+        assert_eq!(result, Err(MovieError::UnsupportedDecoder("mpeg2video".to_string())));
+        assert_eq!(analyzer_ctx.movie_count(), 1);
+        AnalyzerContext::close(analyzer_ctx);
+    }
+
+    #[test]
+    fn test_open_with_external_subtitles() {
+        let srt = std::env::temp_dir().join("rsplayer_test_vid.srt");
+        std::fs::write(&srt, "1\n00:00:00,000 --> 00:00:02,000\nfirst\n\n2\n00:00:03,000 --> 00:00:04,000\nsecond\n").unwrap();
+        let mut analyzer_ctx = AnalyzerContext::new();
+        let filepath: std::ffi::CString = std::ffi::CString::new("test_vid.mp4").unwrap();
+        let options = OpenOptions::new()
+            .subtitle_stream(StreamSelection::Best)
+            .external_subtitles(&srt);
+        unsafe {
+            open_movie_with_options(&mut analyzer_ctx, filepath.as_ptr(), &options).unwrap();
+        }
+        let movie = analyzer_ctx.movie(0).unwrap();
+        assert_eq!(movie.external_subtitles.lock().unwrap().len(), 2);
+        assert_eq!(movie.active_subtitles().len(), 1);
+        // the test movie has no subtitle track, stream 0 is the video
+        assert_eq!(movie.subtitle_stream_index(), None);
+        assert_eq!(movie.select_subtitle_stream(Some(0)), Err(MovieError::InvalidSubtitleStream(0)));
+        assert_eq!(movie.select_subtitle_stream(None), Ok(()));
+        AnalyzerContext::close(analyzer_ctx);
+        let _ = std::fs::remove_file(srt);
+    }
+
+    #[test]
+    fn test_stop_on_last_frame_reports_end_of_stream() {
+        let mut analyzer_ctx = AnalyzerContext::new();
+        let filepath: std::ffi::CString = std::ffi::CString::new(TEST_FILE).unwrap();
+        let options = OpenOptions::new().end_behavior(EndBehavior::StopOnLastFrame);
+        unsafe {
+            open_movie_with_options(&mut analyzer_ctx, filepath.as_ptr(), &options).unwrap();
+        }
+        let control = play(&mut analyzer_ctx);
+
+        // keep pulling frames so the decoder never blocks on a full queue
+        let started = std::time::Instant::now();
+        let mut ended = false;
+        while !ended && started.elapsed() < Duration::from_secs(30) {
+            drop(take_frame(&analyzer_ctx, 0));
+            ended = control.poll_events().contains(&AnalyzerEvent::EndOfStream { movie_index: 0 });
+            sleep(Duration::from_millis(1));
+        }
+        assert!(ended);
+        sleep(Duration::from_millis(50));
+        assert!(analyzer_ctx.movie(0).unwrap().has_ended());
+
+        stop(analyzer_ctx, control);
+    }
+
+    #[test]
+    fn test_close_at_the_end_stops_every_movie_thread() {
+        let mut analyzer_ctx = AnalyzerContext::new();
+        let filepath: std::ffi::CString = std::ffi::CString::new(TEST_FILE).unwrap();
+        let options = OpenOptions::new().end_behavior(EndBehavior::Close);
+        unsafe {
+            open_movie_with_options(&mut analyzer_ctx, filepath.as_ptr(), &options).unwrap();
+        }
+        let control = play(&mut analyzer_ctx);
+
+        let movie = analyzer_ctx.movie(0).unwrap();
+        let finished = || movie.threads.lock().unwrap().iter().all(|thread| thread.is_finished());
+        let started = std::time::Instant::now();
+        while !(movie.is_closed() && finished()) && started.elapsed() < Duration::from_secs(30) {
+            drop(take_frame(&analyzer_ctx, 0));
+            sleep(Duration::from_millis(1));
+        }
+        assert!(movie.is_closed());
+        assert!(finished());
+
+        stop(analyzer_ctx, control);
+    }
+
+    #[test]
+    fn test_synchronized_movies_present_the_same_pts() {
+        let mut analyzer_ctx = opened(&[TEST_FILE, TEST_FILE]);
+        analyzer_ctx.set_synchronized(true);
+        let control = play(&mut analyzer_ctx);
+
+        let started = std::time::Instant::now();
+        let mut ticks = 0;
+        while started.elapsed() < Duration::from_secs(2) {
+            if let (Some(a), Some(b)) = (take_frame(&analyzer_ctx, 0), take_frame(&analyzer_ctx, 1)) {
+                assert_eq!(a.pts, b.pts);
+                assert_eq!(a.info.pict_type, b.info.pict_type);
+                ticks += 1;
+            }
+            sleep(Duration::from_millis(5));
+        }
+        assert!(ticks > 10);
+
+        stop(analyzer_ctx, control);
+    }
+
+    #[test]
+    fn test_difference_of_identical_movies() {
+        let mut analyzer_ctx = opened(&[TEST_FILE, TEST_FILE]);
+        analyzer_ctx.set_synchronized(true);
+        let control = play(&mut analyzer_ctx);
+
+        let mut difference = None;
+        let started = std::time::Instant::now();
+        while difference.is_none() && started.elapsed() < FRAME_TIMEOUT {
+            if let (Some(a), Some(b)) = (take_frame(&analyzer_ctx, 0), take_frame(&analyzer_ctx, 1)) {
+                let options = DifferenceOptions { threshold: 1, ..Default::default() };
+                difference = Some(unsafe { difference_frames(a.frame, b.frame, &options).unwrap() });
+            }
+            sleep(Duration::from_millis(5));
+        }
+        let difference = difference.expect("no frames to compare");
+        assert_eq!((difference.width, difference.height), (1280, 720));
+        assert_eq!(difference.max, 0);
+        assert_eq!(difference.significant, 0);
+
+        stop(analyzer_ctx, control);
+    }
+
+    #[test]
+    fn test_metrics_of_identical_movies() {
+        let mut analyzer_ctx = opened(&[TEST_FILE, TEST_FILE]);
+        analyzer_ctx.set_synchronized(true);
+        analyzer_ctx.set_metrics_enabled(true);
+        let control = play(&mut analyzer_ctx);
+
+        let started = std::time::Instant::now();
+        while started.elapsed() < Duration::from_secs(1) {
+            drop((take_frame(&analyzer_ctx, 0), take_frame(&analyzer_ctx, 1)));
+            sleep(Duration::from_millis(5));
+        }
+        control.send(Command::Quit).unwrap();
+
+        let history = analyzer_ctx.metrics_history();
+        assert!(history.frames.len() > 10);
+        for metrics in history.frames.iter() {
+            assert_eq!(metrics.movie_index, 1);
+            assert_eq!(metrics.pts, metrics.reference_pts);
+            assert_eq!(metrics.psnr_average(), f64::INFINITY);
+            assert!((metrics.ssim_average() - 1.).abs() < 1e-9);
+        }
+        assert_eq!(history.to_csv().lines().count(), history.frames.len() + 1);
+        AnalyzerContext::close(analyzer_ctx);
+    }
+
+    #[test]
+    fn test_speed_is_clamped_and_nan_rejected() {
+        let analyzer_ctx = opened(&[TEST_FILE]);
+        let movie = analyzer_ctx.movie(0).unwrap();
+
+        assert_eq!(analyzer_ctx.set_speed(f32::NAN), Err(MovieError::InvalidSpeed));
+        assert_eq!(analyzer_ctx.set_speed(0.), Err(MovieError::InvalidSpeed));
+        assert_eq!(analyzer_ctx.set_speed(100.), Ok(crate::analyzer_state::MAX_SPEED));
+        assert_eq!(movie.speed(), crate::analyzer_state::MAX_SPEED);
+
+        AnalyzerContext::close(analyzer_ctx);
+    }
+
+    #[test]
+    fn test_step_back_fails_while_playing() {
+        let (analyzer_ctx, control) = playing(&[TEST_FILE]);
+        let resumed = AnalyzerEvent::Applied(Command::Resume);
+        assert!(events_until(&control, FRAME_TIMEOUT, |e| *e == resumed).contains(&resumed));
+
+        assert_eq!(analyzer_ctx.step_back(), Err(MovieError::NotPaused));
+        control.send(Command::StepBack).unwrap();
+        let failed = AnalyzerEvent::Failed { command: Command::StepBack, reason: "not paused".to_string() };
+        assert!(events_until(&control, FRAME_TIMEOUT, |e| *e == failed).contains(&failed));
+
+        stop(analyzer_ctx, control);
+    }
+
+    #[test]
+    fn test_step_back_shows_the_previous_frame() {
+        let mut analyzer_ctx = opened(&[TEST_FILE]);
+        let control = unsafe { start_analyzer(&mut analyzer_ctx) };
+        let next_pts = |analyzer_ctx: &AnalyzerContext| next_frame(analyzer_ctx, 0, FRAME_TIMEOUT).expect("no frame").pts;
+
+        // step past the first keyframe so the previous frame is in another GOP
+        let mut shown = vec![];
+        for _ in 0..32 {
+            analyzer_ctx.movie(0).unwrap().step_force();
+            shown.push(next_pts(&analyzer_ctx));
+        }
+        for expected in shown.iter().rev().skip(1).take(3) {
+            analyzer_ctx.step_back().unwrap();
+            assert_eq!(next_pts(&analyzer_ctx), *expected);
+        }
+        // forwards again from where we stepped back to
+        analyzer_ctx.step();
+        assert_eq!(next_pts(&analyzer_ctx), shown[shown.len() - 3]);
+
+        stop(analyzer_ctx, control);
+    }
+
+    #[test]
+    fn test_close_stops_a_running_analyzer() {
+        let (analyzer_ctx, control) = playing(&[TEST_FILE]);
+        let movie = analyzer_ctx.movie(0).unwrap();
+
+        // no Quit from the caller
+        AnalyzerContext::close(analyzer_ctx);
+        assert!(control.poll_events().contains(&AnalyzerEvent::Stopped));
+        assert!(!movie.has_threads());
+    }
+
+    #[test]
+    fn test_seek_shows_nothing_from_before_the_target() {
+        let (analyzer_ctx, control) = playing(&[TEST_FILE]);
+
+        let movie = analyzer_ctx.movie(0).unwrap();
+        let half_frame = 0.5 / 60.;
+        for target in [4., 1., 3.] {
+            let command = Command::Seek { position: target, mode: SeekMode::Accurate };
+            control.send(command.clone()).unwrap();
+            let queued = AnalyzerEvent::Queued(command.clone());
+            let applied = AnalyzerEvent::MovieApplied { movie_index: 0, command };
+            let events = events_until(&control, FRAME_TIMEOUT, |e| *e == applied);
+            assert!(events.contains(&queued), "seek not queued");
+            assert!(events.contains(&applied), "seek not carried out");
+            let shown = next_frame(&analyzer_ctx, 0, FRAME_TIMEOUT).expect("no frame after the seek");
+            let position = movie.pts_to_position(shown.pts);
+            assert!(position >= target - half_frame, "showed {} after seeking to {}", position, target);
+        }
+
+        stop(analyzer_ctx, control);
+    }
+
+    #[test]
+    fn test_seek_starts_the_filter_graph_over() {
+        let (analyzer_ctx, control) = playing(&[TEST_FILE]);
+        let movie = analyzer_ctx.movie(0).unwrap();
+        next_frame(&analyzer_ctx, 0, FRAME_TIMEOUT).expect("no frame before the seek");
+
+        let command = Command::Seek { position: 2., mode: SeekMode::Accurate };
+        control.send(command.clone()).unwrap();
+        let applied = AnalyzerEvent::MovieApplied { movie_index: 0, command };
+        assert!(events_until(&control, FRAME_TIMEOUT, |e| *e == applied).contains(&applied));
+        // rebuilt with the next frame presented
+        assert!(movie.vgraph.lock().unwrap().ptr.is_null(), "kept the graph from before the seek");
+
+        stop(analyzer_ctx, control);
+    }
+
+    #[test]
+    fn test_scopes_of_a_decoded_frame() {
+        let mut analyzer_ctx = opened(&[TEST_FILE]);
+        let control = unsafe { start_analyzer(&mut analyzer_ctx) };
+
+        analyzer_ctx.movie(0).unwrap().step_force();
+        let shown = next_frame(&analyzer_ctx, 0, FRAME_TIMEOUT).expect("no frame to measure");
+        let scopes = unsafe { crate::scopes::compute_scopes(shown.frame, 320).unwrap() };
+        let pixels = 1280 * 720;
+        assert_eq!(scopes.histogram.y.iter().sum::<u32>(), pixels);
+        assert_eq!(scopes.histogram.u.iter().sum::<u32>(), pixels / 4);
+        assert_eq!(scopes.vectorscope.counts.iter().sum::<u32>(), pixels / 4);
+        assert_eq!(scopes.luma.columns, 320);
+        assert_eq!(scopes.luma.counts.iter().sum::<u32>(), pixels);
+        assert!(scopes.parade.iter().all(|w| w.counts.iter().sum::<u32>() == pixels));
+
+        drop(shown);
+        stop(analyzer_ctx, control);
+    }
+
+    #[test]
+    fn test_frame_info_comes_with_the_frame() {
+        let mut analyzer_ctx = opened(&[TEST_FILE]);
+        let control = unsafe { start_analyzer(&mut analyzer_ctx) };
+
+        let mut infos = vec![];
+        for _ in 0..2 {
+            analyzer_ctx.movie(0).unwrap().step_force();
+            let shown = next_frame(&analyzer_ctx, 0, FRAME_TIMEOUT).expect("no frame");
+            assert_eq!(shown.pts, shown.info.pts);
+            infos.push(shown.info.clone());
+        }
+        assert!(infos[0].key_frame);
+        assert_eq!(infos[0].pict_type, 'I');
+        assert!(!infos[1].key_frame);
+        assert!(infos.iter().all(|info| info.packet_size > 0 && !info.has_decode_errors()));
+
+        stop(analyzer_ctx, control);
+    }
+
+    #[test]
+    fn test_add_remove_and_replace_movies_while_running() {
+        let (mut analyzer_ctx, control) = playing(&[TEST_FILE]);
+        let filepath: std::ffi::CString = std::ffi::CString::new(TEST_FILE).unwrap();
+        let shows_frames = |analyzer_ctx: &AnalyzerContext, movie_index| next_frame(analyzer_ctx, movie_index, FRAME_TIMEOUT).is_some();
+        assert!(shows_frames(&analyzer_ctx, 0));
+
+        unsafe { open_movie(&mut analyzer_ctx, filepath.as_ptr()).unwrap() };
+        assert_eq!(analyzer_ctx.movie_count(), 2);
+        assert!(analyzer_ctx.movie(1).unwrap().has_threads());
+        assert!(shows_frames(&analyzer_ctx, 1));
+
+        analyzer_ctx.remove_movie(0).unwrap();
+        assert_eq!(analyzer_ctx.movie_count(), 1);
+        assert_eq!(analyzer_ctx.movie(0).unwrap().movie_index.load(std::sync::atomic::Ordering::Relaxed), 0);
+        assert!(shows_frames(&analyzer_ctx, 0));
+        assert!(analyzer_ctx.remove_movie(1).is_err());
+
+        let (movie, _) = unsafe { load_movie(filepath.as_ptr(), &OpenOptions::default()).unwrap() };
+        analyzer_ctx.replace_movie(0, movie).unwrap();
+        assert!(shows_frames(&analyzer_ctx, 0));
+
+        let events = control.poll_events();
+        assert!(events.contains(&AnalyzerEvent::MovieAdded { movie_index: 1 }));
+        assert!(events.contains(&AnalyzerEvent::MovieRemoved { movie_index: 0 }));
+        assert!(events.contains(&AnalyzerEvent::MovieReplaced { movie_index: 0 }));
+
+        stop(analyzer_ctx, control);
+    }
+
+    #[test]
+    fn test_packet_timeline_records_what_was_read() {
+        let (analyzer_ctx, control) = playing(&[TEST_FILE]);
+
+        let movie = analyzer_ctx.movie(0).unwrap();
+        let stream_index = movie.video_stream_index() as usize;
+        let started = std::time::Instant::now();
+        while movie.packet_timeline.lock().unwrap().packets(stream_index).len() < 20 && started.elapsed() < FRAME_TIMEOUT {
+            drop(take_frame(&analyzer_ctx, 0));
+            sleep(Duration::from_millis(5));
+        }
+        control.send(Command::Pause).unwrap();
+
+        let timeline = movie.packet_timeline.lock().unwrap();
+        let packets = timeline.packets(stream_index);
+        assert!(packets.len() >= 20);
+        assert!(packets[0].keyframe);
+        assert_eq!(packets[0].pict_type, Some('I'));
+        assert!(packets.iter().all(|p| p.size > 0 && p.stream_index == stream_index));
+        assert!(packets.windows(2).all(|w| w[0].dts < w[1].dts && w[0].time < w[1].time));
+        let at = packets[19].time;
+        assert!(timeline.bitrate_at(stream_index, at, 0.25) > 0.);
+        drop(timeline);
+
+        stop(analyzer_ctx, control);
+    }
+
+    #[test]
+    fn test_filter_description_replaced_while_playing() {
+        let mut analyzer_ctx = opened(&[TEST_FILE]);
+        // checked against the decoder's size before any frame is filtered
+        analyzer_ctx.movie(0).unwrap().set_filter_description(Some("scale=640:360,hflip")).unwrap();
+        let e = analyzer_ctx.movie(0).unwrap().set_filter_description(Some("no_such_filter")).unwrap_err();
+        assert!(matches!(e, crate::error::FilterError::Parse(_)), "{:?}", e);
+        assert_eq!(analyzer_ctx.movie(0).unwrap().filter_description().as_deref(), Some("scale=640:360,hflip"));
+
+        let control = play(&mut analyzer_ctx);
+        let next_width = |analyzer_ctx: &AnalyzerContext| next_frame(analyzer_ctx, 0, FRAME_TIMEOUT).map(|shown| shown.width);
+        assert_eq!(next_width(&analyzer_ctx), Some(640));
+
+        let replace = Command::SetFilter { movie_index: 0, description: Some(String::from("scale=320:180")) };
+        let broken = Command::SetFilter { movie_index: 0, description: Some(String::from("unsharp=no_such_option=1")) };
+        control.send(replace.clone()).unwrap();
+        control.send(broken.clone()).unwrap();
+        let is_broken = |e: &AnalyzerEvent| matches!(e, AnalyzerEvent::Failed { command, .. } if *command == broken);
+        let events = events_until(&control, FRAME_TIMEOUT, is_broken);
+        assert!(events.contains(&AnalyzerEvent::Applied(replace)));
+        assert!(events.iter().any(is_broken));
+        // the broken description left the working one in place
+        assert_eq!(next_width(&analyzer_ctx), Some(320));
+
+        stop(analyzer_ctx, control);
+    }
+
+    #[test]
+    fn test_orientation_override_turns_the_frames() {
+        let mut analyzer_ctx = opened(&[TEST_FILE]);
+        let movie = analyzer_ctx.movie(0).unwrap();
+        assert!(movie.stream_orientation().is_upright());
+        movie.set_orientation(Some(crate::orientation::Orientation { rotation: 90, flip: false }));
+        // the user's filter sees the frames upright
+        movie.set_filter_description(Some("crop=iw:ih/2")).unwrap();
+
+        let control = play(&mut analyzer_ctx);
+        let next_size = |analyzer_ctx: &AnalyzerContext| next_frame(analyzer_ctx, 0, FRAME_TIMEOUT).map(|shown| (shown.width, shown.height));
+        assert_eq!(next_size(&analyzer_ctx), Some((720, 640)));
+
+        let upright = AnalyzerEvent::Applied(Command::SetOrientation { movie_index: 0, orientation: None });
+        control.send(Command::SetOrientation { movie_index: 0, orientation: None }).unwrap();
+        assert!(events_until(&control, FRAME_TIMEOUT, |e| *e == upright).contains(&upright));
+        // frames are filtered as they are taken off the queue
+        assert_eq!(next_size(&analyzer_ctx), Some((1280, 360)));
+
+        stop(analyzer_ctx, control);
+    }
+
+    #[test]
+    fn test_filter_graph_follows_format_changes() {
+        let movie = MovieState::new();
+        movie.set_filter_description(Some("scale=iw/2:ih/2")).unwrap();
+        let filter = |width: i32, height: i32, format: i32| unsafe {
+            let frame = ffi::av_frame_alloc();
+            ((*frame).width, (*frame).height, (*frame).format) = (width, height, format);
+            assert!(ffi::av_frame_get_buffer(frame, 0) >= 0);
+            let mut filtered = movie.filter_frame(crate::movie_state::FrameWrapper { ptr: frame }, FrameInfo::default());
+            assert_eq!(filtered.len(), 1);
+            let dest_frame = &mut filtered[0].frame.ptr;
+            let size = ((**dest_frame).width, (**dest_frame).height, (**dest_frame).format);
+            ffi::av_frame_free(dest_frame);
+            size
+        };
+        let yuv420p = ffi::AVPixelFormat_AV_PIX_FMT_YUV420P;
+        assert_eq!(filter(320, 240, yuv420p), (160, 120, yuv420p));
+        assert_eq!(filter(320, 240, yuv420p), (160, 120, yuv420p));
+        assert_eq!(movie.take_format_change(), None);
+
+        assert_eq!(filter(640, 360, yuv420p), (320, 180, yuv420p));
+        let (from, to) = movie.take_format_change().unwrap();
+        assert_eq!((from.width, from.height, to.width, to.height), (320, 240, 640, 360));
+        assert_eq!(movie.take_format_change(), None);
+
+        let yuv444p = ffi::AVPixelFormat_AV_PIX_FMT_YUV444P;
+        let (width, height, format) = filter(640, 360, yuv444p);
+        assert_eq!((width, height), (320, 180));
+        assert_eq!(movie.take_format_change().map(|(from, to)| (from.format, to.format)), Some((yuv420p, yuv444p)));
+        // the sink only gives out what a display can show
+        assert!(crate::filter::SINK_PIX_FMTS.contains(&format), "{}", format);
+        for format in [ffi::AVPixelFormat_AV_PIX_FMT_YUV422P10LE, ffi::AVPixelFormat_AV_PIX_FMT_GBRP, ffi::AVPixelFormat_AV_PIX_FMT_GRAY8] {
+            assert!(crate::filter::SINK_PIX_FMTS.contains(&filter(64, 64, format).2), "{}", format);
+        }
+        assert_eq!(filter(64, 64, ffi::AVPixelFormat_AV_PIX_FMT_NV12).2, ffi::AVPixelFormat_AV_PIX_FMT_NV12);
+    }
+
+    #[test]
+    fn test_filters_see_the_stream_timing() {
+        let mut analyzer_ctx = opened(&[TEST_FILE]);
+        // one second later, in whatever time_base the source was given
+        analyzer_ctx.movie(0).unwrap().set_filter_description(Some("setpts=PTS+1/TB")).unwrap();
+        let control = play(&mut analyzer_ctx);
+
+        let shown = next_frame(&analyzer_ctx, 0, FRAME_TIMEOUT).expect("no frame");
+        let movie = analyzer_ctx.movie(0).unwrap();
+        let time_base = unsafe { (*movie.video_stream.lock().unwrap().ptr).time_base };
+        assert_eq!(shown.pts - shown.info.pts, (time_base.den / time_base.num) as i64);
+        let sink = movie.out_vfilter.lock().unwrap().ptr;
+        let (sink_time_base, frame_rate) = unsafe { (ffi::av_buffersink_get_time_base(sink), ffi::av_buffersink_get_frame_rate(sink)) };
+        assert_eq!((sink_time_base.num, sink_time_base.den), (time_base.num, time_base.den));
+        assert_eq!((frame_rate.num, frame_rate.den), (60, 1));
+
+        drop(shown);
+        stop(analyzer_ctx, control);
+    }
+
+    #[test]
+    fn test_filters_that_give_out_more_frames_than_go_in() {
+        let mut analyzer_ctx = opened(&[TEST_FILE]);
+        // twice the stream's 60 fps, every decoded frame comes out twice
+        analyzer_ctx.movie(0).unwrap().set_filter_description(Some("fps=120")).unwrap();
+        let control = play(&mut analyzer_ctx);
+
+        let shown: Vec<_> = (0..20)
+            .map_while(|_| next_frame(&analyzer_ctx, 0, FRAME_TIMEOUT))
+            .map(|shown| (shown.pts, shown.info.pts))
+            .collect();
+        assert_eq!(shown.len(), 20);
+        assert!(shown.windows(2).all(|pair| pair[0].0 < pair[1].0), "{:?}", shown);
+        assert!(shown.windows(2).any(|pair| pair[0].1 == pair[1].1), "{:?}", shown);
+
+        stop(analyzer_ctx, control);
+    }
+
     #[test]
     fn test_pause_analyzer_pauses_all_movies() {
         let default_file = String::from("test_vid.mp4");
         let mut analyzer_ctx = AnalyzerContext::new();
         let filepath: std::ffi::CString = std::ffi::CString::new(default_file).unwrap();
         unsafe {
-            open_movie(&mut analyzer_ctx, filepath.as_ptr());
-            open_movie(&mut analyzer_ctx, filepath.as_ptr());
+            open_movie(&mut analyzer_ctx, filepath.as_ptr()).unwrap();
+            open_movie(&mut analyzer_ctx, filepath.as_ptr()).unwrap();
         }
 
         assert_eq!(analyzer_ctx.movie_count(), 2);
         analyzer_ctx.step();
-        for movie in analyzer_ctx.movie_list.iter() {
-            assert_eq!(movie.step, true)
+        for movie in analyzer_ctx.movies().iter() {
+            assert_eq!(movie.step.load(std::sync::atomic::Ordering::Relaxed), true)
         }
         AnalyzerContext::close(analyzer_ctx);
         // analyzer_ctx.close();
@@ -0,0 +1,614 @@
+use std::{
+    collections::VecDeque,
+    ffi::{CStr, CString},
+    ptr,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc, Mutex,
+    },
+};
+
+use log::{debug, error, info};
+use rusty_ffmpeg::ffi;
+use sdl2::audio::AudioCallback;
+
+use crate::{error::AvError, movie_state::MovieState};
+
+/// every movie is resampled to this rate before it reaches SDL
+pub const AUDIO_SAMPLE_RATE: i32 = 48000;
+/// every movie is remixed to interleaved stereo before it reaches SDL
+pub const AUDIO_CHANNELS: i32 = 2;
+/// one second of decoded audio is buffered per movie
+const MAX_QUEUED_SAMPLES: usize = (AUDIO_SAMPLE_RATE * AUDIO_CHANNELS) as usize;
+/// audio closer to the video than this, in seconds of playback, is left alone
+const AV_SYNC_THRESHOLD: f64 = 0.045;
+/// further apart than this the video is seeking or looping, not drifting
+const AV_NOSYNC_THRESHOLD: f64 = 10.;
+
+/// resampled, interleaved f32 samples waiting for the SDL audio callback.
+/// shared between a movie's audio decode thread and the `AudioMixer`.
+pub struct AudioSampleQueue {
+    samples: Mutex<VecDeque<f32>>,
+    muted: AtomicBool,
+    paused: AtomicBool,
+    // only buffer samples while an output device is pulling them
+    attached: AtomicBool,
+}
+
+impl AudioSampleQueue {
+    pub fn new() -> AudioSampleQueue {
+        AudioSampleQueue {
+            samples: Mutex::new(VecDeque::with_capacity(MAX_QUEUED_SAMPLES)),
+            muted: AtomicBool::new(false),
+            paused: AtomicBool::new(false),
+            attached: AtomicBool::new(false),
+        }
+    }
+
+    pub fn is_muted(&self) -> bool {
+        self.muted.load(Ordering::Relaxed)
+    }
+
+    pub fn set_muted(&self, muted: bool) {
+        self.muted.store(muted, Ordering::Relaxed);
+    }
+
+    pub fn is_paused(&self) -> bool {
+        self.paused.load(Ordering::Relaxed)
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, Ordering::Relaxed);
+    }
+
+    pub fn is_attached(&self) -> bool {
+        self.attached.load(Ordering::Relaxed)
+    }
+
+    pub fn len(&self) -> usize {
+        self.samples.lock().unwrap().len()
+    }
+
+    pub fn clear(&self) {
+        self.samples.lock().unwrap().clear();
+    }
+
+    /// queue samples for playback, waiting while the queue is full.
+    /// samples are thrown away when no output device is attached.
+    pub(crate) fn push(&self, samples: &[f32], keep_running: &AtomicBool) {
+        loop {
+            if !self.is_attached() {
+                return;
+            }
+            {
+                let mut queue = self.samples.lock().unwrap();
+                if queue.len() < MAX_QUEUED_SAMPLES {
+                    queue.extend(samples.iter());
+                    return;
+                }
+            }
+            ::std::thread::sleep(::std::time::Duration::from_millis(10));
+            if !keep_running.load(Ordering::Relaxed) {
+                return;
+            }
+        }
+    }
+
+    /// pull `out.len()` samples, adding them to `out` unless muted.
+    /// muted movies still drain so they keep pace with the audible one.
+    fn mix_into(&self, out: &mut [f32]) {
+        if self.is_paused() {
+            return;
+        }
+        let muted = self.is_muted();
+        let mut queue = self.samples.lock().unwrap();
+        for o in out.iter_mut() {
+            match queue.pop_front() {
+                Some(sample) => {
+                    if !muted {
+                        *o += sample;
+                    }
+                }
+                None => break,
+            }
+        }
+    }
+}
+
+/// the sample queues an `AudioMixer` pulls from. shared with the analyzer so
+/// movies can come and go while the device is playing.
+#[derive(Default)]
+pub struct AudioSources {
+    queues: Mutex<Vec<Arc<AudioSampleQueue>>>,
+    // a mixer is pulling, queues added now start buffering right away
+    attached: AtomicBool,
+}
+
+impl AudioSources {
+    pub fn new(queues: Vec<Arc<AudioSampleQueue>>) -> AudioSources {
+        AudioSources { queues: Mutex::new(queues), attached: AtomicBool::new(false) }
+    }
+
+    pub fn add(&self, queue: Arc<AudioSampleQueue>) {
+        queue.attached.store(self.attached.load(Ordering::Relaxed), Ordering::Relaxed);
+        self.queues.lock().unwrap().push(queue);
+    }
+
+    /// stop mixing a queue, its decode thread no longer waits on it
+    pub fn remove(&self, queue: &Arc<AudioSampleQueue>) {
+        self.queues.lock().unwrap().retain(|q| !Arc::ptr_eq(q, queue));
+        queue.attached.store(false, Ordering::Relaxed);
+        queue.clear();
+    }
+
+    fn set_attached(&self, attached: bool) {
+        self.attached.store(attached, Ordering::Relaxed);
+        self.queues.lock().unwrap().iter().for_each(|q| {
+            q.attached.store(attached, Ordering::Relaxed);
+            if !attached {
+                q.clear();
+            }
+        });
+    }
+}
+
+/// SDL audio callback that mixes every movie's sample queue
+pub struct AudioMixer {
+    sources: Arc<AudioSources>,
+}
+
+impl AudioMixer {
+    pub fn new(sources: Arc<AudioSources>) -> AudioMixer {
+        sources.set_attached(true);
+        AudioMixer { sources }
+    }
+}
+
+impl Drop for AudioMixer {
+    fn drop(&mut self) {
+        // let the decode threads stop waiting on us
+        self.sources.set_attached(false);
+    }
+}
+
+impl AudioCallback for AudioMixer {
+    type Channel = f32;
+
+    fn callback(&mut self, out: &mut [f32]) {
+        out.iter_mut().for_each(|o| *o = 0.);
+        for source in self.sources.queues.lock().unwrap().iter() {
+            source.mix_into(out);
+        }
+    }
+}
+
+/// converts decoded frames to interleaved f32 at `AUDIO_SAMPLE_RATE` / `AUDIO_CHANNELS`
+pub struct Resampler {
+    ptr: *mut ffi::SwrContext,
+    in_format: i32,
+    in_rate: i32,
+    in_channels: i32,
+}
+unsafe impl Send for Resampler {}
+
+impl Resampler {
+    pub unsafe fn new(frame: *const ffi::AVFrame) -> Result<Resampler, AvError> {
+        let mut out_layout: ffi::AVChannelLayout = std::mem::zeroed();
+        ffi::av_channel_layout_default(&mut out_layout, AUDIO_CHANNELS);
+
+        let mut swr: *mut ffi::SwrContext = ptr::null_mut();
+        let ret = ffi::swr_alloc_set_opts2(
+            &mut swr,
+            &out_layout,
+            ffi::AVSampleFormat_AV_SAMPLE_FMT_FLT,
+            AUDIO_SAMPLE_RATE,
+            &(*frame).ch_layout,
+            (*frame).format,
+            (*frame).sample_rate,
+            0,
+            ptr::null_mut(),
+        );
+        ffi::av_channel_layout_uninit(&mut out_layout);
+        if ret < 0 {
+            return Err(AvError::from_code(ret));
+        }
+        let ret = ffi::swr_init(swr);
+        if ret < 0 {
+            ffi::swr_free(&mut swr);
+            return Err(AvError::from_code(ret));
+        }
+        info!(
+            "🔊 resampling {} Hz, {} channels, sample format {} to {} Hz stereo f32",
+            (*frame).sample_rate, (*frame).ch_layout.nb_channels, (*frame).format, AUDIO_SAMPLE_RATE
+        );
+        Ok(Resampler {
+            ptr: swr,
+            in_format: (*frame).format,
+            in_rate: (*frame).sample_rate,
+            in_channels: (*frame).ch_layout.nb_channels,
+        })
+    }
+
+    /// true when this resampler was set up for the frame's format
+    pub unsafe fn matches(&self, frame: *const ffi::AVFrame) -> bool {
+        self.in_format == (*frame).format
+            && self.in_rate == (*frame).sample_rate
+            && self.in_channels == (*frame).ch_layout.nb_channels
+    }
+
+    /// replace the contents of `out` with the resampled frame
+    pub unsafe fn convert(&mut self, frame: *const ffi::AVFrame, out: &mut Vec<f32>) -> Result<(), AvError> {
+        let out_count = ffi::swr_get_out_samples(self.ptr, (*frame).nb_samples);
+        if out_count < 0 {
+            out.clear();
+            return Err(AvError::from_code(out_count));
+        }
+        out.resize(out_count as usize * AUDIO_CHANNELS as usize, 0.);
+        let mut out_ptr = out.as_mut_ptr() as *mut u8;
+        let converted = ffi::swr_convert(
+            self.ptr,
+            &mut out_ptr,
+            out_count,
+            (*frame).extended_data as *mut *const u8,
+            (*frame).nb_samples,
+        );
+        if converted < 0 {
+            out.clear();
+            return Err(AvError::from_code(converted));
+        }
+        out.truncate(converted as usize * AUDIO_CHANNELS as usize);
+        Ok(())
+    }
+}
+
+impl Drop for Resampler {
+    fn drop(&mut self) {
+        unsafe { ffi::swr_free(&mut self.ptr) };
+    }
+}
+
+/// changes the tempo of decoded audio without changing its pitch,
+/// abuffer -> atempo -> abuffersink
+pub struct Tempo {
+    graph: *mut ffi::AVFilterGraph,
+    source: *mut ffi::AVFilterContext,
+    sink: *mut ffi::AVFilterContext,
+    speed: f32,
+    in_format: i32,
+    in_rate: i32,
+    in_channels: i32,
+}
+unsafe impl Send for Tempo {}
+
+/// atempo takes factors from 0.5 to 100, slower speeds are a chain of them
+pub fn atempo_chain(speed: f32) -> String {
+    let mut filters = vec![];
+    let mut remaining = speed as f64;
+    while remaining < 0.5 {
+        filters.push(String::from("atempo=0.5"));
+        remaining /= 0.5;
+    }
+    filters.push(format!("atempo={:.6}", remaining));
+    filters.join(",")
+}
+
+impl Tempo {
+    pub unsafe fn new(frame: *const ffi::AVFrame, speed: f32) -> Result<Tempo, AvError> {
+        let mut tempo = Tempo {
+            graph: ffi::avfilter_graph_alloc(),
+            source: ptr::null_mut(),
+            sink: ptr::null_mut(),
+            speed,
+            in_format: (*frame).format,
+            in_rate: (*frame).sample_rate,
+            in_channels: (*frame).ch_layout.nb_channels,
+        };
+        if tempo.graph.is_null() {
+            return Err(AvError::from_code(ffi::AVERROR(ffi::ENOMEM)));
+        }
+        let sample_fmt = ffi::av_get_sample_fmt_name((*frame).format);
+        if sample_fmt.is_null() {
+            return Err(AvError::from_code(ffi::AVERROR(ffi::EINVAL)));
+        }
+        let mut layout = [0 as libc::c_char; 64];
+        ffi::av_channel_layout_describe(&(*frame).ch_layout, layout.as_mut_ptr(), layout.len());
+        let args = CString::new(format!(
+            "time_base=1/{}:sample_rate={}:sample_fmt={}:channel_layout={}",
+            (*frame).sample_rate,
+            (*frame).sample_rate,
+            CStr::from_ptr(sample_fmt).to_string_lossy(),
+            CStr::from_ptr(layout.as_ptr()).to_string_lossy(),
+        )).unwrap();
+
+        let (in_name, out_name) = (CString::new("in").unwrap(), CString::new("out").unwrap());
+        let abuffer = CString::new("abuffer").unwrap();
+        let abuffersink = CString::new("abuffersink").unwrap();
+        let ret = ffi::avfilter_graph_create_filter(
+            &mut tempo.source,
+            ffi::avfilter_get_by_name(abuffer.as_ptr()),
+            in_name.as_ptr(),
+            args.as_ptr(),
+            ptr::null_mut(),
+            tempo.graph,
+        );
+        if ret < 0 {
+            return Err(AvError::from_code(ret));
+        }
+        let ret = ffi::avfilter_graph_create_filter(
+            &mut tempo.sink,
+            ffi::avfilter_get_by_name(abuffersink.as_ptr()),
+            out_name.as_ptr(),
+            ptr::null(),
+            ptr::null_mut(),
+            tempo.graph,
+        );
+        if ret < 0 {
+            return Err(AvError::from_code(ret));
+        }
+
+        let mut outputs = ffi::avfilter_inout_alloc();
+        let mut inputs = ffi::avfilter_inout_alloc();
+        if outputs.is_null() || inputs.is_null() {
+            ffi::avfilter_inout_free(&mut outputs);
+            ffi::avfilter_inout_free(&mut inputs);
+            return Err(AvError::from_code(ffi::AVERROR(ffi::ENOMEM)));
+        }
+        (*outputs).name = ffi::av_strdup(in_name.as_ptr());
+        (*outputs).filter_ctx = tempo.source;
+        (*inputs).name = ffi::av_strdup(out_name.as_ptr());
+        (*inputs).filter_ctx = tempo.sink;
+        let chain = CString::new(atempo_chain(speed)).unwrap();
+        let mut ret = ffi::avfilter_graph_parse_ptr(tempo.graph, chain.as_ptr(), &mut inputs, &mut outputs, ptr::null_mut());
+        ffi::avfilter_inout_free(&mut outputs);
+        ffi::avfilter_inout_free(&mut inputs);
+        if ret >= 0 {
+            ret = ffi::avfilter_graph_config(tempo.graph, ptr::null_mut());
+        }
+        if ret < 0 {
+            return Err(AvError::from_code(ret));
+        }
+        info!("🔊 audio tempo {}", speed);
+        Ok(tempo)
+    }
+
+    /// true when this graph was set up for the frame's format and `speed`
+    pub unsafe fn matches(&self, frame: *const ffi::AVFrame, speed: f32) -> bool {
+        self.speed == speed
+            && self.in_format == (*frame).format
+            && self.in_rate == (*frame).sample_rate
+            && self.in_channels == (*frame).ch_layout.nb_channels
+    }
+
+    /// feed a decoded frame, the caller keeps its reference
+    pub unsafe fn send(&mut self, frame: *mut ffi::AVFrame) -> Result<(), AvError> {
+        let ret = ffi::av_buffersrc_add_frame_flags(self.source, frame, ffi::AV_BUFFERSRC_FLAG_KEEP_REF as i32);
+        if ret < 0 {
+            return Err(AvError::from_code(ret));
+        }
+        Ok(())
+    }
+
+    /// false once the graph needs more input
+    pub unsafe fn receive(&mut self, out: *mut ffi::AVFrame) -> bool {
+        ffi::av_buffersink_get_frame(self.sink, out) >= 0
+    }
+}
+
+impl Drop for Tempo {
+    fn drop(&mut self) {
+        // frees the filters along with the graph
+        unsafe { ffi::avfilter_graph_free(&mut self.graph) };
+    }
+}
+
+/// samples to insert as silence (positive) or drop (negative) so that audio starting at
+/// `audio_position` is heard when the video is there. `queued` samples play before it.
+/// positions are seconds of the movie, which runs `speed` times faster than the wall clock.
+pub fn sync_to_video(audio_position: f64, video_clock: f64, queued: usize, speed: f32) -> i64 {
+    let speed = speed as f64;
+    let latency = queued as f64 / (AUDIO_SAMPLE_RATE * AUDIO_CHANNELS) as f64;
+    // wall clock seconds the audio is ahead of the video when it starts playing
+    let ahead = (audio_position - video_clock) / speed - latency;
+    if ahead.abs() < AV_SYNC_THRESHOLD || ahead.abs() > AV_NOSYNC_THRESHOLD {
+        return 0;
+    }
+    (ahead * AUDIO_SAMPLE_RATE as f64).round() as i64 * AUDIO_CHANNELS as i64
+}
+
+/// decode queued audio packets and feed the movie's sample queue
+pub(crate) unsafe fn audio_decode_thread(movie_state: Arc<MovieState>, keep_running: Arc<AtomicBool>) {
+    let frame = ffi::av_frame_alloc()
+        .as_mut()
+        .expect("failed to allocated memory for AVFrame");
+    let stretched = ffi::av_frame_alloc();
+    let mut resampler: Option<Resampler> = None;
+    let mut tempo: Option<Tempo> = None;
+    let mut converted: Vec<f32> = Vec::with_capacity(8192);
+    let mut pending: Vec<f32> = Vec::with_capacity(8192);
+    // where the first of the pending samples sits in the movie
+    let mut pending_position: Option<f64> = None;
+
+    loop {
+        if !keep_running.load(Ordering::Relaxed) {
+            break;
+        }
+        let packet = movie_state.audioqueue.lock().unwrap().pop_front();
+        let mut packet = match packet {
+            Some(packet) => packet,
+            None => {
+                ::std::thread::sleep(::std::time::Duration::from_millis(5));
+                continue;
+            }
+        };
+
+        // a NULL packet drains the decoder at the end of the file
+        let drain = packet.ptr.is_null();
+        let audio_ctx = movie_state.audio_ctx.lock().unwrap();
+        let mut response = ffi::avcodec_send_packet(audio_ctx.ptr, packet.ptr);
+        if response < 0 {
+            error!("Error while sending a packet to the audio decoder. {:?}", ffi::av_err2str(response));
+        }
+        while response >= 0 {
+            response = ffi::avcodec_receive_frame(audio_ctx.ptr, frame);
+            if response < 0 {
+                break;
+            }
+            if pending_position.is_none() && frame.best_effort_timestamp != ffi::AV_NOPTS_VALUE {
+                pending_position = movie_state.audio_pts_to_position(frame.best_effort_timestamp);
+            }
+            // played at another speed the samples go through atempo first
+            let speed = movie_state.speed();
+            if speed == 1. {
+                tempo = None;
+            } else if tempo.as_ref().map_or(true, |t| !t.matches(frame, speed)) {
+                tempo = match Tempo::new(frame, speed) {
+                    Ok(t) => Some(t),
+                    Err(e) => {
+                        error!("🔊 unable to change the audio tempo: {}", e);
+                        None
+                    }
+                };
+            }
+            match tempo.as_mut() {
+                Some(t) => {
+                    if let Err(e) = t.send(frame) {
+                        debug!("🔊 dropped audio frame: {}", e);
+                    }
+                    while t.receive(stretched) {
+                        resample(&mut resampler, stretched, &mut converted, &mut pending);
+                        ffi::av_frame_unref(stretched);
+                    }
+                }
+                None => resample(&mut resampler, frame, &mut converted, &mut pending),
+            }
+            ffi::av_frame_unref(frame);
+        }
+        // reset the drained decoder for the rewind
+        if drain {
+            ffi::avcodec_flush_buffers(audio_ctx.ptr);
+        }
+        drop(audio_ctx);
+        ffi::av_packet_free(&mut packet.ptr as *mut *mut _);
+
+        // the video is the master clock, audio drifting away from it is cut or padded
+        if let (Some(position), Some(clock)) = (pending_position.take(), movie_state.video_clock()) {
+            let correction = sync_to_video(position, clock, movie_state.audio_samples.len(), movie_state.speed());
+            if correction > 0 {
+                let silence = (correction as usize).min(MAX_QUEUED_SAMPLES);
+                pending.splice(0..0, std::iter::repeat(0.).take(silence));
+            } else if correction < 0 {
+                pending.drain(..(-correction as usize).min(pending.len()));
+            }
+        }
+        // don't hold the codec while waiting for the device to catch up
+        movie_state.audio_samples.push(&pending, &keep_running);
+        pending.clear();
+        if drain {
+            movie_state.audio_drained.store(true, Ordering::Relaxed);
+        }
+    }
+    ffi::av_frame_free(&mut (frame as *mut _) as *mut *mut _);
+    ffi::av_frame_free(&mut (stretched as *mut _) as *mut *mut _);
+}
+
+/// append `frame` to `pending` as interleaved f32, rebuilding the resampler when the format changed
+unsafe fn resample(resampler: &mut Option<Resampler>, frame: *const ffi::AVFrame, converted: &mut Vec<f32>, pending: &mut Vec<f32>) {
+    if resampler.as_ref().map_or(true, |r| !r.matches(frame)) {
+        *resampler = match Resampler::new(frame) {
+            Ok(r) => Some(r),
+            Err(e) => {
+                error!("🔊 unable to create resampler: {}", e);
+                None
+            }
+        };
+    }
+    if let Some(r) = resampler.as_mut() {
+        match r.convert(frame, converted) {
+            Ok(_) => pending.extend_from_slice(converted),
+            Err(e) => debug!("🔊 dropped audio frame: {}", e),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_mixer_only_plays_unmuted_movies() {
+        let keep_running = AtomicBool::new(true);
+        let audible = Arc::new(AudioSampleQueue::new());
+        let muted = Arc::new(AudioSampleQueue::new());
+        let mut mixer = AudioMixer::new(Arc::new(AudioSources::new(vec![Arc::clone(&audible), Arc::clone(&muted)])));
+
+        muted.set_muted(true);
+        audible.push(&[0.25, 0.5, 0.75, 1.0], &keep_running);
+        muted.push(&[0.5, 0.5, 0.5, 0.5], &keep_running);
+
+        let mut out = [9.0f32; 4];
+        mixer.callback(&mut out);
+
+        assert_eq!(out, [0.25, 0.5, 0.75, 1.0]);
+        // the muted movie is consumed at the same pace
+        assert_eq!(muted.len(), 0);
+    }
+
+    #[test]
+    fn test_detached_queue_discards_samples() {
+        let keep_running = AtomicBool::new(true);
+        let queue = Arc::new(AudioSampleQueue::new());
+        queue.push(&[0.5, 0.5], &keep_running);
+        assert_eq!(queue.len(), 0);
+
+        let mixer = AudioMixer::new(Arc::new(AudioSources::new(vec![Arc::clone(&queue)])));
+        queue.push(&[0.5, 0.5], &keep_running);
+        assert_eq!(queue.len(), 2);
+
+        drop(mixer);
+        assert_eq!(queue.len(), 0);
+    }
+
+    #[test]
+    fn test_sources_added_and_removed_while_mixing() {
+        let keep_running = AtomicBool::new(true);
+        let sources = Arc::new(AudioSources::default());
+        let mut mixer = AudioMixer::new(Arc::clone(&sources));
+
+        let queue = Arc::new(AudioSampleQueue::new());
+        sources.add(Arc::clone(&queue));
+        queue.push(&[0.5, 0.25], &keep_running);
+        let mut out = [0.0f32; 2];
+        mixer.callback(&mut out);
+        assert_eq!(out, [0.5, 0.25]);
+
+        sources.remove(&queue);
+        queue.push(&[0.5, 0.25], &keep_running);
+        assert_eq!(queue.len(), 0);
+        mixer.callback(&mut out);
+        assert_eq!(out, [0., 0.]);
+    }
+
+    #[test]
+    fn test_audio_follows_the_video_clock() {
+        let second = (AUDIO_SAMPLE_RATE * AUDIO_CHANNELS) as usize;
+        // in step, with the queue ahead of it accounted for
+        assert_eq!(sync_to_video(10.5, 10., second / 2, 1.), 0);
+        assert_eq!(sync_to_video(10.01, 10., 0, 1.), 0);
+        // early audio waits behind silence, late audio is cut
+        assert_eq!(sync_to_video(10.5, 10., 0, 1.), second as i64 / 2);
+        assert_eq!(sync_to_video(10., 10.5, 0, 1.), -(second as i64) / 2);
+        assert_eq!(sync_to_video(10., 10., second / 4, 1.), -(second as i64) / 4);
+        // at twice the speed a movie second plays in half a second
+        assert_eq!(sync_to_video(11., 10., 0, 2.), second as i64 / 2);
+        // that far off the video is seeking or looping
+        assert_eq!(sync_to_video(30., 10., 0, 1.), 0);
+    }
+
+    #[test]
+    fn test_atempo_chain_stays_in_range() {
+        assert_eq!(atempo_chain(2.), "atempo=2.000000");
+        assert_eq!(atempo_chain(8.), "atempo=8.000000");
+        assert_eq!(atempo_chain(0.25), "atempo=0.5,atempo=0.500000");
+        assert_eq!(atempo_chain(0.1), "atempo=0.5,atempo=0.5,atempo=0.5,atempo=0.800000");
+    }
+}
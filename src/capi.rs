@@ -0,0 +1,673 @@
+//! C entry points. Everything here works on an opaque `RspAnalyzer` handle and
+//! returns `MovieStatus` codes as plain integers. `include/rsplayer.h` is
+//! generated from this file by `build.rs`.
+//!
+//! All functions taking a handle must be called from the same thread, the one
+//! that created it. Commands are forwarded to the analyzer's control thread.
+use std::collections::VecDeque;
+use std::ffi::CStr;
+use std::ptr;
+
+use log::error;
+use rusty_ffmpeg::ffi;
+
+use crate::analyzer_state::AnalyzerContext;
+use crate::app::{load_movie, open_movie, open_movie_from_bytes, start_analyzer};
+use crate::command::{AnalyzerEvent, Command, ControlChannel};
+use crate::error::{MovieError, MovieStatus};
+use crate::movie_state::{EndBehavior, SeekMode};
+use crate::open_options::OpenOptions;
+use crate::orientation::Orientation;
+
+/// an analyzer and, once started, the channel to its control thread
+pub struct RspAnalyzer {
+    ctx: AnalyzerContext,
+    control: Option<ControlChannel>,
+    /// received from the control thread, not yet handed out by `rsp_poll_event`
+    events: VecDeque<AnalyzerEvent>,
+}
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RspCommandKind {
+    Pause,
+    Resume,
+    TogglePause,
+    Step,
+    /// uses `position` and `mode`
+    Seek,
+    /// uses `speed`
+    SetSpeed,
+    /// uses `movie_index`
+    SoloAudio,
+    /// uses `movie_index` and `stream_index`
+    SelectVideoStream,
+    /// uses `movie_index` and `stream_index`, `UINT32_MAX` turns subtitles off
+    SelectSubtitleStream,
+    /// uses `movie_index` and `end_behavior`
+    SetEndBehavior,
+    /// uses `enabled`
+    SetSynchronized,
+    /// uses `movie_index` and `offset`
+    SetTimeOffset,
+    /// uses `enabled`
+    SetMetrics,
+    StepBack,
+    /// uses `enabled`, 1 plays backwards
+    SetReverse,
+}
+
+/// a command for `rsp_send_command`, only the fields named by `kind` are read
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct RspCommand {
+    /// a `RspCommandKind`
+    pub kind: u32,
+    pub position: f64,
+    /// a `SeekMode`
+    pub mode: u32,
+    pub speed: f32,
+    pub movie_index: u32,
+    pub stream_index: u32,
+    /// an `EndBehavior`
+    pub end_behavior: u32,
+    /// seconds
+    pub offset: f64,
+    /// 0 or 1
+    pub enabled: i32,
+}
+
+impl RspCommandKind {
+    const ALL: [RspCommandKind; 15] = [
+        RspCommandKind::Pause,
+        RspCommandKind::Resume,
+        RspCommandKind::TogglePause,
+        RspCommandKind::Step,
+        RspCommandKind::Seek,
+        RspCommandKind::SetSpeed,
+        RspCommandKind::SoloAudio,
+        RspCommandKind::SelectVideoStream,
+        RspCommandKind::SelectSubtitleStream,
+        RspCommandKind::SetEndBehavior,
+        RspCommandKind::SetSynchronized,
+        RspCommandKind::SetTimeOffset,
+        RspCommandKind::SetMetrics,
+        RspCommandKind::StepBack,
+        RspCommandKind::SetReverse,
+    ];
+
+    fn from_raw(kind: u32) -> Option<RspCommandKind> {
+        RspCommandKind::ALL.iter().copied().find(|k| *k as u32 == kind)
+    }
+}
+
+fn seek_mode(mode: u32) -> Option<SeekMode> {
+    [SeekMode::Keyframe, SeekMode::Accurate].into_iter().find(|m| *m as u32 == mode)
+}
+
+fn end_behavior(behavior: u32) -> Option<EndBehavior> {
+    [EndBehavior::Loop, EndBehavior::StopOnLastFrame, EndBehavior::PingPong, EndBehavior::Close]
+        .into_iter()
+        .find(|b| *b as u32 == behavior)
+}
+
+/// the command a C caller asked for, None when `kind` or an enum field it uses is out of range
+fn to_command(command: &RspCommand) -> Option<Command> {
+    let kind = RspCommandKind::from_raw(command.kind)?;
+    Some(match kind {
+        RspCommandKind::Pause => Command::Pause,
+        RspCommandKind::Resume => Command::Resume,
+        RspCommandKind::TogglePause => Command::TogglePause,
+        RspCommandKind::Step => Command::Step,
+        RspCommandKind::Seek => Command::Seek { position: command.position, mode: seek_mode(command.mode)? },
+        RspCommandKind::SetSpeed => Command::SetSpeed(command.speed),
+        RspCommandKind::SoloAudio => Command::SoloAudio(command.movie_index as usize),
+        RspCommandKind::SelectVideoStream => Command::SelectVideoStream {
+            movie_index: command.movie_index as usize,
+            stream_index: command.stream_index as usize,
+        },
+        RspCommandKind::SelectSubtitleStream => Command::SelectSubtitleStream {
+            movie_index: command.movie_index as usize,
+            stream_index: match command.stream_index {
+                u32::MAX => None,
+                index => Some(index as usize),
+            },
+        },
+        RspCommandKind::SetEndBehavior => Command::SetEndBehavior {
+            movie_index: command.movie_index as usize,
+            behavior: end_behavior(command.end_behavior)?,
+        },
+        RspCommandKind::SetSynchronized => Command::SetSynchronized(command.enabled != 0),
+        RspCommandKind::SetTimeOffset => Command::SetTimeOffset {
+            movie_index: command.movie_index as usize,
+            offset: command.offset,
+        },
+        RspCommandKind::SetMetrics => Command::SetMetrics(command.enabled != 0),
+        RspCommandKind::StepBack => Command::StepBack,
+        RspCommandKind::SetReverse => Command::SetReverse(command.enabled != 0),
+    })
+}
+
+/// what `RspEvent.kind` says happened, see `AnalyzerEvent`
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RspEventKind {
+    /// `command` has taken effect
+    Applied,
+    /// the movies took `command` and carry it out next
+    Queued,
+    /// `command` does not apply to this kind of player
+    Ignored,
+    /// `command` was rejected, `reason` says why
+    Failed,
+    /// movie `movie_index` reached the end of the file
+    EndOfStream,
+    MovieAdded,
+    MovieRemoved,
+    MovieReplaced,
+    /// the frames of movie `movie_index` are now `width` x `height` in `format`
+    FormatChanged,
+    /// the control thread is done and every movie thread has been joined
+    Stopped,
+    /// movie `movie_index` carried out the `Queued` `command`
+    MovieApplied,
+    /// movie `movie_index` could not carry out the `Queued` `command`, `reason` says why
+    MovieFailed,
+}
+
+/// an event from `rsp_poll_event`, only the fields named by `kind` are set
+#[repr(C)]
+#[derive(Debug, Clone, Copy)]
+pub struct RspEvent {
+    /// a `RspEventKind`
+    pub kind: u32,
+    /// a `RspCommandKind`, `UINT32_MAX` for commands sent another way, such as `rsp_set_filter`
+    pub command: u32,
+    /// `UINT32_MAX` when the event is not about one movie
+    pub movie_index: u32,
+    pub width: i32,
+    pub height: i32,
+    /// an `AVPixelFormat` value
+    pub format: i32,
+    /// NUL terminated, cut short when it does not fit
+    pub reason: [libc::c_char; 256],
+}
+
+impl From<&AnalyzerEvent> for RspEvent {
+    fn from(event: &AnalyzerEvent) -> Self {
+        let mut out = RspEvent {
+            kind: RspEventKind::Stopped as u32,
+            command: u32::MAX,
+            movie_index: u32::MAX,
+            width: 0,
+            height: 0,
+            format: -1,
+            reason: [0; 256],
+        };
+        let about = |out: &mut RspEvent, kind: RspEventKind, command: &Command| {
+            out.kind = kind as u32;
+            out.command = command_kind(command).map_or(u32::MAX, |kind| kind as u32);
+            out.movie_index = command_movie_index(command).map_or(u32::MAX, |index| index as u32);
+        };
+        let because = |out: &mut RspEvent, reason: &str| {
+            let length = reason.len().min(out.reason.len() - 1);
+            for (to, from) in out.reason.iter_mut().zip(&reason.as_bytes()[..length]) {
+                *to = *from as libc::c_char;
+            }
+        };
+        match event {
+            AnalyzerEvent::Applied(command) => about(&mut out, RspEventKind::Applied, command),
+            AnalyzerEvent::Queued(command) => about(&mut out, RspEventKind::Queued, command),
+            AnalyzerEvent::Ignored(command) => about(&mut out, RspEventKind::Ignored, command),
+            AnalyzerEvent::Failed { command, reason } => {
+                about(&mut out, RspEventKind::Failed, command);
+                because(&mut out, reason);
+            }
+            AnalyzerEvent::MovieApplied { movie_index, command } => {
+                about(&mut out, RspEventKind::MovieApplied, command);
+                out.movie_index = *movie_index as u32;
+            }
+            AnalyzerEvent::MovieFailed { movie_index, command, reason } => {
+                about(&mut out, RspEventKind::MovieFailed, command);
+                out.movie_index = *movie_index as u32;
+                because(&mut out, reason);
+            }
+            AnalyzerEvent::EndOfStream { movie_index } => {
+                (out.kind, out.movie_index) = (RspEventKind::EndOfStream as u32, *movie_index as u32);
+            }
+            AnalyzerEvent::MovieAdded { movie_index } => {
+                (out.kind, out.movie_index) = (RspEventKind::MovieAdded as u32, *movie_index as u32);
+            }
+            AnalyzerEvent::MovieRemoved { movie_index } => {
+                (out.kind, out.movie_index) = (RspEventKind::MovieRemoved as u32, *movie_index as u32);
+            }
+            AnalyzerEvent::MovieReplaced { movie_index } => {
+                (out.kind, out.movie_index) = (RspEventKind::MovieReplaced as u32, *movie_index as u32);
+            }
+            AnalyzerEvent::FormatChanged { movie_index, to, .. } => {
+                (out.kind, out.movie_index) = (RspEventKind::FormatChanged as u32, *movie_index as u32);
+                (out.width, out.height, out.format) = (to.width, to.height, to.format);
+            }
+            AnalyzerEvent::Stopped => out.kind = RspEventKind::Stopped as u32,
+        }
+        out
+    }
+}
+
+/// the `RspCommandKind` a C caller sends for this command
+fn command_kind(command: &Command) -> Option<RspCommandKind> {
+    Some(match command {
+        Command::Pause => RspCommandKind::Pause,
+        Command::Resume => RspCommandKind::Resume,
+        Command::TogglePause => RspCommandKind::TogglePause,
+        Command::Step => RspCommandKind::Step,
+        Command::StepBack => RspCommandKind::StepBack,
+        Command::SetReverse(_) => RspCommandKind::SetReverse,
+        Command::Seek { .. } => RspCommandKind::Seek,
+        Command::SetSpeed(_) => RspCommandKind::SetSpeed,
+        Command::SoloAudio(_) => RspCommandKind::SoloAudio,
+        Command::SelectVideoStream { .. } => RspCommandKind::SelectVideoStream,
+        Command::SelectSubtitleStream { .. } => RspCommandKind::SelectSubtitleStream,
+        Command::SetSynchronized(_) => RspCommandKind::SetSynchronized,
+        Command::SetTimeOffset { .. } => RspCommandKind::SetTimeOffset,
+        Command::SetMetrics(_) => RspCommandKind::SetMetrics,
+        Command::SetEndBehavior { .. } => RspCommandKind::SetEndBehavior,
+        Command::SetFilter { .. } | Command::SetOrientation { .. } | Command::Quit => return None,
+    })
+}
+
+fn command_movie_index(command: &Command) -> Option<usize> {
+    match command {
+        Command::SoloAudio(movie_index)
+        | Command::SelectVideoStream { movie_index, .. }
+        | Command::SelectSubtitleStream { movie_index, .. }
+        | Command::SetTimeOffset { movie_index, .. }
+        | Command::SetEndBehavior { movie_index, .. }
+        | Command::SetFilter { movie_index, .. }
+        | Command::SetOrientation { movie_index, .. } => Some(*movie_index),
+        _ => None,
+    }
+}
+
+/// planes of a decoded, filtered frame. the pointers stay valid until the
+/// frame is passed to `rsp_frame_release`.
+#[repr(C)]
+#[derive(Debug)]
+pub struct RspFrame {
+    pub width: i32,
+    pub height: i32,
+    /// an `AVPixelFormat` value, yuv420p, nv12, nv21, rgb24, bgr24, rgba or bgra
+    pub format: i32,
+    pub pts: i64,
+    /// dts of the packet the frame came from
+    pub pkt_dts: i64,
+    /// size in bytes of the packet the frame came from, -1 when unknown
+    pub pkt_size: i32,
+    /// 'I', 'P', 'B' and so on, '?' when unknown
+    pub pict_type: libc::c_char,
+    /// 1 for a key frame
+    pub key_frame: i32,
+    /// `FF_DECODE_ERROR_*` bits, 0 for a clean frame
+    pub decode_error_flags: i32,
+    /// seconds to wait before showing this frame
+    pub delay: f64,
+    pub data: [*const u8; 4],
+    pub linesize: [i32; 4],
+    /// the underlying `AVFrame`, owned by the library
+    pub handle: *mut libc::c_void,
+}
+
+/// create an empty analyzer, free it with `rsp_analyzer_free`
+#[no_mangle]
+pub extern "C" fn rsp_analyzer_new() -> *mut RspAnalyzer {
+    Box::into_raw(Box::new(RspAnalyzer { ctx: AnalyzerContext::new(), control: None, events: VecDeque::new() }))
+}
+
+/// stop the analyzer if it is running and free every movie
+#[no_mangle]
+pub unsafe extern "C" fn rsp_analyzer_free(analyzer: *mut RspAnalyzer) {
+    if analyzer.is_null() {
+        return;
+    }
+    let mut analyzer = Box::from_raw(analyzer);
+    stop(&mut analyzer);
+    AnalyzerContext::close(analyzer.ctx);
+}
+
+/// open a movie file and add it to the analyzer. only valid before `rsp_start`,
+/// `rsp_replace_movie` swaps movies while it runs.
+#[no_mangle]
+pub unsafe extern "C" fn rsp_open_movie(analyzer: *mut RspAnalyzer, filepath: *const libc::c_char) -> i32 {
+    let analyzer = match analyzer.as_mut() {
+        Some(analyzer) if !filepath.is_null() => analyzer,
+        _ => return MovieStatus::InvalidArgument as i32,
+    };
+    if analyzer.control.is_some() {
+        return MovieStatus::AlreadyStarted as i32;
+    }
+    status(open_movie(&mut analyzer.ctx, filepath))
+}
+
+/// open a movie from `len` bytes at `data` and add it to the analyzer. only
+/// valid before `rsp_start`. the bytes are copied, the caller keeps ownership of `data`.
+#[no_mangle]
+pub unsafe extern "C" fn rsp_open_movie_from_memory(analyzer: *mut RspAnalyzer, data: *const u8, len: usize) -> i32 {
+    let analyzer = match analyzer.as_mut() {
+        Some(analyzer) if !data.is_null() => analyzer,
+        _ => return MovieStatus::InvalidArgument as i32,
+    };
+    if analyzer.control.is_some() {
+        return MovieStatus::AlreadyStarted as i32;
+    }
+    let data = std::slice::from_raw_parts(data, len);
+    status(open_movie_from_bytes(&mut analyzer.ctx, data, &OpenOptions::default()).map(|_| ()))
+}
+
+/// stop and drop a movie, the movies after it move up one index
+#[no_mangle]
+pub unsafe extern "C" fn rsp_remove_movie(analyzer: *mut RspAnalyzer, movie_index: u32) -> i32 {
+    let analyzer = match analyzer.as_mut() {
+        Some(analyzer) => analyzer,
+        None => return MovieStatus::InvalidArgument as i32,
+    };
+    status(analyzer.ctx.remove_movie(movie_index as usize))
+}
+
+/// open a movie file in place of the one at `movie_index`, it takes over
+/// that movie's position and audio
+#[no_mangle]
+pub unsafe extern "C" fn rsp_replace_movie(analyzer: *mut RspAnalyzer, movie_index: u32, filepath: *const libc::c_char) -> i32 {
+    let analyzer = match analyzer.as_mut() {
+        Some(analyzer) if !filepath.is_null() => analyzer,
+        _ => return MovieStatus::InvalidArgument as i32,
+    };
+    if movie_index as usize >= analyzer.ctx.movie_count() as usize {
+        return status(Err(MovieError::InvalidMovieIndex(movie_index as usize)));
+    }
+    let movie = match load_movie(filepath, &OpenOptions::default()) {
+        Ok((movie, _)) => movie,
+        Err(e) => return status(Err(e)),
+    };
+    status(analyzer.ctx.replace_movie(movie_index as usize, movie))
+}
+
+/// number of movies attached, or -1 for a null handle
+#[no_mangle]
+pub unsafe extern "C" fn rsp_movie_count(analyzer: *const RspAnalyzer) -> i32 {
+    match analyzer.as_ref() {
+        Some(analyzer) => analyzer.ctx.movie_count() as i32,
+        None => -1,
+    }
+}
+
+/// 1 once the movie stopped at the end of the file, 0 while it is playing,
+/// -1 for a null handle or a bad index
+#[no_mangle]
+pub unsafe extern "C" fn rsp_has_ended(analyzer: *const RspAnalyzer, movie_index: u32) -> i32 {
+    match analyzer.as_ref().and_then(|a| a.ctx.movie(movie_index as usize)) {
+        Some(movie) => movie.has_ended() as i32,
+        None => -1,
+    }
+}
+
+/// start the packet, decode and control threads for every movie
+#[no_mangle]
+pub unsafe extern "C" fn rsp_start(analyzer: *mut RspAnalyzer) -> i32 {
+    let analyzer = match analyzer.as_mut() {
+        Some(analyzer) => analyzer,
+        None => return MovieStatus::InvalidArgument as i32,
+    };
+    if analyzer.control.is_some() {
+        return MovieStatus::AlreadyStarted as i32;
+    }
+    analyzer.control = Some(start_analyzer(&mut analyzer.ctx));
+    MovieStatus::Ok as i32
+}
+
+/// stop and join every thread. movies stay attached.
+#[no_mangle]
+pub unsafe extern "C" fn rsp_stop(analyzer: *mut RspAnalyzer) -> i32 {
+    let analyzer = match analyzer.as_mut() {
+        Some(analyzer) => analyzer,
+        None => return MovieStatus::InvalidArgument as i32,
+    };
+    if analyzer.control.is_none() {
+        return MovieStatus::NotStarted as i32;
+    }
+    stop(analyzer);
+    MovieStatus::Ok as i32
+}
+
+/// forward a command to the control thread. returns `InvalidArgument` for a
+/// `kind`, `mode` or `end_behavior` that is not one of the enum's values.
+#[no_mangle]
+pub unsafe extern "C" fn rsp_send_command(analyzer: *mut RspAnalyzer, command: *const RspCommand) -> i32 {
+    let (analyzer, command) = match (analyzer.as_mut(), command.as_ref()) {
+        (Some(analyzer), Some(command)) => (analyzer, command),
+        _ => return MovieStatus::InvalidArgument as i32,
+    };
+    let command = match to_command(command) {
+        Some(command) => command,
+        None => return MovieStatus::InvalidArgument as i32,
+    };
+    match analyzer.control.as_ref() {
+        Some(control) => match control.send(command) {
+            Ok(_) => MovieStatus::Ok as i32,
+            Err(_) => MovieStatus::NotStarted as i32,
+        },
+        None => MovieStatus::NotStarted as i32,
+    }
+}
+
+/// run a movie's video through a libavfilter description such as
+/// `"hqdn3d,unsharp,eq=contrast=1.2"`, NULL shows the frames as decoded.
+/// works before and after `rsp_start`. returns `InvalidFilter` when the
+/// description does not parse or configure, the movie keeps its filter then.
+#[no_mangle]
+pub unsafe extern "C" fn rsp_set_filter(analyzer: *mut RspAnalyzer, movie_index: u32, description: *const libc::c_char) -> i32 {
+    let movie = match analyzer.as_ref().and_then(|a| a.ctx.movie(movie_index as usize)) {
+        Some(movie) => movie,
+        None => return MovieStatus::InvalidArgument as i32,
+    };
+    let description = match description.is_null() {
+        true => None,
+        false => match CStr::from_ptr(description).to_str() {
+            Ok(description) => Some(description),
+            Err(_) => return MovieStatus::InvalidArgument as i32,
+        },
+    };
+    match movie.set_filter_description(description) {
+        Ok(_) => MovieStatus::Ok as i32,
+        Err(e) => {
+            error!("🚩 {}", e);
+            MovieStatus::from(&e) as i32
+        }
+    }
+}
+
+/// show a movie turned `rotation` degrees clockwise, a multiple of 90, and
+/// mirrored when `flip` is non-zero. a negative `rotation` goes back to what
+/// the stream's display matrix or `rotate` tag asks for. works before and
+/// after `rsp_start`.
+#[no_mangle]
+pub unsafe extern "C" fn rsp_set_orientation(analyzer: *mut RspAnalyzer, movie_index: u32, rotation: i32, flip: i32) -> i32 {
+    let movie = match analyzer.as_ref().and_then(|a| a.ctx.movie(movie_index as usize)) {
+        Some(movie) => movie,
+        None => return MovieStatus::InvalidArgument as i32,
+    };
+    if rotation >= 0 && rotation % 90 != 0 {
+        return MovieStatus::InvalidArgument as i32;
+    }
+    let orientation = match rotation < 0 {
+        true => None,
+        false => Some(Orientation::new(rotation as f64, flip != 0)),
+    };
+    movie.set_orientation(orientation);
+    MovieStatus::Ok as i32
+}
+
+/// fill `frame` with the next frame that is due for `movie_index`.
+/// returns `NoFrame` when nothing is due yet, `frame` is untouched then.
+#[no_mangle]
+pub unsafe extern "C" fn rsp_next_frame(analyzer: *const RspAnalyzer, movie_index: u32, frame: *mut RspFrame) -> i32 {
+    let (analyzer, out) = match (analyzer.as_ref(), frame.as_mut()) {
+        (Some(analyzer), Some(out)) => (analyzer, out),
+        _ => return MovieStatus::InvalidArgument as i32,
+    };
+    if movie_index >= analyzer.ctx.movie_count() as u32 {
+        return MovieStatus::InvalidArgument as i32;
+    }
+    let (delay, av_frame) = analyzer.ctx.dequeue_frame(movie_index as u8);
+    let (av_frame, info) = match av_frame.and_then(|(f, info)| Some((f.as_mut()?, info))) {
+        Some(decoded) => decoded,
+        None => return MovieStatus::NoFrame as i32,
+    };
+    out.width = av_frame.width;
+    out.height = av_frame.height;
+    out.format = av_frame.format;
+    out.pts = av_frame.pts;
+    out.pkt_dts = info.dts;
+    out.pkt_size = info.packet_size;
+    out.pict_type = info.pict_type as u8 as libc::c_char;
+    out.key_frame = info.key_frame as i32;
+    out.decode_error_flags = info.decode_error_flags;
+    out.delay = delay;
+    for plane in 0..4 {
+        out.data[plane] = av_frame.data[plane];
+        out.linesize[plane] = av_frame.linesize[plane];
+    }
+    out.handle = av_frame as *mut ffi::AVFrame as *mut libc::c_void;
+    MovieStatus::Ok as i32
+}
+
+/// fill `event` with the oldest event the analyzer reported and not handed out yet.
+/// returns `NoEvent` when there is none, `event` is untouched then. the events
+/// up to `Stopped` stay available after `rsp_stop`.
+#[no_mangle]
+pub unsafe extern "C" fn rsp_poll_event(analyzer: *mut RspAnalyzer, event: *mut RspEvent) -> i32 {
+    let (analyzer, out) = match (analyzer.as_mut(), event.as_mut()) {
+        (Some(analyzer), Some(out)) => (analyzer, out),
+        _ => return MovieStatus::InvalidArgument as i32,
+    };
+    if let Some(control) = analyzer.control.as_ref() {
+        analyzer.events.extend(control.poll_events());
+    }
+    match analyzer.events.pop_front() {
+        Some(next) => {
+            *out = RspEvent::from(&next);
+            MovieStatus::Ok as i32
+        }
+        None => MovieStatus::NoEvent as i32,
+    }
+}
+
+/// give a frame from `rsp_next_frame` back to the library
+#[no_mangle]
+pub unsafe extern "C" fn rsp_frame_release(frame: *mut RspFrame) {
+    let frame = match frame.as_mut() {
+        Some(frame) => frame,
+        None => return,
+    };
+    let mut av_frame = frame.handle as *mut ffi::AVFrame;
+    if !av_frame.is_null() {
+        ffi::av_frame_free(&mut av_frame);
+    }
+    frame.handle = ptr::null_mut();
+    frame.data = [ptr::null(); 4];
+}
+
+fn stop(analyzer: &mut RspAnalyzer) {
+    if let Some(control) = analyzer.control.take() {
+        let _ = control.send(Command::Quit);
+        analyzer.ctx.join();
+        analyzer.events.extend(control.poll_events());
+    }
+}
+
+fn status(result: Result<(), MovieError>) -> i32 {
+    if let Err(e) = &result {
+        error!("🚩 {}", e);
+    }
+    MovieStatus::from(&result) as i32
+}
+
+#[cfg(test)]
+mod tests {
+    use std::ffi::CString;
+    use std::thread::sleep;
+    use std::time::{Duration, Instant};
+
+    use super::*;
+
+    #[test]
+    fn test_open_play_and_release_a_frame() {
+        unsafe {
+            let analyzer = rsp_analyzer_new();
+            let filepath = CString::new("test_vid.mp4").unwrap();
+            assert_eq!(rsp_open_movie(analyzer, filepath.as_ptr()), MovieStatus::Ok as i32);
+            assert_eq!(rsp_movie_count(analyzer), 1);
+            assert_eq!(rsp_stop(analyzer), MovieStatus::NotStarted as i32);
+            assert_eq!(rsp_start(analyzer), MovieStatus::Ok as i32);
+            assert_eq!(rsp_start(analyzer), MovieStatus::AlreadyStarted as i32);
+            let resume = RspCommand { kind: RspCommandKind::Resume as u32, ..std::mem::zeroed() };
+            assert_eq!(rsp_send_command(analyzer, &resume), MovieStatus::Ok as i32);
+
+            let mut frame: RspFrame = std::mem::zeroed();
+            assert_eq!(rsp_next_frame(analyzer, 1, &mut frame), MovieStatus::InvalidArgument as i32);
+            let started = Instant::now();
+            let mut status = MovieStatus::NoFrame as i32;
+            while status == MovieStatus::NoFrame as i32 && started.elapsed() < Duration::from_secs(5) {
+                status = rsp_next_frame(analyzer, 0, &mut frame);
+                sleep(Duration::from_millis(5));
+            }
+            assert_eq!(status, MovieStatus::Ok as i32);
+            assert_eq!((frame.width, frame.height), (1280, 720));
+            assert!(!frame.data[0].is_null() && frame.linesize[0] >= 1280);
+            assert!(!frame.handle.is_null());
+            rsp_frame_release(&mut frame);
+            assert!(frame.handle.is_null() && frame.data[0].is_null());
+
+            assert_eq!(rsp_stop(analyzer), MovieStatus::Ok as i32);
+            let mut event: RspEvent = std::mem::zeroed();
+            let mut kinds = vec![];
+            while rsp_poll_event(analyzer, &mut event) == MovieStatus::Ok as i32 {
+                kinds.push((event.kind, event.command));
+            }
+            assert_eq!(kinds.first(), Some(&(RspEventKind::Applied as u32, RspCommandKind::Resume as u32)));
+            assert_eq!(kinds.last(), Some(&(RspEventKind::Stopped as u32, u32::MAX)));
+            assert_eq!(rsp_poll_event(analyzer, &mut event), MovieStatus::NoEvent as i32);
+            rsp_analyzer_free(analyzer);
+        }
+    }
+
+    #[test]
+    fn test_out_of_range_commands_are_rejected() {
+        unsafe {
+            let analyzer = rsp_analyzer_new();
+            let filepath = CString::new("test_vid.mp4").unwrap();
+            assert_eq!(rsp_open_movie(analyzer, filepath.as_ptr()), MovieStatus::Ok as i32);
+            assert_eq!(rsp_start(analyzer), MovieStatus::Ok as i32);
+            let unknown = RspCommand { kind: 99, ..std::mem::zeroed() };
+            let seek = RspCommand { kind: RspCommandKind::Seek as u32, mode: 7, ..std::mem::zeroed() };
+            let end = RspCommand { kind: RspCommandKind::SetEndBehavior as u32, end_behavior: 4, ..std::mem::zeroed() };
+            for command in [unknown, seek, end] {
+                assert_eq!(rsp_send_command(analyzer, &command), MovieStatus::InvalidArgument as i32);
+            }
+            let seek = RspCommand { kind: RspCommandKind::Seek as u32, mode: SeekMode::Accurate as u32, ..std::mem::zeroed() };
+            assert_eq!(rsp_send_command(analyzer, &seek), MovieStatus::Ok as i32);
+            rsp_analyzer_free(analyzer);
+        }
+    }
+
+    #[test]
+    fn test_null_handles_are_rejected() {
+        unsafe {
+            let mut frame: RspFrame = std::mem::zeroed();
+            assert_eq!(rsp_next_frame(ptr::null(), 0, &mut frame), MovieStatus::InvalidArgument as i32);
+            assert_eq!(rsp_start(ptr::null_mut()), MovieStatus::InvalidArgument as i32);
+            assert_eq!(rsp_movie_count(ptr::null()), -1);
+            rsp_frame_release(ptr::null_mut());
+            rsp_analyzer_free(ptr::null_mut());
+        }
+    }
+}
@@ -0,0 +1,93 @@
+use std::sync::mpsc::{Receiver, SendError, Sender};
+
+use crate::filter::FilterInput;
+use crate::movie_state::{EndBehavior, SeekMode};
+use crate::orientation::Orientation;
+
+/// control messages executed by the analyzer's control thread
+#[derive(Debug, Clone, PartialEq)]
+pub enum Command {
+    Pause,
+    Resume,
+    TogglePause,
+    /// advance one frame, only while paused
+    Step,
+    /// go back one frame, only while paused
+    StepBack,
+    /// play backwards from the frame on screen, false plays forwards again
+    SetReverse(bool),
+    /// jump to `position` seconds from the start of every movie
+    Seek { position: f64, mode: SeekMode },
+    SetSpeed(f32),
+    /// make one movie the only audible one
+    SoloAudio(usize),
+    /// decode another video stream of the same file
+    SelectVideoStream { movie_index: usize, stream_index: usize },
+    /// decode a subtitle stream of the same file, None turns subtitles off
+    SelectSubtitleStream { movie_index: usize, stream_index: Option<usize> },
+    /// present the frame with the same offset-adjusted pts in every movie on each tick
+    SetSynchronized(bool),
+    /// shift one movie against the others, in seconds, see `MovieState::set_time_offset`
+    SetTimeOffset { movie_index: usize, offset: f64 },
+    /// measure PSNR and SSIM of every movie against movie 0, see `AnalyzerContext::metrics_history`
+    SetMetrics(bool),
+    /// change what a movie does when it reaches the end of the file
+    SetEndBehavior { movie_index: usize, behavior: EndBehavior },
+    /// run a movie's video through a libavfilter description, None for no filtering.
+    /// fails when the description does not parse or configure, the old graph stays.
+    SetFilter { movie_index: usize, description: Option<String> },
+    /// show a movie turned and mirrored this way whatever its stream asks for,
+    /// None goes back to the stream's display matrix or `rotate` tag
+    SetOrientation { movie_index: usize, orientation: Option<Orientation> },
+    /// stop the control thread and join all movie threads
+    Quit,
+}
+
+/// what the control thread reports back to the caller
+#[derive(Debug, Clone, PartialEq)]
+pub enum AnalyzerEvent {
+    /// the command has taken effect
+    Applied(Command),
+    /// the movies took the command and their packet threads carry it out next,
+    /// for seeks, stepping back, reversing and switching video streams.
+    /// every movie that took it follows up with `MovieApplied` or `MovieFailed`.
+    Queued(Command),
+    /// the command does not apply to this kind of player
+    Ignored(Command),
+    /// the command was rejected
+    Failed { command: Command, reason: String },
+    /// the packet thread of a movie carried out a `Queued` command
+    MovieApplied { movie_index: usize, command: Command },
+    /// the packet thread of a movie could not carry out a `Queued` command
+    MovieFailed { movie_index: usize, command: Command, reason: String },
+    /// a movie reached the end of the file, sent before its `EndBehavior` kicks in
+    EndOfStream { movie_index: usize },
+    /// `AnalyzerContext::add_movie` attached a movie
+    MovieAdded { movie_index: usize },
+    /// `AnalyzerContext::remove_movie` dropped a movie, the ones after it moved up one index
+    MovieRemoved { movie_index: usize },
+    /// `AnalyzerContext::replace_movie` swapped the movie at this index
+    MovieReplaced { movie_index: usize },
+    /// a movie's frames changed size, pixel format, aspect or colorspace mid-stream
+    /// and its filter graph was rebuilt for them
+    FormatChanged { movie_index: usize, from: FilterInput, to: FilterInput },
+    /// the control thread is done and every movie thread has been joined
+    Stopped,
+}
+
+/// the caller's end of a running analyzer or movie
+pub struct ControlChannel {
+    pub commands: Sender<Command>,
+    pub events: Receiver<AnalyzerEvent>,
+}
+
+impl ControlChannel {
+    pub fn send(&self, command: Command) -> Result<(), SendError<Command>> {
+        self.commands.send(command)
+    }
+
+    /// all events that arrived since the last call, without blocking
+    pub fn poll_events(&self) -> Vec<AnalyzerEvent> {
+        self.events.try_iter().collect()
+    }
+}
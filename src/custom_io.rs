@@ -0,0 +1,103 @@
+use std::io::{Read, Seek, SeekFrom};
+
+use log::debug;
+use rusty_ffmpeg::ffi;
+
+use crate::error::AvError;
+
+/// size of the buffer ffmpeg reads through, same as the avio default
+const IO_BUFFER_SIZE: usize = 32 * 1024;
+
+pub trait ReadSeek: Read + Seek {}
+impl<T: Read + Seek> ReadSeek for T {}
+
+type Source = Box<dyn ReadSeek + Send>;
+
+/// an `AVIOContext` that pulls from a rust `Read + Seek` source.
+/// the format context using it must be closed before this is dropped.
+pub(crate) struct CustomIo {
+    pub ptr: *mut ffi::AVIOContext,
+    source: *mut Source,
+}
+unsafe impl Send for CustomIo {}
+
+impl CustomIo {
+    pub unsafe fn new(source: Source) -> Result<CustomIo, AvError> {
+        let buffer = ffi::av_malloc(IO_BUFFER_SIZE) as *mut u8;
+        if buffer.is_null() {
+            return Err(AvError::from_code(ffi::AVERROR(ffi::ENOMEM)));
+        }
+        let source = Box::into_raw(Box::new(source));
+        let ptr = ffi::avio_alloc_context(
+            buffer,
+            IO_BUFFER_SIZE as i32,
+            0,
+            source as *mut libc::c_void,
+            Some(read_packet),
+            None,
+            Some(seek),
+        );
+        if ptr.is_null() {
+            ffi::av_free(buffer as *mut _);
+            drop(Box::from_raw(source));
+            return Err(AvError::from_code(ffi::AVERROR(ffi::ENOMEM)));
+        }
+        Ok(CustomIo { ptr, source })
+    }
+}
+
+impl Drop for CustomIo {
+    fn drop(&mut self) {
+        unsafe {
+            // avio may have swapped the buffer, free whatever it holds now
+            if !self.ptr.is_null() {
+                ffi::av_freep(&mut (*self.ptr).buffer as *mut *mut u8 as *mut libc::c_void);
+                ffi::avio_context_free(&mut self.ptr);
+            }
+            drop(Box::from_raw(self.source));
+        }
+        debug!("freed custom io context");
+    }
+}
+
+unsafe extern "C" fn read_packet(opaque: *mut libc::c_void, buf: *mut u8, buf_size: i32) -> i32 {
+    let source = &mut *(opaque as *mut Source);
+    let buf = std::slice::from_raw_parts_mut(buf, buf_size as usize);
+    match source.read(buf) {
+        Ok(0) => ffi::AVERROR_EOF,
+        Ok(read) => read as i32,
+        Err(e) => {
+            debug!("custom io read failed: {}", e);
+            ffi::AVERROR(libc::EIO)
+        }
+    }
+}
+
+unsafe extern "C" fn seek(opaque: *mut libc::c_void, offset: i64, whence: i32) -> i64 {
+    let source = &mut *(opaque as *mut Source);
+    let whence = whence & !(ffi::AVSEEK_FORCE as i32);
+    if whence == ffi::AVSEEK_SIZE as i32 {
+        return source_size(source).unwrap_or(-1);
+    }
+    let pos = match whence {
+        libc::SEEK_SET => SeekFrom::Start(offset as u64),
+        libc::SEEK_CUR => SeekFrom::Current(offset),
+        libc::SEEK_END => SeekFrom::End(offset),
+        _ => return ffi::AVERROR(libc::EINVAL) as i64,
+    };
+    match source.seek(pos) {
+        Ok(pos) => pos as i64,
+        Err(e) => {
+            debug!("custom io seek failed: {}", e);
+            ffi::AVERROR(libc::EIO) as i64
+        }
+    }
+}
+
+/// total length of the source, leaving the position where it was
+fn source_size(source: &mut Source) -> Option<i64> {
+    let current = source.stream_position().ok()?;
+    let size = source.seek(SeekFrom::End(0)).ok()?;
+    source.seek(SeekFrom::Start(current)).ok()?;
+    Some(size as i64)
+}
@@ -7,27 +7,47 @@ use ::rusty_ffmpeg::ffi;
 use crate::movie_state::{movie_state_enqueue_frame, CodecContextWrapper, MovieState};
 
 
-pub unsafe fn decode_thread(movie_state: Arc<&mut MovieState>, keep_running: Arc<AtomicBool>) {
+pub unsafe fn decode_thread(movie_state: Arc<MovieState>, keep_running: Arc<AtomicBool>) {
     let frame = ffi::av_frame_alloc()
         .as_mut()
         .expect("failed to allocated memory for AVFrame");
     loop {
         let mut locked_videoqueue = movie_state.videoqueue.lock().unwrap();
         if let Some(packet) = locked_videoqueue.front_mut() {
+            // the packet thread hit the end of the file
+            if packet.ptr.is_null() {
+                drain_decoder(&movie_state, frame, &keep_running);
+                locked_videoqueue.pop_front();
+                continue;
+            }
             // !Note that AVPacket.pts is in AVStream.time_base units, not AVCodecContext.time_base units.
             if let Ok(_) = decode_packet(packet.ptr, &movie_state.video_ctx, frame) {
+                movie_state.note_picture_type(frame);
                 {
                     // let time_base = movie_state.video_stream.lock().unwrap().ptr.as_ref().unwrap().time_base;
                     // delay *= (time_base.num as f64) / (time_base.den as f64);
                 }
 
+                if !reached_seek_target(&movie_state, frame) {
+                    ffi::av_frame_unref(frame as *mut _);
+                    ffi::av_packet_unref(packet.ptr);
+                    ffi::av_packet_free(&mut packet.ptr as *mut *mut _);
+                    locked_videoqueue.pop_front();
+                    continue;
+                }
+
+                movie_state.last_decoded_pts.store(frame.pts, std::sync::atomic::Ordering::Relaxed);
                 // this returns an error when the queue is full.
                 while let Err(_) = movie_state_enqueue_frame(&movie_state.picq, frame) {
                     // ::std::thread::yield_now();
-                    ::std::thread::sleep(::std::time::Duration::from_millis(70));
+                    ::std::thread::sleep(movie_state.frame_queue_wait());
                     if ! keep_running.load(std::sync::atomic::Ordering::Relaxed) {
                         break;
                     }
+                    // a seek is waiting on the packet queue lock, this frame is stale anyway
+                    if movie_state.is_seeking() {
+                        break;
+                    }
                 }
             }
             ffi::av_frame_unref(frame as *mut _);
@@ -45,6 +65,47 @@ pub unsafe fn decode_thread(movie_state: Arc<&mut MovieState>, keep_running: Arc
     ffi::av_frame_free(&mut (frame as *mut _) as *mut *mut _);
 }
 
+/// send the NULL packet so the decoder gives up the frames it is holding back,
+/// then reset it so it takes packets again after a rewind
+unsafe fn drain_decoder(movie_state: &MovieState, frame: &mut ffi::AVFrame, keep_running: &AtomicBool) {
+    let codec_context = movie_state.video_ctx.lock().unwrap();
+    let response = ffi::avcodec_send_packet(codec_context.ptr, std::ptr::null());
+    if response < 0 {
+        error!("Error while draining the decoder. {:?}", ffi::av_err2str(response));
+    }
+    while ffi::avcodec_receive_frame(codec_context.ptr, frame) >= 0 {
+        frame.format = (*codec_context.ptr).pix_fmt;
+        movie_state.note_picture_type(frame);
+        if reached_seek_target(movie_state, frame) {
+            movie_state.last_decoded_pts.store(frame.pts, std::sync::atomic::Ordering::Relaxed);
+            while let Err(_) = movie_state_enqueue_frame(&movie_state.picq, frame) {
+                ::std::thread::sleep(movie_state.frame_queue_wait());
+                if !keep_running.load(std::sync::atomic::Ordering::Relaxed) || movie_state.is_seeking() {
+                    break;
+                }
+            }
+        }
+        ffi::av_frame_unref(frame as *mut _);
+    }
+    debug!("drained the video decoder");
+    ffi::avcodec_flush_buffers(codec_context.ptr);
+    movie_state.video_drained.store(true, std::sync::atomic::Ordering::Relaxed);
+}
+
+/// after an accurate seek, frames before the target are decoded but never shown
+unsafe fn reached_seek_target(movie_state: &MovieState, frame: &ffi::AVFrame) -> bool {
+    let target = movie_state.seek_target.load(std::sync::atomic::Ordering::Relaxed);
+    if target == ffi::AV_NOPTS_VALUE {
+        return true;
+    }
+    let pts = frame.best_effort_timestamp;
+    if pts != ffi::AV_NOPTS_VALUE && pts < target {
+        return false;
+    }
+    movie_state.seek_target.store(ffi::AV_NOPTS_VALUE, std::sync::atomic::Ordering::Relaxed);
+    true
+}
+
 fn decode_packet(
     packet: *mut ffi::AVPacket,
     arc_codec_context: &Mutex<CodecContextWrapper>,
@@ -73,17 +134,6 @@ fn decode_packet(
                 "Error while receiving a frame from the decoder.",
             ));
         }
-        // let codec_context = unsafe{codec_context.as_ref().unwrap()};
-        debug!(
-            "Frame {} (type={}, size={} bytes) pts {} key_frame {} [DTS {}]",
-            codec_context.frame_number,
-            unsafe { ffi::av_get_picture_type_char(frame.pict_type) },
-            frame.pkt_size,
-            // frame.pts * codec_context.time_base.num as i64 / codec_context.time_base.den as i64,
-            unsafe {ffi::av_rescale_q(frame.pts, codec_context.time_base, ffi::AVRational { num: 1, den: 1 })},
-            frame.key_frame,
-            frame.pkt_dts
-        );
         frame.format = codec_context.pix_fmt;
         return Ok(());
     }
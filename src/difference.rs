@@ -0,0 +1,175 @@
+//! per-pixel difference between two frames, movie 0 against movie 1 in the
+//! sdl player. besides the RGBA picture a `Difference` keeps the largest
+//! difference and how many pixels passed the threshold.
+use rusty_ffmpeg::ffi;
+
+use crate::error::AvError;
+use crate::metrics::{yuv420_planes, Plane, Yuv420Frame};
+
+/// how the differences are turned into a picture
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum DifferenceMode {
+    /// grey, brighter is more different
+    Amplified,
+    /// black through blue, green and yellow to red
+    Heatmap,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct DifferenceOptions {
+    pub mode: DifferenceMode,
+    /// differences are multiplied by this before they are drawn
+    pub gain: f32,
+    /// differences below this are drawn black and not counted
+    pub threshold: u8,
+}
+
+impl Default for DifferenceOptions {
+    fn default() -> Self {
+        DifferenceOptions { mode: DifferenceMode::Amplified, gain: 8., threshold: 0 }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct Difference {
+    pub width: usize,
+    pub height: usize,
+    /// the largest of the Y, U and V differences of each pixel, chroma taken
+    /// from the sample the pixel shares
+    pub values: Vec<u8>,
+    /// `values` drawn with the options, 4 bytes a pixel
+    pub rgba: Vec<u8>,
+    pub max: u8,
+    pub mean: f64,
+    /// pixels at or over the threshold
+    pub significant: usize,
+}
+
+impl Difference {
+    /// differences of two sets of 4:2:0 planes of the same size
+    pub fn from_planes(a: &[Plane; 3], b: &[Plane; 3], options: &DifferenceOptions) -> Difference {
+        let (width, height) = (a[0].width.min(b[0].width), a[0].height.min(b[0].height));
+        let mut values = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let (ay, by) = (&a[0].data[y * a[0].stride..], &b[0].data[y * b[0].stride..]);
+            let chroma_y = y / 2;
+            let (au, bu) = (&a[1].data[chroma_y * a[1].stride..], &b[1].data[chroma_y * b[1].stride..]);
+            let (av, bv) = (&a[2].data[chroma_y * a[2].stride..], &b[2].data[chroma_y * b[2].stride..]);
+            for x in 0..width {
+                let c = x / 2;
+                let d = ay[x].abs_diff(by[x]).max(au[c].abs_diff(bu[c])).max(av[c].abs_diff(bv[c]));
+                values.push(d);
+            }
+        }
+        let mut difference = Difference {
+            width,
+            height,
+            max: values.iter().copied().max().unwrap_or(0),
+            mean: values.iter().map(|&d| d as f64).sum::<f64>() / values.len().max(1) as f64,
+            significant: 0,
+            values,
+            rgba: vec![],
+        };
+        difference.render(options);
+        difference
+    }
+
+    /// draw `values` again after the mode, gain or threshold changed
+    pub fn render(&mut self, options: &DifferenceOptions) {
+        self.significant = self.values.iter().filter(|&&d| d >= options.threshold.max(1)).count();
+        self.rgba = self
+            .values
+            .iter()
+            .flat_map(|&d| {
+                let [r, g, b] = match d < options.threshold {
+                    true => [0, 0, 0],
+                    false => colour(d, options),
+                };
+                [r, g, b, 255]
+            })
+            .collect();
+    }
+}
+
+fn colour(d: u8, options: &DifferenceOptions) -> [u8; 3] {
+    let amount = (d as f32 * options.gain / 255.).clamp(0., 1.);
+    match options.mode {
+        DifferenceMode::Amplified => [(amount * 255.).round() as u8; 3],
+        DifferenceMode::Heatmap => heat(amount),
+    }
+}
+
+/// 0 is black, 1 is red
+fn heat(amount: f32) -> [u8; 3] {
+    let stops: [[f32; 3]; 5] = [[0., 0., 0.], [0., 0., 255.], [0., 255., 0.], [255., 255., 0.], [255., 0., 0.]];
+    let scaled = amount * (stops.len() - 1) as f32;
+    let i = (scaled as usize).min(stops.len() - 2);
+    let t = scaled - i as f32;
+    [0, 1, 2].map(|c| (stops[i][c] + (stops[i + 1][c] - stops[i][c]) * t).round() as u8)
+}
+
+/// the per pixel luma and chroma error of `distorted` against `reference`,
+/// rendered as set by `options`. `distorted` is scaled to the reference's size.
+pub unsafe fn difference_frames(
+    reference: *const ffi::AVFrame,
+    distorted: *const ffi::AVFrame,
+    options: &DifferenceOptions,
+) -> Result<Difference, AvError> {
+    if reference.is_null() || distorted.is_null() {
+        return Err(AvError::from_code(ffi::AVERROR(ffi::EINVAL)));
+    }
+    let (width, height) = ((*reference).width, (*reference).height);
+    let reference = Yuv420Frame::new(reference, width, height)?;
+    let distorted = Yuv420Frame::new(distorted, width, height)?;
+    Ok(Difference::from_planes(&yuv420_planes(&*reference.ptr), &yuv420_planes(&*distorted.ptr), options))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn planes<'a>(y: &'a [u8], c: &'a [u8], width: usize) -> [Plane<'a>; 3] {
+        let chroma = (width + 1) / 2;
+        [
+            Plane { data: y, width, height: y.len() / width, stride: width },
+            Plane { data: c, width: chroma, height: c.len() / chroma, stride: chroma },
+            Plane { data: c, width: chroma, height: c.len() / chroma, stride: chroma },
+        ]
+    }
+
+    #[test]
+    fn test_identical_frames_are_black() {
+        let (y, c) = (vec![90u8; 8 * 4], vec![128u8; 4 * 2]);
+        let difference = Difference::from_planes(&planes(&y, &c, 8), &planes(&y, &c, 8), &DifferenceOptions::default());
+        assert_eq!(difference.max, 0);
+        assert_eq!(difference.significant, 0);
+        assert!(difference.rgba.chunks(4).all(|p| p == [0, 0, 0, 255]));
+    }
+
+    #[test]
+    fn test_threshold_hides_small_differences() {
+        let (a, c) = (vec![100u8; 8 * 4], vec![128u8; 4 * 2]);
+        let mut b = a.clone();
+        b[0] = 102;
+        b[9] = 130;
+        let mut options = DifferenceOptions { threshold: 10, ..Default::default() };
+        let mut difference = Difference::from_planes(&planes(&a, &c, 8), &planes(&b, &c, 8), &options);
+        assert_eq!((difference.values[0], difference.values[9], difference.max), (2, 30, 30));
+        assert_eq!(difference.significant, 1);
+        assert_eq!(&difference.rgba[0..3], &[0, 0, 0]);
+        // 30 * 8 is nearly white
+        assert_eq!(&difference.rgba[36..39], &[240, 240, 240]);
+
+        options.threshold = 0;
+        difference.render(&options);
+        assert_eq!(difference.significant, 2);
+        assert_eq!(&difference.rgba[0..3], &[16, 16, 16]);
+    }
+
+    #[test]
+    fn test_heatmap_ends() {
+        assert_eq!(heat(0.), [0, 0, 0]);
+        assert_eq!(heat(1.), [255, 0, 0]);
+        assert_eq!(heat(0.5), [0, 255, 0]);
+    }
+}
@@ -0,0 +1,184 @@
+use std::fmt;
+
+use rusty_ffmpeg::ffi;
+
+/// an ffmpeg return code along with the text from `av_err2str`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AvError {
+    pub code: i32,
+    pub message: String,
+}
+
+impl AvError {
+    pub fn from_code(code: i32) -> AvError {
+        AvError {
+            code,
+            message: ffi::av_err2str(code),
+        }
+    }
+}
+
+impl fmt::Display for AvError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} ({})", self.message, self.code)
+    }
+}
+
+/// everything that can go wrong while opening or controlling a movie
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum MovieError {
+    /// `avformat_open_input` failed
+    OpenInput(AvError),
+    /// `avformat_find_stream_info` failed
+    StreamInfo(AvError),
+    /// the container has no video stream we have a decoder for
+    NoVideoStream,
+    /// the requested stream index is not a video stream we have a decoder for
+    InvalidVideoStream(usize),
+    /// the requested stream index is not a subtitle stream we have a decoder for
+    InvalidSubtitleStream(usize),
+    /// the named decoder does not exist or cannot decode the video stream
+    UnsupportedDecoder(String),
+    /// `avcodec_parameters_to_context` failed
+    CodecParameters(AvError),
+    /// `avcodec_open2` failed
+    CodecOpen(AvError),
+    /// the analyzer has no movie at this index
+    InvalidMovieIndex(usize),
+    /// the command only works while paused
+    NotPaused,
+    /// the playback speed is not a finite number above 0
+    InvalidSpeed,
+}
+
+impl MovieError {
+    /// the underlying ffmpeg error, if there is one
+    pub fn av_error(&self) -> Option<&AvError> {
+        match self {
+            MovieError::OpenInput(e)
+            | MovieError::StreamInfo(e)
+            | MovieError::CodecParameters(e)
+            | MovieError::CodecOpen(e) => Some(e),
+            MovieError::NoVideoStream
+            | MovieError::InvalidVideoStream(_)
+            | MovieError::InvalidSubtitleStream(_)
+            | MovieError::UnsupportedDecoder(_)
+            | MovieError::InvalidMovieIndex(_)
+            | MovieError::NotPaused
+            | MovieError::InvalidSpeed => None,
+        }
+    }
+}
+
+impl fmt::Display for MovieError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            MovieError::OpenInput(e) => write!(f, "cannot open file: {}", e),
+            MovieError::StreamInfo(e) => write!(f, "could not get the stream info: {}", e),
+            MovieError::NoVideoStream => write!(f, "no decodable video stream"),
+            MovieError::InvalidVideoStream(index) => write!(f, "stream {} is not a decodable video stream", index),
+            MovieError::InvalidSubtitleStream(index) => write!(f, "stream {} is not a decodable subtitle stream", index),
+            MovieError::UnsupportedDecoder(name) => write!(f, "decoder {} does not exist or cannot decode the video stream", name),
+            MovieError::CodecParameters(e) => write!(f, "failed to copy codec params to codec context: {}", e),
+            MovieError::CodecOpen(e) => write!(f, "failed to open codec through avcodec_open2: {}", e),
+            MovieError::InvalidMovieIndex(index) => write!(f, "no movie at index {}", index),
+            MovieError::NotPaused => write!(f, "not paused"),
+            MovieError::InvalidSpeed => write!(f, "the speed must be a finite number above 0"),
+        }
+    }
+}
+
+impl std::error::Error for MovieError {}
+
+/// everything that can go wrong while building a movie's filter graph
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FilterError {
+    /// the `buffer` source or the `buffersink` could not be created
+    CreateFilter(AvError),
+    /// `avfilter_graph_parse_ptr` rejected the description
+    Parse(AvError),
+    /// `avfilter_graph_config` could not link the filters or agree on formats
+    Config(AvError),
+}
+
+impl FilterError {
+    pub fn av_error(&self) -> &AvError {
+        match self {
+            FilterError::CreateFilter(e) | FilterError::Parse(e) | FilterError::Config(e) => e,
+        }
+    }
+}
+
+impl fmt::Display for FilterError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            FilterError::CreateFilter(e) => write!(f, "cannot create the buffer source or sink: {}", e),
+            FilterError::Parse(e) => write!(f, "cannot parse the filter description: {}", e),
+            FilterError::Config(e) => write!(f, "cannot configure the filter graph: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for FilterError {}
+
+/// C compatible status codes for the cdylib entry points.
+/// 0 is success, 1 to 5, 7, 11, 12 and 14 map to a `MovieError` variant, 13 to a `FilterError`.
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum MovieStatus {
+    Ok = 0,
+    OpenInput = 1,
+    StreamInfo = 2,
+    NoVideoStream = 3,
+    CodecParameters = 4,
+    CodecOpen = 5,
+    InvalidArgument = 6,
+    InvalidVideoStream = 7,
+    /// the analyzer has not been started, or was stopped
+    NotStarted = 8,
+    /// the call is only valid before the analyzer is started
+    AlreadyStarted = 9,
+    /// no frame is due yet, try again later
+    NoFrame = 10,
+    InvalidSubtitleStream = 11,
+    UnsupportedDecoder = 12,
+    /// the filter description could not be parsed or configured
+    InvalidFilter = 13,
+    InvalidMovieIndex = 14,
+    /// no event is waiting, try again later
+    NoEvent = 15,
+    NotPaused = 16,
+}
+
+impl From<&MovieError> for MovieStatus {
+    fn from(err: &MovieError) -> Self {
+        match err {
+            MovieError::OpenInput(_) => MovieStatus::OpenInput,
+            MovieError::StreamInfo(_) => MovieStatus::StreamInfo,
+            MovieError::NoVideoStream => MovieStatus::NoVideoStream,
+            MovieError::InvalidVideoStream(_) => MovieStatus::InvalidVideoStream,
+            MovieError::InvalidSubtitleStream(_) => MovieStatus::InvalidSubtitleStream,
+            MovieError::UnsupportedDecoder(_) => MovieStatus::UnsupportedDecoder,
+            MovieError::CodecParameters(_) => MovieStatus::CodecParameters,
+            MovieError::CodecOpen(_) => MovieStatus::CodecOpen,
+            MovieError::InvalidMovieIndex(_) => MovieStatus::InvalidMovieIndex,
+            MovieError::NotPaused => MovieStatus::NotPaused,
+            MovieError::InvalidSpeed => MovieStatus::InvalidArgument,
+        }
+    }
+}
+
+impl From<&FilterError> for MovieStatus {
+    fn from(_: &FilterError) -> Self {
+        MovieStatus::InvalidFilter
+    }
+}
+
+impl<T> From<&Result<T, MovieError>> for MovieStatus {
+    fn from(result: &Result<T, MovieError>) -> Self {
+        match result {
+            Ok(_) => MovieStatus::Ok,
+            Err(e) => e.into(),
+        }
+    }
+}
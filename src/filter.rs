@@ -1,173 +1,281 @@
-
 #![allow(unused_variables, non_camel_case_types)]
 use std::ffi::{CStr, CString};
+use log::debug;
 use rusty_ffmpeg::ffi::{self};
 
+use crate::error::{AvError, FilterError};
+use crate::orientation::Orientation;
+
+
+/// the pixel formats the buffersink hands out, the ones a display can upload
+/// as they are: planar and semi-planar 4:2:0, packed RGB. the graph converts
+/// anything else, 4:2:2, 4:4:4, high bit depth or planar RGB, to the nearest.
+pub static SINK_PIX_FMTS: [ffi::AVPixelFormat; 7] = [
+    ffi::AVPixelFormat_AV_PIX_FMT_YUV420P,
+    ffi::AVPixelFormat_AV_PIX_FMT_NV12,
+    ffi::AVPixelFormat_AV_PIX_FMT_NV21,
+    ffi::AVPixelFormat_AV_PIX_FMT_RGB24,
+    ffi::AVPixelFormat_AV_PIX_FMT_BGR24,
+    ffi::AVPixelFormat_AV_PIX_FMT_RGBA,
+    ffi::AVPixelFormat_AV_PIX_FMT_BGRA,
+];
 
-/*
-enum pix_fmts {
-    AVPixelFormat_AV_PIX_FMT_YUV420P,
-    AVPixelFormat_AV_PIX_FMT_NONE
-}
-*/
 pub struct RotateFilter {
     pub filter_graph: *mut ffi::AVFilterGraph,
     pub buffersink_ctx: *mut ffi::AVFilterContext,
     pub buffersrc_ctx: *mut ffi::AVFilterContext
 }
 
+/// what the buffer source is told about the frames it is given. a frame
+/// that differs from what the graph was built for needs a new graph.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FilterInput {
+    pub width: i32,
+    pub height: i32,
+    /// an `AVPixelFormat` value
+    pub format: i32,
+    /// num, den, 0/1 when unknown. the container's aspect wins over the codec's, as in the ffmpeg CLI.
+    pub sample_aspect_ratio: (i32, i32),
+    pub colorspace: ffi::AVColorSpace,
+    pub color_range: ffi::AVColorRange,
+    /// num, den of the stream the frames come from, the frames' pts are in it
+    pub time_base: (i32, i32),
+    /// num, den, 0/1 when unknown
+    pub frame_rate: (i32, i32),
+}
+
+/// the stream's time_base, or microseconds when there is no stream
+unsafe fn stream_time_base(stream: *const ffi::AVStream) -> (i32, i32) {
+    match stream.as_ref() {
+        Some(stream) if stream.time_base.num > 0 && stream.time_base.den > 0 => (stream.time_base.num, stream.time_base.den),
+        _ => (1, ffi::AV_TIME_BASE as i32),
+    }
+}
+
+impl FilterInput {
+    /// a decoded frame of `stream`, which plays at `frame_rate`
+    pub unsafe fn from_frame(frame: *const ffi::AVFrame, stream: *const ffi::AVStream, frame_rate: ffi::AVRational) -> FilterInput {
+        let sample_aspect_ratio = ffi::av_guess_sample_aspect_ratio(std::ptr::null_mut(), stream as *mut _, frame as *mut _);
+        let frame = &*frame;
+        FilterInput {
+            width: frame.width,
+            height: frame.height,
+            format: frame.format,
+            sample_aspect_ratio: (sample_aspect_ratio.num, sample_aspect_ratio.den),
+            colorspace: frame.colorspace,
+            color_range: frame.color_range,
+            time_base: stream_time_base(stream),
+            frame_rate: (frame_rate.num, frame_rate.den),
+        }
+    }
+
+    /// what the decoder of `stream` expects to put out, before any frame was decoded
+    pub unsafe fn from_codec_context(ctx: *const ffi::AVCodecContext, stream: *const ffi::AVStream, frame_rate: ffi::AVRational) -> FilterInput {
+        let sample_aspect_ratio = ffi::av_guess_sample_aspect_ratio(std::ptr::null_mut(), stream as *mut _, std::ptr::null_mut());
+        let ctx = &*ctx;
+        let sample_aspect_ratio = match sample_aspect_ratio.num {
+            0 => ctx.sample_aspect_ratio,
+            _ => sample_aspect_ratio,
+        };
+        FilterInput {
+            width: ctx.width,
+            height: ctx.height,
+            format: ctx.pix_fmt,
+            sample_aspect_ratio: (sample_aspect_ratio.num, sample_aspect_ratio.den),
+            colorspace: ctx.colorspace,
+            color_range: ctx.color_range,
+            time_base: stream_time_base(stream),
+            frame_rate: (frame_rate.num, frame_rate.den),
+        }
+    }
+
+    pub fn is_valid(&self) -> bool {
+        self.width > 0 && self.height > 0 && self.format >= 0
+    }
+}
+
+impl std::fmt::Display for FilterInput {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = unsafe { ffi::av_get_pix_fmt_name(self.format) };
+        let name = match name.is_null() {
+            true => std::borrow::Cow::from("unknown"),
+            false => unsafe { CStr::from_ptr(name) }.to_string_lossy(),
+        };
+        write!(
+            f,
+            "{}x{} {} sar {}/{} colorspace {} range {}",
+            self.width, self.height, name,
+            self.sample_aspect_ratio.0, self.sample_aspect_ratio.1,
+            self.colorspace, self.color_range
+        )
+    }
+}
+
 //pub fn init_filter(avblock* block, char* filters_descr) -> i32
+/// build `buffer -> orientation -> description -> buffersink` in a new graph,
+/// freeing the old one. the frames are turned upright before the description
+/// sees them. `None` or an empty description passes them through untouched.
+/// on error the graph and both filter contexts are left null.
 pub fn init_filter(
-    rotation: i32,
+    orientation: Orientation,
     filter_graph: &mut  *mut ffi::AVFilterGraph,
     buffersink_ctx: &mut *mut ffi::AVFilterContext,
     buffersrc_ctx: &mut  *mut ffi::AVFilterContext,
-    wh: (i32, i32),
-    format: i32,
-) -> i32 {
-	let ret: i32 = 0;
-    let buffer_src_name = CString::new("buffer").unwrap();
-    let buffer_sink_name = CString::new("buffersink").unwrap();
-	let buffer_src: *const ffi::AVFilter = unsafe { ffi::avfilter_get_by_name(buffer_src_name.as_ptr()) };
-	let buffer_sink: *const ffi::AVFilter = unsafe { ffi::avfilter_get_by_name(buffer_sink_name.as_ptr()) };
-	let mut outputs: *mut ffi::AVFilterInOut = unsafe { ffi::avfilter_inout_alloc() };
-	let mut inputs: *mut ffi::AVFilterInOut = unsafe { ffi::avfilter_inout_alloc() };
-
-	let time_base: ffi::AVRational  = ffi::AVRational{num: 1, den: 240};
-
-	unsafe {
-        if !buffersink_ctx.is_null() {ffi::avfilter_free(buffersink_ctx.as_mut().unwrap());}
-        if !buffersrc_ctx.is_null() {ffi::avfilter_free(buffersrc_ctx.as_mut().unwrap());}
+    input: &FilterInput,
+    description: Option<&str>,
+) -> Result<(), FilterError> {
+    unsafe {
+        // the filter contexts belong to the graph and go with it
         ffi::avfilter_graph_free(filter_graph as *mut _);
+        *buffersink_ctx = std::ptr::null_mut();
+        *buffersrc_ctx = std::ptr::null_mut();
         *filter_graph = ffi::avfilter_graph_alloc();
-
-        if outputs.is_null() || inputs.is_null() || filter_graph.is_null() {
-            ffi::avfilter_graph_config(*filter_graph as *mut _, std::ptr::null_mut());
-            ffi::avfilter_inout_free(&mut inputs  as *mut _);
-            ffi::avfilter_inout_free(&mut outputs  as *mut _);
-            return ffi::AVERROR(ffi::ENOMEM);
+        if (*filter_graph).is_null() {
+            return Err(FilterError::CreateFilter(AvError::from_code(ffi::AVERROR(ffi::ENOMEM))));
+        }
+        let result = build_graph(*filter_graph, input, orientation, description);
+        match result {
+            Ok((source, sink)) => {
+                *buffersrc_ctx = source;
+                *buffersink_ctx = sink;
+                Ok(())
+            }
+            Err(e) => {
+                ffi::avfilter_graph_free(filter_graph as *mut _);
+                Err(e)
+            }
         }
     }
+}
 
-    let width = wh.0;
-    let height = wh.1;
-    // let width = 450;
-    // let height = 800;
-	// assume source is AV_PIX_FMT_YUV420P;
-	/* buffer video source: the decoded frames from the decoder will be inserted here. */
-    let args = format!(
-        "video_size={}x{}:pix_fmt={}:time_base={}/{}:pixel_aspect={}/{}",
-        width, height,
-        // ffi::AVPixelFormat_AV_PIX_FMT_ARGB,
-        format as ffi::AVPixelFormat,
-        time_base.num, time_base.den, 1, 1
-    );
-    let args = &CString::new(args).unwrap();
-    println!(
-        "video_size={}x{}:pix_fmt={}:time_base={}/{}:pixel_aspect={}/{}",
-        width, height,
-        // ffi::AVPixelFormat_AV_PIX_FMT_ARGB,
-        format as ffi::AVPixelFormat,
-        time_base.num, time_base.den, 1, 1
-    );
+/// returns the buffer source and the buffer sink
+unsafe fn build_graph(
+    filter_graph: *mut ffi::AVFilterGraph,
+    input: &FilterInput,
+    orientation: Orientation,
+    description: Option<&str>,
+) -> Result<(*mut ffi::AVFilterContext, *mut ffi::AVFilterContext), FilterError> {
+    let buffer_src_name = CString::new("buffer").unwrap();
+    let buffer_sink_name = CString::new("buffersink").unwrap();
+	let buffer_src: *const ffi::AVFilter = ffi::avfilter_get_by_name(buffer_src_name.as_ptr());
+	let buffer_sink: *const ffi::AVFilter = ffi::avfilter_get_by_name(buffer_sink_name.as_ptr());
 
-    unsafe {
-        let in_buff = CString::new("in").unwrap();
-        let ret = ffi::avfilter_graph_create_filter(
-            &mut *buffersrc_ctx as *mut _,
-            buffer_src,
-            (&in_buff as &CStr).as_ptr(),
-            // std::ptr::null(),
-            args.as_ptr() as *const _,
-            std::ptr::null_mut(),
-            *filter_graph
-        );
-        if ret < 0 {
-            ffi::avfilter_inout_free(&mut inputs  as *mut _);
-            ffi::avfilter_inout_free(&mut outputs as *mut _);
-            return ret;
-        }
-        // The buffer source output must be connected to the input pad of
-        // the first filter described by filters_descr; since the first
-        // filter input label is not specified, it is set to "in" by
-        // default.
-        (*outputs).name       = ffi::av_strdup(in_buff.as_ptr());
-        (*outputs).filter_ctx = *buffersrc_ctx;
-        (*outputs).pad_idx    = 0;
-        (*outputs).next       = std::ptr::null_mut();
+    /* buffer video source: the decoded frames from the decoder will be inserted here. */
+    debug!("buffer source {}, time_base {}/{}, frame_rate {}/{}", input,
+        input.time_base.0, input.time_base.1, input.frame_rate.0, input.frame_rate.1);
+    let in_buff = CString::new("in").unwrap();
+    let buffersrc_ctx = ffi::avfilter_graph_alloc_filter(filter_graph, buffer_src, in_buff.as_ptr());
+    if buffersrc_ctx.is_null() {
+        return Err(FilterError::CreateFilter(AvError::from_code(ffi::AVERROR(ffi::ENOMEM))));
+    }
+    let params = ffi::av_buffersrc_parameters_alloc();
+    if params.is_null() {
+        return Err(FilterError::CreateFilter(AvError::from_code(ffi::AVERROR(ffi::ENOMEM))));
+    }
+    let rational = |(num, den): (i32, i32)| ffi::AVRational { num, den };
+    (*params).format = input.format;
+    (*params).width = input.width;
+    (*params).height = input.height;
+    (*params).time_base = rational(input.time_base);
+    (*params).sample_aspect_ratio = rational(input.sample_aspect_ratio);
+    (*params).frame_rate = rational(input.frame_rate);
+    // FFmpeg 6.0 has no colourspace or range in AVBufferSrcParameters, later
+    // releases do. the frames carry their own and filters such as scale and
+    // colorspace read them from there, a change rebuilds the graph anyway.
+    let ret = ffi::av_buffersrc_parameters_set(buffersrc_ctx, params);
+    ffi::av_free(params as *mut _);
+    if ret < 0 {
+        return Err(FilterError::CreateFilter(AvError::from_code(ret)));
+    }
+    let ret = ffi::avfilter_init_str(buffersrc_ctx, std::ptr::null());
+    if ret < 0 {
+        return Err(FilterError::CreateFilter(AvError::from_code(ret)));
+    }
+
+    let out_buff = CString::new("out").unwrap();
+    let mut buffersink_ctx = std::ptr::null_mut();
+    /* buffer video sink: to terminate the filter chain. */
+    let ret = ffi::avfilter_graph_create_filter(
+        &mut buffersink_ctx,
+        buffer_sink,
+        out_buff.as_ptr(),
+        std::ptr::null(),
+        std::ptr::null_mut(),
+        filter_graph
+    );
+    if ret < 0 {
+        return Err(FilterError::CreateFilter(AvError::from_code(ret)));
+    }
 
+    let key = CString::new("pix_fmts").unwrap();
+    let ret = ffi::av_opt_set_bin(
+        buffersink_ctx as *mut _,
+        key.as_ptr(),
+        SINK_PIX_FMTS.as_ptr() as *const u8,
+        std::mem::size_of_val(&SINK_PIX_FMTS) as i32,
+        ffi::AV_OPT_SEARCH_CHILDREN as i32,
+    );
+    if ret < 0 {
+        return Err(FilterError::CreateFilter(AvError::from_code(ret)));
     }
 
+    // "null" is the pass-through filter
+    let description = [orientation.filters(), description.map_or("", str::trim)]
+        .iter()
+        .filter(|d| !d.is_empty())
+        .copied()
+        .collect::<Vec<_>>()
+        .join(",");
+    let description = if description.is_empty() { String::from("null") } else { description };
+    debug!("filter graph {}", description);
+    let filter_desc = CString::new(description)
+        .map_err(|_| FilterError::Parse(AvError::from_code(ffi::AVERROR(ffi::EINVAL))))?;
 
-    unsafe {
-        let out_buff = CString::new("out").unwrap();
-        /* buffer video sink: to terminate the filter chain. */
-        let ret = ffi::avfilter_graph_create_filter(
-            buffersink_ctx as *mut *mut _,
-            buffer_sink,
-            out_buff.as_ptr(),
-            std::ptr::null(),
-            std::ptr::null_mut(),
-            *filter_graph
-        );
-        if ret < 0 {
-            ffi::avfilter_inout_free(&mut inputs  as *mut _);
-            ffi::avfilter_inout_free(&mut outputs as *mut _);
-            return ret;
-        }
-        ffi::avfilter_link(*buffersrc_ctx, 0, *buffersink_ctx, 0);
-
-        // The buffer sink input must be connected to the output pad of
-        // the last filter described by filters_descr; since the last
-        // filter output label is not specified, it is set to "out" by
-        // default.
-        (*inputs).name       = ffi::av_strdup(out_buff.as_ptr());
-        (*inputs).filter_ctx = *buffersink_ctx;
-        (*inputs).pad_idx    = 0;
-        (*inputs).next       = std::ptr::null_mut();
+    // The buffer source output must be connected to the input pad of
+    // the first filter described by filters_descr; since the first
+    // filter input label is not specified, it is set to "in" by
+    // default.
+	let mut outputs: *mut ffi::AVFilterInOut = ffi::avfilter_inout_alloc();
+	let mut inputs: *mut ffi::AVFilterInOut = ffi::avfilter_inout_alloc();
+    if outputs.is_null() || inputs.is_null() {
+        ffi::avfilter_inout_free(&mut inputs  as *mut _);
+        ffi::avfilter_inout_free(&mut outputs  as *mut _);
+        return Err(FilterError::CreateFilter(AvError::from_code(ffi::AVERROR(ffi::ENOMEM))));
     }
+    (*outputs).name       = ffi::av_strdup(in_buff.as_ptr());
+    (*outputs).filter_ctx = buffersrc_ctx;
+    (*outputs).pad_idx    = 0;
+    (*outputs).next       = std::ptr::null_mut();
 
-// unsafe {
-//     let key = CString::new("pix_fmts").unwrap();
-//     let result = ffi::av_opt_set_pixel_fmt(*buffersink_ctx as _, key.as_ptr(), ffi::AVPixelFormat_AV_PIX_FMT_YUV420P, AV_OPT_SEARCH_CHILDREN as i32);
-//     println!("result {}", result);
-// }
-// 	ret = av_opt_set_int_list(block->buffersink_ctx, "pix_fmts", pix_fmts,
-// 			AV_PIX_FMT_NONE, AV_OPT_SEARCH_CHILDREN);
-// 	if (ret < 0) {
-// 		avfilter_inout_free(&inputs);
-// 		avfilter_inout_free(&outputs);
-// 		return ret;
-// 	}
+    // The buffer sink input must be connected to the output pad of
+    // the last filter described by filters_descr; since the last
+    // filter output label is not specified, it is set to "out" by
+    // default.
+    (*inputs).name       = ffi::av_strdup(out_buff.as_ptr());
+    (*inputs).filter_ctx = buffersink_ctx;
+    (*inputs).pad_idx    = 0;
+    (*inputs).next       = std::ptr::null_mut();
 
     //
     // Set the endpoints for the filter graph. The filter_graph will
     // be linked to the graph described by filters_descr.
     //
-    unsafe {
+    let ret = ffi::avfilter_graph_parse_ptr(
+        filter_graph,
+        filter_desc.as_ptr(),
+        &mut inputs as *mut _,
+        &mut outputs as *mut _,
+        std::ptr::null_mut()
+    );
+    ffi::avfilter_inout_free(&mut inputs  as *mut _);
+    ffi::avfilter_inout_free(&mut outputs  as *mut _);
+    if ret < 0 {
+        return Err(FilterError::Parse(AvError::from_code(ret)));
+    }
 
-        // let transpose = match rotation {
-        //     90 => "transpose=1",
-        //     270 => "transpose=2",
-        //     -90 => "transpose=3",
-        //     _  => "tpad=0",
-        // };
-        // let filter_desc = CString::new(transpose).unwrap();
-        // let ret = ffi::avfilter_graph_parse_ptr(
-        //     *filter_graph,
-        //     filter_desc.as_ptr(),
-        //     &mut inputs as *mut _,
-        //     &mut outputs as *mut _,
-        //     std::ptr::null_mut()
-        // );
-        // if ret >= 0 {
-        //     ffi::avfilter_graph_config(*filter_graph as *mut _, std::ptr::null_mut());
-        // }
-        // ffi::avfilter_graph_parse_ptr(*filter_graph, null(), &mut inputs as *mut _, &mut outputs as *mut _, null_mut());
-
-        ffi::avfilter_graph_config(*filter_graph as *mut _, std::ptr::null_mut());
-		ffi::avfilter_inout_free(&mut inputs  as *mut _);
-		ffi::avfilter_inout_free(&mut outputs  as *mut _);
+    let ret = ffi::avfilter_graph_config(filter_graph, std::ptr::null_mut());
+    if ret < 0 {
+        return Err(FilterError::Config(AvError::from_code(ret)));
     }
-    return ret;
+    Ok((buffersrc_ctx, buffersink_ctx))
 }
@@ -0,0 +1,82 @@
+use std::ffi::CStr;
+
+use rusty_ffmpeg::ffi;
+
+/// colour properties of a decoded frame, by their ffmpeg names ("tv", "bt709").
+/// "unknown" when the stream does not say.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ColorInfo {
+    pub range: &'static str,
+    pub primaries: &'static str,
+    pub transfer: &'static str,
+    pub space: &'static str,
+    pub chroma_location: &'static str,
+}
+
+/// what the decoder knew about a frame. taken when the frame is queued, so
+/// pts and dts are in the video stream's time_base and never mirrored.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct FrameInfo {
+    /// 'I', 'P', 'B' and so on, '?' when unknown
+    pub pict_type: char,
+    pub key_frame: bool,
+    pub pts: i64,
+    /// dts of the packet the frame came from
+    pub dts: i64,
+    pub best_effort_timestamp: i64,
+    pub duration: i64,
+    /// size in bytes of the packet the frame came from, -1 when unknown
+    pub packet_size: i32,
+    pub interlaced: bool,
+    pub top_field_first: bool,
+    /// extra fields to repeat for soft telecine, `AVFrame.repeat_pict`
+    pub repeat_pict: i32,
+    pub color: ColorInfo,
+    /// `FF_DECODE_ERROR_*` bits, 0 for a clean frame
+    pub decode_error_flags: i32,
+    /// names of the side data attached to the frame, "Motion vectors", "Mastering display metadata"
+    pub side_data: Vec<&'static str>,
+}
+
+impl FrameInfo {
+    pub unsafe fn from_frame(frame: *const ffi::AVFrame) -> FrameInfo {
+        let f = &*frame;
+        let side_data = (0..f.nb_side_data.max(0) as usize)
+            .filter_map(|i| (*f.side_data.add(i)).as_ref())
+            .map(|data| name(ffi::av_frame_side_data_name(data.type_)))
+            .collect();
+        FrameInfo {
+            pict_type: ffi::av_get_picture_type_char(f.pict_type) as u8 as char,
+            key_frame: f.key_frame != 0,
+            pts: f.pts,
+            dts: f.pkt_dts,
+            best_effort_timestamp: f.best_effort_timestamp,
+            duration: f.duration,
+            packet_size: f.pkt_size,
+            interlaced: f.interlaced_frame != 0,
+            top_field_first: f.top_field_first != 0,
+            repeat_pict: f.repeat_pict,
+            color: ColorInfo {
+                range: name(ffi::av_color_range_name(f.color_range)),
+                primaries: name(ffi::av_color_primaries_name(f.color_primaries)),
+                transfer: name(ffi::av_color_transfer_name(f.color_trc)),
+                space: name(ffi::av_color_space_name(f.colorspace)),
+                chroma_location: name(ffi::av_chroma_location_name(f.chroma_location)),
+            },
+            decode_error_flags: f.decode_error_flags,
+            side_data,
+        }
+    }
+
+    pub fn has_decode_errors(&self) -> bool {
+        self.decode_error_flags != 0
+    }
+}
+
+/// the av_*_name functions hand out pointers into ffmpeg's static tables
+unsafe fn name(ptr: *const libc::c_char) -> &'static str {
+    match ptr.is_null() {
+        true => "unknown",
+        false => CStr::from_ptr(ptr).to_str().unwrap_or("unknown"),
+    }
+}
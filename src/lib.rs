@@ -1,6 +1,19 @@
 pub mod app;
+pub mod audio;
+pub mod capi;
+pub mod command;
+pub mod error;
+    mod custom_io;
     mod decode_thread;
 pub mod movie_state;
+pub mod open_options;
 pub mod filter;
+pub mod orientation;
+pub mod frame_info;
 pub mod record_state;
 pub mod analyzer_state;
+pub mod subtitle;
+pub mod metrics;
+pub mod scopes;
+pub mod difference;
+pub mod timeline;
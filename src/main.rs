@@ -1,10 +1,10 @@
 #![allow(unused_mut)]
 use std::time::Duration;
 
-use log::debug;
+use log::{debug, error};
 use ::rsplayer::app::start_analyzer;
 #[allow(unused_imports)]
-use rsplayer::{analyzer_state::AnalyzerContext, app::{open_movie, play_movie}};
+use rsplayer::{analyzer_state::AnalyzerContext, app::{open_movie, play_movie}, command::Command};
 use rusty_ffmpeg::ffi;
 
 fn main() {
@@ -23,24 +23,28 @@ fn main() {
     let mut analyzer_ctx = AnalyzerContext::new();
     unsafe {
         let filepath: std::ffi::CString = std::ffi::CString::new(args.get(1).unwrap_or(&default_file).as_str()).unwrap();
-        open_movie(&mut analyzer_ctx, filepath.as_ptr());
-        open_movie(&mut analyzer_ctx, filepath.as_ptr());
+        for _ in 0..2 {
+            if let Err(e) = open_movie(&mut analyzer_ctx, filepath.as_ptr()) {
+                error!("🚩 {}", e);
+                return;
+            }
+        }
     }
 
     unsafe {
-        let tx = start_analyzer(&mut analyzer_ctx);
+        let control = start_analyzer(&mut analyzer_ctx);
         // let tx = play_movie(analyzer_ctx.movie_list.get_mut(0).unwrap());
         let mut keep_playing = true;
         while keep_playing == true {
 
-            let _ = tx.send("pause".to_owned());
+            let _ = control.send(Command::Pause);
             ::std::thread::yield_now();
             ::std::thread::sleep(Duration::from_secs(2));
-            let _ = tx.send("unpause".to_owned());
-            let _ = tx.send("quit".to_owned());
+            let _ = control.send(Command::Resume);
+            let _ = control.send(Command::Quit);
             keep_playing = false;
         }
-        drop(tx);
+        drop(control);
         drop(analyzer_ctx);
     }
 }
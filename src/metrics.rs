@@ -0,0 +1,499 @@
+//! PSNR and SSIM between the frames the analyzer presents.
+//! movie 0 is the reference, every other movie is compared against it
+//! whenever both show the same (offset adjusted) position.
+use std::{
+    fmt::Write as _,
+    io,
+    path::Path,
+    sync::{
+        mpsc::{self, Receiver, SyncSender},
+        Arc, Mutex,
+    },
+    thread::{self, JoinHandle},
+};
+
+use log::error;
+use rusty_ffmpeg::ffi;
+
+use crate::error::AvError;
+use crate::movie_state::FrameWrapper;
+
+const SSIM_WINDOW: usize = 8;
+const SSIM_STEP: usize = 4;
+const SSIM_C1: f64 = (0.01 * 255.) * (0.01 * 255.);
+const SSIM_C2: f64 = (0.03 * 255.) * (0.03 * 255.);
+
+/// one 8 bit image plane
+#[derive(Debug, Clone, Copy)]
+pub struct Plane<'a> {
+    pub data: &'a [u8],
+    pub width: usize,
+    pub height: usize,
+    /// bytes per row, at least `width`
+    pub stride: usize,
+}
+
+impl<'a> Plane<'a> {
+    fn row(&self, y: usize) -> &'a [u8] {
+        &self.data[y * self.stride..y * self.stride + self.width]
+    }
+}
+
+/// mean squared error of two planes of the same size
+pub fn mse(a: &Plane, b: &Plane) -> f64 {
+    let mut sum: u64 = 0;
+    for y in 0..a.height {
+        sum += a.row(y).iter().zip(b.row(y)).map(|(p, q)| {
+            let d = *p as i64 - *q as i64;
+            (d * d) as u64
+        }).sum::<u64>();
+    }
+    sum as f64 / (a.width * a.height).max(1) as f64
+}
+
+/// peak signal to noise ratio in dB, infinite for identical planes
+pub fn psnr(a: &Plane, b: &Plane) -> f64 {
+    mse_to_psnr(mse(a, b))
+}
+
+fn mse_to_psnr(mse: f64) -> f64 {
+    if mse == 0. {
+        return f64::INFINITY;
+    }
+    10. * (255. * 255. / mse).log10()
+}
+
+/// mean SSIM over 8x8 windows placed every 4 pixels, 1.0 for identical planes
+pub fn ssim(a: &Plane, b: &Plane) -> f64 {
+    let window_w = SSIM_WINDOW.min(a.width);
+    let window_h = SSIM_WINDOW.min(a.height);
+    if window_w == 0 || window_h == 0 {
+        return 1.;
+    }
+    let mut total = 0.;
+    let mut windows = 0;
+    let mut y = 0;
+    while y + window_h <= a.height {
+        let mut x = 0;
+        while x + window_w <= a.width {
+            total += window_ssim(a, b, x, y, window_w, window_h);
+            windows += 1;
+            x += SSIM_STEP;
+        }
+        y += SSIM_STEP;
+    }
+    total / windows as f64
+}
+
+fn window_ssim(a: &Plane, b: &Plane, x: usize, y: usize, w: usize, h: usize) -> f64 {
+    let (mut sum_a, mut sum_b, mut sum_aa, mut sum_bb, mut sum_ab) = (0u64, 0u64, 0u64, 0u64, 0u64);
+    for row in y..y + h {
+        let (ra, rb) = (&a.row(row)[x..x + w], &b.row(row)[x..x + w]);
+        for (p, q) in ra.iter().zip(rb) {
+            let (p, q) = (*p as u64, *q as u64);
+            sum_a += p;
+            sum_b += q;
+            sum_aa += p * p;
+            sum_bb += q * q;
+            sum_ab += p * q;
+        }
+    }
+    let n = (w * h) as f64;
+    let (mean_a, mean_b) = (sum_a as f64 / n, sum_b as f64 / n);
+    let var_a = sum_aa as f64 / n - mean_a * mean_a;
+    let var_b = sum_bb as f64 / n - mean_b * mean_b;
+    let covariance = sum_ab as f64 / n - mean_a * mean_b;
+    ((2. * mean_a * mean_b + SSIM_C1) * (2. * covariance + SSIM_C2))
+        / ((mean_a * mean_a + mean_b * mean_b + SSIM_C1) * (var_a + var_b + SSIM_C2))
+}
+
+/// a score for each of the Y, U and V planes
+#[derive(Debug, Clone, Copy, PartialEq, Default)]
+pub struct PlaneScores {
+    pub y: f64,
+    pub u: f64,
+    pub v: f64,
+}
+
+/// the metrics of one distorted frame against the reference frame shown with it
+#[derive(Debug, Clone, PartialEq)]
+pub struct FrameMetrics {
+    /// the compared movie, movie 0 is the reference
+    pub movie_index: usize,
+    pub reference_pts: i64,
+    pub pts: i64,
+    /// seconds from the start of the reference movie
+    pub position: f64,
+    pub psnr: PlaneScores,
+    pub ssim: PlaneScores,
+}
+
+impl FrameMetrics {
+    /// PSNR over all samples of a 4:2:0 frame, like ffmpeg's "average"
+    pub fn psnr_average(&self) -> f64 {
+        let to_mse = |psnr: f64| 255. * 255. / 10f64.powf(psnr / 10.);
+        mse_to_psnr((4. * to_mse(self.psnr.y) + to_mse(self.psnr.u) + to_mse(self.psnr.v)) / 6.)
+    }
+
+    /// SSIM weighted by the number of samples in each 4:2:0 plane
+    pub fn ssim_average(&self) -> f64 {
+        (4. * self.ssim.y + self.ssim.u + self.ssim.v) / 6.
+    }
+}
+
+/// every measured frame, in the order they were presented
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct MetricsHistory {
+    pub frames: Vec<FrameMetrics>,
+}
+
+impl MetricsHistory {
+    pub fn push(&mut self, metrics: FrameMetrics) {
+        self.frames.push(metrics);
+    }
+
+    pub fn clear(&mut self) {
+        self.frames.clear();
+    }
+
+    /// one row per frame. identical frames have a PSNR of "inf".
+    pub fn to_csv(&self) -> String {
+        let mut csv = String::from("movie,reference_pts,pts,position,psnr_y,psnr_u,psnr_v,psnr_avg,ssim_y,ssim_u,ssim_v,ssim_avg\n");
+        for m in self.frames.iter() {
+            let _ = writeln!(
+                csv,
+                "{},{},{},{:.6},{:.4},{:.4},{:.4},{:.4},{:.6},{:.6},{:.6},{:.6}",
+                m.movie_index, m.reference_pts, m.pts, m.position,
+                m.psnr.y, m.psnr.u, m.psnr.v, m.psnr_average(),
+                m.ssim.y, m.ssim.u, m.ssim.v, m.ssim_average(),
+            );
+        }
+        csv
+    }
+
+    /// an array of objects with the CSV columns as keys. JSON has no infinity,
+    /// the PSNR of identical frames is null.
+    pub fn to_json(&self) -> String {
+        let number = |value: f64| match value.is_finite() {
+            true => format!("{:.6}", value),
+            false => String::from("null"),
+        };
+        let rows: Vec<String> = self.frames.iter().map(|m| format!(
+            "{{\"movie\":{},\"reference_pts\":{},\"pts\":{},\"position\":{},\
+             \"psnr_y\":{},\"psnr_u\":{},\"psnr_v\":{},\"psnr_avg\":{},\
+             \"ssim_y\":{},\"ssim_u\":{},\"ssim_v\":{},\"ssim_avg\":{}}}",
+            m.movie_index, m.reference_pts, m.pts, number(m.position),
+            number(m.psnr.y), number(m.psnr.u), number(m.psnr.v), number(m.psnr_average()),
+            number(m.ssim.y), number(m.ssim.u), number(m.ssim.v), number(m.ssim_average()),
+        )).collect();
+        format!("[{}]\n", rows.join(",\n"))
+    }
+
+    pub fn write_csv(&self, path: &Path) -> io::Result<()> {
+        std::fs::write(path, self.to_csv())
+    }
+
+    pub fn write_json(&self, path: &Path) -> io::Result<()> {
+        std::fs::write(path, self.to_json())
+    }
+}
+
+/// the last frame each movie presented, kept until its counterpart in the
+/// reference movie shows up
+struct PresentedFrame {
+    frame: FrameWrapper,
+    position: f64,
+    /// already compared against the reference
+    measured: bool,
+}
+
+/// work for the metrics thread
+enum MetricsJob {
+    /// measure `distorted` against `reference`, the thread frees both clones
+    Compare { movie_index: usize, reference: FrameWrapper, distorted: FrameWrapper, position: f64 },
+    /// answered once every pair queued before it is in the history
+    Flush(mpsc::Sender<()>),
+}
+
+/// pairs the metrics thread may fall behind by before `present` waits for it
+const QUEUED_PAIRS: usize = 16;
+
+/// collects frame metrics while the analyzer plays, see `AnalyzerContext::set_metrics_enabled`.
+/// pairing happens on the presenting thread, PSNR and SSIM on a thread of their own.
+pub struct MetricsRecorder {
+    pub enabled: bool,
+    history: Arc<Mutex<MetricsHistory>>,
+    presented: Vec<Option<PresentedFrame>>,
+    jobs: Option<SyncSender<MetricsJob>>,
+    worker: Option<JoinHandle<()>>,
+}
+
+impl MetricsRecorder {
+    pub fn new() -> MetricsRecorder {
+        MetricsRecorder {
+            enabled: false,
+            history: Arc::new(Mutex::new(MetricsHistory::default())),
+            presented: vec![],
+            jobs: None,
+            worker: None,
+        }
+    }
+
+    /// remember a presented frame and queue every pair it completes for measuring.
+    /// frames are paired when their positions are less than `tolerance` seconds apart.
+    pub unsafe fn present(&mut self, movie_index: usize, frame: *const ffi::AVFrame, position: f64, tolerance: f64) {
+        if self.presented.len() <= movie_index {
+            self.presented.resize_with(movie_index + 1, || None);
+        }
+        if let Some(mut old) = self.presented[movie_index].take() {
+            ffi::av_frame_free(&mut old.frame.ptr);
+        }
+        let frame = FrameWrapper { ptr: ffi::av_frame_clone(frame) };
+        self.presented[movie_index] = Some(PresentedFrame { frame, position, measured: false });
+
+        let reference = match self.presented.first() {
+            Some(Some(reference)) => (reference.frame.ptr, reference.position),
+            _ => return,
+        };
+        for index in 1..self.presented.len() {
+            let distorted = match self.presented[index].as_mut() {
+                Some(d) if !d.measured && (d.position - reference.1).abs() < tolerance => d,
+                _ => continue,
+            };
+            distorted.measured = true;
+            let job = MetricsJob::Compare {
+                movie_index: index,
+                reference: FrameWrapper { ptr: ffi::av_frame_clone(reference.0) },
+                distorted: FrameWrapper { ptr: ffi::av_frame_clone(distorted.frame.ptr) },
+                position: reference.1,
+            };
+            if let Err(mpsc::SendError(job)) = self.jobs().send(job) {
+                free_job(job);
+            }
+        }
+    }
+
+    /// every frame measured so far, waits for the pairs still queued
+    pub fn history(&self) -> MetricsHistory {
+        self.flush();
+        self.history.lock().unwrap().clone()
+    }
+
+    /// drop the measured frames, including the ones still queued
+    pub fn clear_history(&self) {
+        self.flush();
+        self.history.lock().unwrap().clear();
+    }
+
+    /// forget the held frames, after a seek nothing pairs with them anymore
+    pub fn reset(&mut self) {
+        self.presented.iter_mut().filter_map(|p| p.take()).for_each(|mut p| unsafe {
+            ffi::av_frame_free(&mut p.frame.ptr);
+        });
+    }
+
+    /// the metrics thread's queue, started with the first pair
+    fn jobs(&mut self) -> &SyncSender<MetricsJob> {
+        if self.jobs.is_none() {
+            let (sender, receiver) = mpsc::sync_channel(QUEUED_PAIRS);
+            let history = self.history.clone();
+            self.worker = Some(thread::spawn(move || measure_pairs(receiver, history)));
+            self.jobs = Some(sender);
+        }
+        self.jobs.as_ref().unwrap()
+    }
+
+    fn flush(&self) {
+        if let Some(jobs) = &self.jobs {
+            let (done, flushed) = mpsc::channel();
+            if jobs.send(MetricsJob::Flush(done)).is_ok() {
+                let _ = flushed.recv();
+            }
+        }
+    }
+}
+
+fn measure_pairs(jobs: Receiver<MetricsJob>, history: Arc<Mutex<MetricsHistory>>) {
+    for job in jobs {
+        match &job {
+            MetricsJob::Compare { movie_index, reference, distorted, position } => unsafe {
+                match compare_frames(reference.ptr, distorted.ptr) {
+                    Ok((psnr, ssim)) => history.lock().unwrap().push(FrameMetrics {
+                        movie_index: *movie_index,
+                        reference_pts: (*reference.ptr).pts,
+                        pts: (*distorted.ptr).pts,
+                        position: *position,
+                        psnr,
+                        ssim,
+                    }),
+                    Err(e) => error!("🚩 cannot compare frames: {}", e),
+                }
+            },
+            MetricsJob::Flush(done) => {
+                let _ = done.send(());
+            }
+        }
+        free_job(job);
+    }
+}
+
+fn free_job(job: MetricsJob) {
+    if let MetricsJob::Compare { mut reference, mut distorted, .. } = job {
+        unsafe {
+            ffi::av_frame_free(&mut reference.ptr);
+            ffi::av_frame_free(&mut distorted.ptr);
+        }
+    }
+}
+
+impl Default for MetricsRecorder {
+    fn default() -> Self {
+        MetricsRecorder::new()
+    }
+}
+
+impl Drop for MetricsRecorder {
+    fn drop(&mut self) {
+        self.reset();
+        self.jobs = None;
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
+
+/// per plane PSNR and SSIM of `distorted` against `reference`, measured in
+/// YUV420P at the reference's size.
+pub unsafe fn compare_frames(
+    reference: *const ffi::AVFrame,
+    distorted: *const ffi::AVFrame,
+) -> Result<(PlaneScores, PlaneScores), AvError> {
+    let (width, height) = ((*reference).width, (*reference).height);
+    let reference = Yuv420Frame::new(reference, width, height)?;
+    let distorted = Yuv420Frame::new(distorted, width, height)?;
+    let (r, d) = (yuv420_planes(&*reference.ptr), yuv420_planes(&*distorted.ptr));
+    let psnr = PlaneScores { y: psnr(&r[0], &d[0]), u: psnr(&r[1], &d[1]), v: psnr(&r[2], &d[2]) };
+    let ssim = PlaneScores { y: ssim(&r[0], &d[0]), u: ssim(&r[1], &d[1]), v: ssim(&r[2], &d[2]) };
+    Ok((psnr, ssim))
+}
+
+pub(crate) unsafe fn yuv420_planes(frame: &ffi::AVFrame) -> [Plane<'_>; 3] {
+    let (w, h) = (frame.width as usize, frame.height as usize);
+    let sizes = [(w, h), ((w + 1) / 2, (h + 1) / 2), ((w + 1) / 2, (h + 1) / 2)];
+    [0, 1, 2].map(|i| {
+        let stride = frame.linesize[i] as usize;
+        Plane {
+            data: std::slice::from_raw_parts(frame.data[i], stride * sizes[i].1),
+            width: sizes[i].0,
+            height: sizes[i].1,
+            stride,
+        }
+    })
+}
+
+/// a frame in YUV420P at a given size, scaled with swscale when the source differs
+pub(crate) struct Yuv420Frame {
+    pub(crate) ptr: *const ffi::AVFrame,
+    /// set when we scaled, freed on drop
+    owned: *mut ffi::AVFrame,
+}
+
+impl Yuv420Frame {
+    pub(crate) unsafe fn new(frame: *const ffi::AVFrame, width: i32, height: i32) -> Result<Yuv420Frame, AvError> {
+        let f = &*frame;
+        if f.format == ffi::AVPixelFormat_AV_PIX_FMT_YUV420P && f.width == width && f.height == height {
+            return Ok(Yuv420Frame { ptr: frame, owned: std::ptr::null_mut() });
+        }
+        let sws = ffi::sws_getContext(
+            f.width, f.height, f.format,
+            width, height, ffi::AVPixelFormat_AV_PIX_FMT_YUV420P,
+            ffi::SWS_BICUBIC as i32,
+            std::ptr::null_mut(), std::ptr::null_mut(), std::ptr::null(),
+        );
+        if sws.is_null() {
+            return Err(AvError::from_code(ffi::AVERROR(ffi::EINVAL)));
+        }
+        let mut scaled = ffi::av_frame_alloc();
+        (*scaled).format = ffi::AVPixelFormat_AV_PIX_FMT_YUV420P;
+        (*scaled).width = width;
+        (*scaled).height = height;
+        let ret = ffi::av_frame_get_buffer(scaled, 0);
+        if ret < 0 {
+            ffi::sws_freeContext(sws);
+            ffi::av_frame_free(&mut scaled);
+            return Err(AvError::from_code(ret));
+        }
+        ffi::sws_scale(
+            sws,
+            f.data.as_ptr() as *const *const u8,
+            f.linesize.as_ptr(),
+            0,
+            f.height,
+            (*scaled).data.as_ptr(),
+            (*scaled).linesize.as_ptr(),
+        );
+        ffi::sws_freeContext(sws);
+        (*scaled).pts = f.pts;
+        Ok(Yuv420Frame { ptr: scaled, owned: scaled })
+    }
+}
+
+impl Drop for Yuv420Frame {
+    fn drop(&mut self) {
+        if !self.owned.is_null() {
+            unsafe { ffi::av_frame_free(&mut self.owned) };
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn plane(data: &[u8], width: usize) -> Plane<'_> {
+        Plane { data, width, height: data.len() / width, stride: width }
+    }
+
+    #[test]
+    fn test_identical_planes() {
+        let data: Vec<u8> = (0..16 * 16).map(|i| (i * 7 % 256) as u8).collect();
+        let a = plane(&data, 16);
+        assert_eq!(psnr(&a, &a), f64::INFINITY);
+        assert!((ssim(&a, &a) - 1.).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_psnr_of_constant_error() {
+        let a = vec![100u8; 64];
+        let b = vec![110u8; 64];
+        // mse 100, 10 * log10(65025 / 100)
+        assert!((psnr(&plane(&a, 8), &plane(&b, 8)) - 28.1308).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_ssim_drops_with_noise() {
+        let a: Vec<u8> = (0..32 * 32).map(|i| ((i % 32) * 8) as u8).collect();
+        let b: Vec<u8> = a.iter().enumerate().map(|(i, p)| p.wrapping_add((i * 37 % 41) as u8)).collect();
+        let score = ssim(&plane(&a, 32), &plane(&b, 32));
+        assert!(score < 0.9 && score > 0.);
+    }
+
+    #[test]
+    fn test_export() {
+        let mut history = MetricsHistory::default();
+        history.push(FrameMetrics {
+            movie_index: 1,
+            reference_pts: 512,
+            pts: 512,
+            position: 1.,
+            psnr: PlaneScores { y: f64::INFINITY, u: f64::INFINITY, v: f64::INFINITY },
+            ssim: PlaneScores { y: 1., u: 1., v: 1. },
+        });
+        let csv = history.to_csv();
+        assert_eq!(csv.lines().count(), 2);
+        assert!(csv.lines().nth(1).unwrap().starts_with("1,512,512,1.000000,inf,inf,inf,inf,1.000000"));
+        let json = history.to_json();
+        assert!(json.contains("\"psnr_avg\":null"));
+        assert!(json.contains("\"ssim_avg\":1.000000"));
+    }
+}
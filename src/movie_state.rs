@@ -1,37 +1,220 @@
 #![allow(unused_variables, dead_code, unused)]
-use std::{ops::Deref, sync::Mutex, collections::VecDeque};
+use std::{ffi::{CStr, CString}, ops::Deref, path::Path, sync::{atomic::{AtomicBool, AtomicI64, AtomicU32, AtomicU64, AtomicUsize, Ordering}, Arc, Mutex, MutexGuard}, collections::VecDeque, thread::JoinHandle};
 
 use log::{error, info};
 use rusty_ffmpeg::ffi::{self};
 
-use crate::filter::init_filter;
+use crate::audio::AudioSampleQueue;
+use crate::command::Command;
+use crate::custom_io::CustomIo;
+use crate::error::{AvError, FilterError, MovieError};
+use crate::filter::{init_filter, FilterInput};
+use crate::frame_info::FrameInfo;
+use crate::open_options::{DecoderOptions, Dictionary};
+use crate::orientation::Orientation;
+use crate::timeline::PacketTimeline;
+use crate::subtitle::{decode_subtitle, open_subtitle_codec, parse_srt, Subtitle, SubtitleContent, SubtitleQueue, SubtitleStreamInfo};
 
 static PACKET_QUEUE_SIZE: usize = 4;
+static AUDIO_PACKET_QUEUE_SIZE: usize = 32;
+
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SeekMode {
+    /// land on the keyframe at or before the target
+    Keyframe,
+    /// decode from the keyframe and discard frames until the target
+    Accurate,
+}
+
+/// what a movie does once the demuxer reaches the end of the file
+#[repr(C)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum EndBehavior {
+    /// rewind and play again from the start
+    #[default]
+    Loop,
+    /// hold the last frame until the movie is seeked
+    StopOnLastFrame,
+    /// play backwards to the start, then forwards again
+    PingPong,
+    /// stop reading the movie, its threads wind down
+    Close,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct SeekRequest {
+    /// target in video stream time_base units
+    pub pts: i64,
+    pub mode: SeekMode,
+    /// the command the packet thread reports as applied or failed once it is done
+    pub report: Option<Command>,
+}
+
+/// ask the packet thread to decode a different video stream
+#[derive(Debug, Clone, PartialEq)]
+pub struct VideoStreamRequest {
+    pub index: usize,
+    /// seconds from the start of the movie to resume at on the new stream
+    pub position: f64,
+    pub report: Option<Command>,
+}
+
+/// a change of direction or a step back, carried out by the packet thread
+#[derive(Debug, Clone, PartialEq)]
+pub enum PlaybackRequest {
+    /// play backwards from the frame with this pts
+    Reverse { from_pts: i64, report: Option<Command> },
+    /// show the newest frame before this pts and continue forwards from there
+    StepBack { before_pts: i64, report: Option<Command> },
+}
+
+/// the frames of the last GOP decoded for stepping back, newest first
+pub struct GopCache {
+    /// the frames are every frame of the GOP before this pts
+    pub end_pts: i64,
+    pub frames: Vec<FrameWrapper>,
+}
+
+impl GopCache {
+    fn new() -> GopCache {
+        GopCache { end_pts: ffi::AV_NOPTS_VALUE, frames: vec![] }
+    }
+
+    /// a copy of the newest frame before `before_pts`, None when the
+    /// frame is not in the cached GOP
+    fn frame_before(&self, before_pts: i64) -> Option<FrameWrapper> {
+        let oldest = self.frames.last()?;
+        if before_pts > self.end_pts || unsafe { (*oldest.ptr).pts } >= before_pts {
+            return None;
+        }
+        self.frames.iter()
+            .find(|f| unsafe { (*f.ptr).pts } < before_pts)
+            .map(|f| FrameWrapper { ptr: unsafe { ffi::av_frame_clone(f.ptr) } })
+    }
+
+    fn replace(&mut self, end_pts: i64, frames: Vec<FrameWrapper>) {
+        self.clear();
+        self.end_pts = end_pts;
+        self.frames = frames;
+    }
+
+    fn clear(&mut self) {
+        self.frames.iter_mut().for_each(|f| unsafe { ffi::av_frame_free(&mut f.ptr) });
+        self.frames.clear();
+        self.end_pts = ffi::AV_NOPTS_VALUE;
+    }
+}
+
+impl Drop for GopCache {
+    fn drop(&mut self) {
+        self.clear();
+    }
+}
+
+/// one video stream of the container, as listed by `MovieState::video_streams`
+#[derive(Debug, Clone, PartialEq)]
+pub struct VideoStreamInfo {
+    pub index: usize,
+    pub codec_name: String,
+    pub width: i32,
+    pub height: i32,
+    pub frame_rate: ffi::AVRational,
+    /// false when there is no decoder for the codec in this ffmpeg build
+    pub decodable: bool,
+    /// the stream currently being decoded
+    pub active: bool,
+}
+
 #[repr(C)]
 pub struct MovieState {
     pub format_context: Mutex<FormatContextWrapper>,
-    pub video_stream_idx: i64,
+    pub video_stream_idx: AtomicI64,
     pub audio_stream_idx: i64,
     pub audio_stream: Mutex<StreamWrapper>,
     pub audio_ctx: Mutex<CodecContextWrapper>,
     pub audio_buf: [u8; 1024 * 1024],
     // pub audio_pkt: *const ffi::AVPacket,
+    pub audioqueue: Mutex<VecDeque<PacketWrapper>>,
+    pub audio_samples: Arc<AudioSampleQueue>,
     pub videoqueue: Mutex<VecDeque<PacketWrapper>>,
     pub video_stream: Mutex<StreamWrapper>,
     pub video_ctx: Mutex<CodecContextWrapper>,
-    pub picq: Mutex<VecDeque<FrameWrapper>>,
+    pub picq: Mutex<VecDeque<DecodedFrame>>,
+    /// what the filter graph gave out and is yet to be shown. `fps`, `yadif=1` or
+    /// `minterpolate` give out more or fewer frames than go in.
+    pub filtered_queue: Mutex<VecDeque<FilteredFrame>>,
     pub paused: std::sync::atomic::AtomicBool,
     pub in_vfilter: Mutex<FilterContextWrapper>,   // the first filter in the video chain
     pub out_vfilter: Mutex<FilterContextWrapper>,   // the last filter in the video chain
     pub vgraph: Mutex<FilterGraphWrapper>,
-    pub video_frame_rate: ffi::AVRational,
-    pub last_pts: i64,
-    pub last_pts_time: f64,
-    pub last_display_time: f64,
-    pub step: bool,
+    /// libavfilter description the video runs through, `None` shows the frames as decoded
+    pub filter_description: Mutex<Option<String>>,
+    /// what the current graph was built for, from the frames it was given
+    pub filter_input: Mutex<Option<FilterInput>>,
+    /// old and new format of the frames when they changed, see `take_format_change`
+    pub format_change: Mutex<Option<(FilterInput, FilterInput)>>,
+    /// shown this way up instead of the way the video stream asks for, see `set_orientation`
+    pub orientation_override: Mutex<Option<Orientation>>,
+    pub video_frame_rate: Mutex<ffi::AVRational>,
+    /// pts of the frame on screen, written by the UI thread and read by the control thread
+    pub last_pts: AtomicI64,
+    /// seconds from the start of the movie shown at `last_display_time`, the presentation anchor
+    pub last_pts_time: AtomicF64,
+    pub last_display_time: AtomicF64,
+    pub step: AtomicBool,
+    pub seek_request: Mutex<Option<SeekRequest>>,
+    /// set from `seek()` until the packet thread has flushed everything
+    pub seek_pending: AtomicBool,
+    /// frames before this pts are discarded after an accurate seek
+    pub seek_target: AtomicI64,
+    /// the presentation timing needs to be reset on the next dequeue
+    pub resync: AtomicBool,
+    pub video_stream_request: Mutex<Option<VideoStreamRequest>>,
+    pub end_behavior: Mutex<EndBehavior>,
+    /// the end of the file was reached and the movie is not looping
+    pub ended: AtomicBool,
+    /// the packet thread stopped because of `EndBehavior::Close`
+    pub closed: AtomicBool,
+    /// pts of the newest frame the decoder handed over
+    pub last_decoded_pts: AtomicI64,
+    /// cleared when the drain markers are queued at the end of the file, set once the
+    /// decode thread handed over the last frames of the video decoder
+    pub video_drained: AtomicBool,
+    /// the same for the audio decode thread, always set without an audio stream
+    pub audio_drained: AtomicBool,
+    /// while playing backwards, frames are queued with their pts mirrored around this
+    pub reverse_origin: AtomicI64,
+    /// set when the movie is read from memory instead of a file, outlives the format context
+    pub(crate) custom_io: Option<CustomIo>,
+    /// container index of the subtitle stream being decoded, -1 for none
+    pub subtitle_stream_idx: AtomicI64,
+    pub subtitle_ctx: Mutex<CodecContextWrapper>,
+    pub subtitles: SubtitleQueue,
+    /// loaded from a .srt file next to the movie, shown on top of the selected stream
+    pub external_subtitles: Mutex<Vec<Subtitle>>,
+    /// how video decoders for this movie are opened, including after a stream switch
+    pub decoder_options: Mutex<DecoderOptions>,
+    /// seconds this movie runs ahead of the others when presented in sync
+    pub time_offset: AtomicF64,
+    /// playback rate, set for every movie by `AnalyzerContext::set_speed`
+    pub speed: AtomicF32,
+    pub playback_request: Mutex<Option<PlaybackRequest>>,
+    /// asked to play backwards, unlike ping-pong it holds the first frame at the start
+    pub backwards: AtomicBool,
+    pub gop_cache: Mutex<GopCache>,
+    /// cleared to stop this movie's packet and decode threads
+    pub(crate) keep_running: Arc<AtomicBool>,
+    /// the packet and decode threads, joined by `stop_threads`
+    pub(crate) threads: Mutex<Vec<JoinHandle<()>>>,
+    /// where the movie sits in the analyzer's list, reported with its events
+    pub movie_index: AtomicUsize,
+    /// every packet the demuxer read, for bitrate and frame size graphs
+    pub packet_timeline: Mutex<PacketTimeline>,
 }
 impl Drop for MovieState {
     fn drop(&mut self) {
+        // the movie threads hold an Arc of it, they are gone by now
         // claim lock to drain other threads
         {
             info!("free video codec context");
@@ -40,8 +223,18 @@ impl Drop for MovieState {
             unsafe {ffi::av_free(video_ctx.ptr as *mut _);}
             drop(video_ctx);
         }
+        {
+            info!("free audio codec context");
+            let mut audio_ctx = self.audio_ctx.lock().unwrap();
+            unsafe {ffi::avcodec_free_context(&mut audio_ctx.ptr as *mut *mut _);}
+        }
+        {
+            let mut subtitle_ctx = self.subtitle_ctx.lock().unwrap();
+            unsafe {ffi::avcodec_free_context(&mut subtitle_ctx.ptr as *mut *mut _);}
+        }
         {
             self.clear_packet_queue().unwrap();
+            self.clear_audio_queue().unwrap();
         }
         {
             self.clear_frame_queue().unwrap();
@@ -65,29 +258,65 @@ impl MovieState {
         let vgraph = unsafe {ffi::avfilter_graph_alloc()};
         MovieState {
             format_context: Mutex::new(FormatContextWrapper{ptr:std::ptr::null_mut()}),
-            video_stream_idx: -1,
+            video_stream_idx: AtomicI64::new(-1),
             audio_stream_idx: -1,
             audio_stream: Mutex::new(StreamWrapper{ptr:std::ptr::null_mut()}),
             audio_ctx: Mutex::new(CodecContextWrapper{ptr:std::ptr::null_mut()}),
             audio_buf: [0; 1024 * 1024],
+            audioqueue: Mutex::new(VecDeque::with_capacity(AUDIO_PACKET_QUEUE_SIZE)),
+            audio_samples: Arc::new(AudioSampleQueue::new()),
             videoqueue: Mutex::new(VecDeque::with_capacity(PACKET_QUEUE_SIZE)),
             // audio_pkt: std::ptr::null_mut(),
             video_stream: Mutex::new(StreamWrapper{ptr:std::ptr::null_mut()}),
             video_ctx: Mutex::new(CodecContextWrapper{ptr:std::ptr::null_mut()}),
             picq: Mutex::new(VecDeque::with_capacity(3)),
+            filtered_queue: Mutex::new(VecDeque::new()),
             paused: std::sync::atomic::AtomicBool::new(false),
             in_vfilter: Mutex::new(FilterContextWrapper{ ptr:std::ptr::null_mut() }),
             out_vfilter: Mutex::new(FilterContextWrapper { ptr: std::ptr::null_mut() }),
             vgraph: Mutex::new(FilterGraphWrapper { ptr: vgraph }),
-            video_frame_rate: ffi::AVRational { num: 1, den: 60 },
-            last_pts: ffi::AV_NOPTS_VALUE,
-            last_pts_time: 0.,
-            last_display_time: 0.,
-            step: false,
+            filter_description: Mutex::new(None),
+            filter_input: Mutex::new(None),
+            format_change: Mutex::new(None),
+            orientation_override: Mutex::new(None),
+            video_frame_rate: Mutex::new(ffi::AVRational { num: 1, den: 60 }),
+            last_pts: AtomicI64::new(ffi::AV_NOPTS_VALUE),
+            last_pts_time: AtomicF64::new(0.),
+            last_display_time: AtomicF64::new(0.),
+            step: AtomicBool::new(false),
+            seek_request: Mutex::new(None),
+            seek_pending: AtomicBool::new(false),
+            seek_target: AtomicI64::new(ffi::AV_NOPTS_VALUE),
+            resync: AtomicBool::new(false),
+            video_stream_request: Mutex::new(None),
+            end_behavior: Mutex::new(EndBehavior::default()),
+            ended: AtomicBool::new(false),
+            closed: AtomicBool::new(false),
+            last_decoded_pts: AtomicI64::new(ffi::AV_NOPTS_VALUE),
+            video_drained: AtomicBool::new(true),
+            audio_drained: AtomicBool::new(true),
+            reverse_origin: AtomicI64::new(ffi::AV_NOPTS_VALUE),
+            custom_io: None,
+            subtitle_stream_idx: AtomicI64::new(-1),
+            subtitle_ctx: Mutex::new(CodecContextWrapper{ptr:std::ptr::null_mut()}),
+            subtitles: SubtitleQueue::new(),
+            external_subtitles: Mutex::new(vec![]),
+            decoder_options: Mutex::new(DecoderOptions::default()),
+            time_offset: AtomicF64::new(0.),
+            speed: AtomicF32::new(1.),
+            playback_request: Mutex::new(None),
+            backwards: AtomicBool::new(false),
+            gop_cache: Mutex::new(GopCache::new()),
+            keep_running: Arc::new(AtomicBool::new(false)),
+            threads: Mutex::new(vec![]),
+            movie_index: AtomicUsize::new(0),
+            packet_timeline: Mutex::new(PacketTimeline::new()),
         }
     }
 }
 unsafe impl Send for MovieState{}
+// shared with its packet, decode and audio threads, what they touch is behind a mutex or an atomic
+unsafe impl Sync for MovieState{}
 impl MovieState {
     pub fn set_format_context(&mut self, format_context: *mut ffi::AVFormatContext) {
         self.format_context = Mutex::new(FormatContextWrapper{ptr:format_context});
@@ -101,7 +330,7 @@ impl MovieState {
         return Ok(());
     }
 
-    pub fn clear_packet_queue(&mut self) -> Result<(), ()> {
+    pub fn clear_packet_queue(&self) -> Result<(), ()> {
         let mut vq = self.videoqueue.lock().unwrap();
 
         vq.iter_mut().for_each(|p| unsafe {
@@ -112,28 +341,50 @@ impl MovieState {
         return Ok(());
     }
 
-    pub fn clear_frame_queue(&mut self) -> Result<(), ()> {
+    pub fn clear_audio_queue(&self) -> Result<(), ()> {
+        let mut aq = self.audioqueue.lock().unwrap();
+
+        aq.iter_mut().for_each(|p| unsafe {
+            ffi::av_packet_free(&mut p.ptr as *mut *mut _);
+        });
+        aq.clear();
+        self.audio_samples.clear();
+        return Ok(());
+    }
+
+    pub fn clear_frame_queue(&self) -> Result<(), ()> {
         let mut pq = self.picq.lock().unwrap();
 
         pq.iter_mut().for_each(|f| unsafe {
             info!("free frame left in queue");
-            ffi::av_frame_free(&mut f.ptr as *mut *mut _ );
+            ffi::av_frame_free(&mut f.frame.ptr as *mut *mut _ );
         });
         pq.clear();
+        drop(pq);
+        self.clear_filtered_queue();
         return Ok(());
     }
 
-    pub fn enqueue_frame(&self, frame: *mut ffi::AVFrame) -> Result<(), ()> {
+    fn clear_filtered_queue(&self) {
+        let mut filtered = self.filtered_queue.lock().unwrap();
+        filtered.iter_mut().for_each(|f| unsafe {
+            ffi::av_frame_free(&mut f.frame.ptr as *mut *mut _);
+        });
+        filtered.clear();
+    }
+
+    /// queue a frame the caller owns, with the info taken before its pts was touched
+    pub fn enqueue_frame(&self, frame: *mut ffi::AVFrame, info: FrameInfo) -> Result<(), ()> {
         let mut pq = self.picq.lock().unwrap();
         if pq.len() >= 4 {
             // eprintln!("dropping frame");
             return Err(());
         }
-        pq.push_back(FrameWrapper{ptr:frame});
+        pq.push_back(DecodedFrame { frame: FrameWrapper{ptr:frame}, info });
         return Ok(());
     }
 
-    pub fn dequeue_frame(&self) -> Option<FrameWrapper> {
+    pub fn dequeue_frame(&self) -> Option<DecodedFrame> {
         let mut pq = self.picq.lock().unwrap();
         if pq.len() <= 0 {
             return None
@@ -141,89 +392,967 @@ impl MovieState {
         pq.pop_front()
     }
 
-    pub fn dequeue_frame_raw(&mut self) -> Option<*mut ffi::AVFrame> {
-        if (self.step == false && self.is_paused()) {
+    /// the next frame the filter graph gave out, the caller frees it
+    pub fn dequeue_filtered_frame(&self) -> Option<FilteredFrame> {
+        self.fill_filtered_queue().pop_front()
+    }
+
+    /// run queued frames through the filter graph until it gives something out
+    /// or the queue runs dry
+    fn fill_filtered_queue(&self) -> MutexGuard<'_, VecDeque<FilteredFrame>> {
+        let mut filtered = self.filtered_queue.lock().unwrap();
+        while filtered.is_empty() {
+            match self.dequeue_frame() {
+                Some(decoded) => filtered.extend(unsafe { self.filter_frame(decoded.frame, decoded.info) }),
+                None => break,
+            }
+        }
+        filtered
+    }
+
+    pub fn dequeue_frame_raw(&self) -> Option<*mut ffi::AVFrame> {
+        if (self.step.load(Ordering::Relaxed) == false && self.is_paused()) {
             return None;
         }
-        if (self.step) {
-            self.step = false;
+        self.step.store(false, Ordering::Relaxed);
+        self.dequeue_filtered_frame().map(|filtered| filtered.frame.ptr)
+    }
+
+    /// run a decoded frame through the filter graph and take every frame the graph
+    /// has ready, building the graph from the first frame and again whenever the
+    /// frames change size, pixel format, aspect or colorspace. frees `frame`. empty
+    /// when the graph held on to the frame and gave nothing back yet.
+    pub(crate) unsafe fn filter_frame(&self, mut frame: FrameWrapper, info: FrameInfo) -> Vec<FilteredFrame> {
+        let orientation = self.orientation();
+        let (stream, frame_rate) = self.filter_source();
+        let mut in_vfilter = self.in_vfilter.lock().unwrap();
+        let mut out_vfilter = self.out_vfilter.lock().unwrap();
+        let mut vgraph = self.vgraph.lock().unwrap();
+
+        let input = FilterInput::from_frame(frame.ptr, stream, frame_rate);
+        let previous = *self.filter_input.lock().unwrap();
+        if let Some(previous) = previous.filter(|previous| *previous != input) {
+            info!("📽  video format changed from {} to {}, rebuilding the filter graph", previous, input);
+            *self.format_change.lock().unwrap() = Some((previous, input));
+            ffi::avfilter_graph_free(&mut vgraph.ptr as *mut *mut _);
+            in_vfilter.ptr = std::ptr::null_mut();
+            out_vfilter.ptr = std::ptr::null_mut();
+        }
+        if in_vfilter.is_null() || out_vfilter.is_null() {
+            let description = self.filter_description();
+            if let Err(e) = init_filter(orientation, &mut vgraph.ptr, &mut out_vfilter.ptr, &mut in_vfilter.ptr, &input, description.as_deref()) {
+                // the description worked for the frames it was set with, show these unfiltered
+                error!("📽  filter graph for {}x{} failed, showing the frames as decoded: {}", input.width, input.height, e);
+                if let Err(e) = init_filter(orientation, &mut vgraph.ptr, &mut out_vfilter.ptr, &mut in_vfilter.ptr, &input, None) {
+                    error!("📽  cannot build a filter graph: {}", e);
+                }
+            }
+            *self.filter_input.lock().unwrap() = Some(input);
         }
-        let mut pq = self.picq.lock().unwrap();
-        if pq.len() <= 0 {
-            return None
+        let input_pts = (*frame.ptr).pts;
+        let mut filtered = vec![];
+        if !in_vfilter.is_null() && ffi::av_buffersrc_add_frame(in_vfilter.ptr, frame.ptr) >= 0 {
+            let sink_time_base = ffi::av_buffersink_get_time_base(out_vfilter.ptr);
+            loop {
+                let mut dest_frame = ffi::av_frame_alloc();
+                let ret = ffi::av_buffersink_get_frame_flags(out_vfilter.ptr, dest_frame, 0);
+                if ret < 0 {
+                    ffi::av_frame_free(&mut dest_frame);
+                    // EAGAIN only means the graph wants more frames first
+                    if ret != ffi::AVERROR(ffi::EAGAIN) && ret != ffi::AVERROR_EOF {
+                        error!("📽  filter graph failed: {}", AvError::from_code(ret));
+                    }
+                    break;
+                }
+                let pts = match ((*dest_frame).pts, stream.as_ref()) {
+                    (ffi::AV_NOPTS_VALUE, _) => input_pts,
+                    (pts, Some(stream)) => ffi::av_rescale_q(pts, sink_time_base, stream.time_base),
+                    (pts, None) => pts,
+                };
+                filtered.push(FilteredFrame { frame: FrameWrapper { ptr: dest_frame }, info: info.clone(), pts });
+            }
         }
-        unsafe {
-            let dest_frame = 
-                ffi::av_frame_alloc()
-                .as_mut()
-                .expect("failed to allocated memory for AVFrame");
+        ffi::av_frame_free(&mut frame.ptr);
+        filtered
+    }
 
-            let frame = pq.pop_front().unwrap();
-            let mut in_vfilter = self.in_vfilter.lock().unwrap();
-            let mut out_vfilter = self.out_vfilter.lock().unwrap();
-            let mut vgraph = self.vgraph.lock().unwrap();
+    /// the video stream and its frame rate, what the filter graph's source is set up from
+    fn filter_source(&self) -> (*const ffi::AVStream, ffi::AVRational) {
+        let stream = self.video_stream.lock().unwrap().ptr;
+        let frame_rate = match stream.is_null() {
+            true => ffi::AVRational { num: 0, den: 1 },
+            false => *self.video_frame_rate.lock().unwrap(),
+        };
+        (stream, frame_rate)
+    }
 
-            if in_vfilter.is_null() || out_vfilter.is_null() {
-                ffi::avfilter_graph_free(&mut vgraph.ptr as *mut *mut _);
-                let rotation = 0;
-                init_filter(
-                    rotation,
-                    &mut vgraph.ptr,
-                    &mut out_vfilter.ptr,
-                    &mut in_vfilter.ptr,
-                    (
-                        frame.ptr.as_ref().unwrap().width,
-                        frame.ptr.as_ref().unwrap().height,
-                    ),
-                    frame.ptr.as_ref().unwrap().format,
-                );
+    /// the format the frames changed from and to since this was last asked,
+    /// the filter graph has been rebuilt for the new one
+    pub fn take_format_change(&self) -> Option<(FilterInput, FilterInput)> {
+        self.format_change.lock().unwrap().take()
+    }
+
+    pub fn filter_description(&self) -> Option<String> {
+        self.filter_description.lock().unwrap().clone()
+    }
+
+    /// run the video through a libavfilter description such as
+    /// `hqdn3d,unsharp,eq=contrast=1.2`, `None` to show the frames as decoded.
+    /// the new graph is built right away for the frames coming in, so a bad
+    /// description is reported here and the current graph stays in place.
+    pub fn set_filter_description(&self, description: Option<&str>) -> Result<(), FilterError> {
+        let orientation = self.orientation();
+        let (stream, frame_rate) = self.filter_source();
+        let mut in_vfilter = self.in_vfilter.lock().unwrap();
+        let mut out_vfilter = self.out_vfilter.lock().unwrap();
+        let mut vgraph = self.vgraph.lock().unwrap();
+        let filtered = *self.filter_input.lock().unwrap();
+        let input = match filtered {
+            Some(input) => input,
+            // nothing was filtered yet, the decoder knows what is coming
+            None => {
+                let video_ctx = self.video_ctx.lock().unwrap();
+                match video_ctx.ptr.is_null() {
+                    false => unsafe { FilterInput::from_codec_context(video_ctx.ptr, stream, frame_rate) },
+                    true => FilterInput {
+                        width: 0,
+                        height: 0,
+                        format: ffi::AVPixelFormat_AV_PIX_FMT_NONE,
+                        sample_aspect_ratio: (0, 1),
+                        colorspace: ffi::AVColorSpace_AVCOL_SPC_UNSPECIFIED,
+                        color_range: ffi::AVColorRange_AVCOL_RANGE_UNSPECIFIED,
+                        time_base: (1, ffi::AV_TIME_BASE as i32),
+                        frame_rate: (0, 1),
+                    },
+                }
+            }
+        };
+        let description = description.map(String::from);
+        if input.is_valid() {
+            let mut graph = std::ptr::null_mut();
+            let mut sink = std::ptr::null_mut();
+            let mut source = std::ptr::null_mut();
+            init_filter(orientation, &mut graph, &mut sink, &mut source, &input, description.as_deref())?;
+            if filtered.is_some() {
+                unsafe { ffi::avfilter_graph_free(&mut vgraph.ptr as *mut *mut _) };
+                (vgraph.ptr, out_vfilter.ptr, in_vfilter.ptr) = (graph, sink, source);
+            } else {
+                // only a check, the first frame builds the graph for what it really is
+                unsafe { ffi::avfilter_graph_free(&mut graph as *mut *mut _) };
             }
-            ffi::av_buffersrc_add_frame(in_vfilter.ptr, frame.ptr);
-            ffi::av_buffersink_get_frame_flags(out_vfilter.ptr, dest_frame, 0);
-            return Some(dest_frame);
         }
+        info!("📽  video filter {}", description.as_deref().unwrap_or("off"));
+        *self.filter_description.lock().unwrap() = description;
+        Ok(())
+    }
+
+    /// which way up the video stream asks to be shown, from its display matrix or `rotate` tag
+    pub fn stream_orientation(&self) -> Orientation {
+        unsafe { Orientation::of_stream(self.video_stream.lock().unwrap().ptr) }
+    }
+
+    /// which way up the frames come out of the filter graph
+    pub fn orientation(&self) -> Orientation {
+        self.orientation_override.lock().unwrap().unwrap_or_else(|| self.stream_orientation())
     }
 
+    /// show the video this way up whatever the stream asks for, `None` goes
+    /// back to the stream's own orientation. the graph is rebuilt for the next frame.
+    pub fn set_orientation(&self, orientation: Option<Orientation>) {
+        *self.orientation_override.lock().unwrap() = orientation;
+        self.reset_filter_graph();
+        let orientation = self.orientation();
+        info!("📽  showing the video turned {}° clockwise{}", orientation.rotation, if orientation.flip { " and mirrored" } else { "" });
+    }
+
+    /// when the next frame is to be shown, in video stream time_base. filters
+    /// the next queued frame when the graph has nothing left to give out.
     pub fn peek_frame_pts(&self) -> Option<i64> {
-        let pq = self.picq.lock().unwrap();
-        if pq.len() <= 0 {
-            return None
+        self.peek_frame_pts_at(0)
+    }
+
+    /// `peek_frame_pts` of the frame `index` places behind the front, past what
+    /// the graph gave out that is the decoded frames still to be filtered
+    pub fn peek_frame_pts_at(&self, index: usize) -> Option<i64> {
+        let filtered = self.fill_filtered_queue();
+        if let Some(next) = filtered.get(index) {
+            return Some(next.pts);
         }
-        let front = pq.front().unwrap();
-        unsafe {Some(front.ptr.as_ref().unwrap().pts)}
+        let pq = self.picq.lock().unwrap();
+        pq.get(index - filtered.len()).map(|queued| unsafe { (*queued.frame.ptr).pts })
+    }
+
+    pub fn pause(&self) {
+        self.set_paused(!self.is_paused());
+    }
+
+    pub fn set_paused(&self, paused: bool) {
+        self.paused.store(paused, std::sync::atomic::Ordering::Relaxed);
+        self.audio_samples.set_paused(paused);
+        self.resync.store(true, Ordering::Relaxed);
+    }
+
+    pub fn has_audio(&self) -> bool {
+        self.audio_stream_idx >= 0
     }
 
-    pub fn pause(&mut self) {
-        let state = self.paused.load(std::sync::atomic::Ordering::Relaxed);
-        self.paused.store(!state, std::sync::atomic::Ordering::Relaxed);
-        self.last_pts = ffi::AV_NOPTS_VALUE;
+    pub fn is_muted(&self) -> bool {
+        self.audio_samples.is_muted()
+    }
+
+    pub fn set_muted(&self, muted: bool) {
+        self.audio_samples.set_muted(muted);
     }
 
     pub fn is_paused(&self) -> bool {
         self.paused.load(std::sync::atomic::Ordering::Relaxed)
     }
 
-    pub fn step(&mut self) {
+    pub fn step(&self) {
         if ! self.paused.load(std::sync::atomic::Ordering::Relaxed) {
             return;
         }
-        self.step = true;
+        self.step.store(true, Ordering::Relaxed);
+    }
+
+    pub fn step_force(&self) {
+        self.step.store(true, Ordering::Relaxed);
+    }
+
+    /// ask the packet thread to jump to `position` seconds from the start of the movie
+    pub fn seek(&self, position: f64, mode: SeekMode) {
+        self.seek_pts(self.position_to_pts(position), mode, None);
+    }
+
+    /// `seek` with the target in video stream time_base units. the packet thread
+    /// reports `report` back once it has seeked, unless another seek replaced this one.
+    pub fn seek_pts(&self, pts: i64, mode: SeekMode, report: Option<Command>) {
+        let mut seek_request = self.seek_request.lock().unwrap();
+        // flagged before the packet thread can take the request, nothing stale is shown meanwhile
+        self.seek_pending.store(true, Ordering::Relaxed);
+        self.resync.store(true, Ordering::Relaxed);
+        *seek_request = Some(SeekRequest { pts, mode, report });
+    }
+
+    pub fn is_seeking(&self) -> bool {
+        self.seek_pending.load(Ordering::Relaxed)
+    }
+
+    pub fn take_seek_request(&self) -> Option<SeekRequest> {
+        self.seek_request.lock().unwrap().take()
+    }
+
+    /// let presentation go on again, unless a seek, stream switch or playback
+    /// request came in while the last one ran. runs on the packet thread.
+    fn clear_seek_pending(&self) {
+        let seek_request = self.seek_request.lock().unwrap();
+        let video_stream_request = self.video_stream_request.lock().unwrap();
+        let playback_request = self.playback_request.lock().unwrap();
+        if seek_request.is_none() && video_stream_request.is_none() && playback_request.is_none() {
+            self.seek_pending.store(false, Ordering::Relaxed);
+        }
+    }
+
+    /// jump the demuxer to the request and throw away everything queued or buffered
+    /// in the decoders. runs on the packet thread.
+    pub unsafe fn execute_seek(&self, request: SeekRequest) -> Result<(), AvError> {
+        // holding the packet queue keeps the decode thread out until the codec is flushed
+        let mut vq = self.videoqueue.lock().unwrap();
+        let ret = ffi::av_seek_frame(
+            self.format_context.lock().unwrap().ptr,
+            self.video_stream_index() as i32,
+            request.pts,
+            ffi::AVSEEK_FLAG_BACKWARD as i32,
+        );
+        vq.iter_mut().for_each(|p| {
+            ffi::av_packet_free(&mut p.ptr as *mut *mut _);
+        });
+        vq.clear();
+        {
+            let video_ctx = self.video_ctx.lock().unwrap();
+            if !video_ctx.is_null() {
+                ffi::avcodec_flush_buffers(video_ctx.ptr);
+            }
+        }
+        {
+            self.clear_audio_queue().unwrap();
+            let audio_ctx = self.audio_ctx.lock().unwrap();
+            if !audio_ctx.is_null() {
+                ffi::avcodec_flush_buffers(audio_ctx.ptr);
+            }
+        }
+        self.clear_frame_queue().unwrap();
+        self.flush_subtitles();
+        // temporal filters would still blend in frames from before the seek
+        self.reset_filter_graph();
+
+        let target = match request.mode {
+            SeekMode::Accurate => request.pts,
+            SeekMode::Keyframe => ffi::AV_NOPTS_VALUE,
+        };
+        self.seek_target.store(target, Ordering::Relaxed);
+        // a seek always continues forwards, even from the end of the file
+        self.reverse_origin.store(ffi::AV_NOPTS_VALUE, Ordering::Relaxed);
+        self.backwards.store(false, Ordering::Relaxed);
+        self.ended.store(false, Ordering::Relaxed);
+        self.clear_seek_pending();
+        drop(vq);
+
+        if ret < 0 {
+            return Err(AvError::from_code(ret));
+        }
+        Ok(())
+    }
+
+    /// note a packet the demuxer read in the timeline. runs on the packet thread.
+    pub unsafe fn record_packet(&self, packet: *const ffi::AVPacket) {
+        let mut timeline = self.packet_timeline.lock().unwrap();
+        let stream_index = (*packet).stream_index as usize;
+        if !timeline.has_stream(stream_index) {
+            let format_ctx = self.format_context.lock().unwrap();
+            match format_ctx.ptr.as_ref() {
+                Some(ctx) if stream_index < ctx.nb_streams as usize => {
+                    let stream = &**ctx.streams.add(stream_index);
+                    timeline.add_stream(stream_index, stream.time_base, stream.start_time);
+                }
+                _ => return,
+            }
+        }
+        timeline.record_packet(packet);
+    }
+
+    /// mark the packet a decoded video frame came from with its picture type
+    pub unsafe fn note_picture_type(&self, frame: *const ffi::AVFrame) {
+        let pict_type = ffi::av_get_picture_type_char((*frame).pict_type) as u8 as char;
+        let stream_index = self.video_stream_index();
+        if stream_index >= 0 {
+            self.packet_timeline.lock().unwrap().set_picture_type(stream_index as usize, (*frame).pkt_dts, pict_type);
+        }
+    }
+
+    /// container index of the video stream being decoded
+    pub fn video_stream_index(&self) -> i64 {
+        self.video_stream_idx.load(Ordering::Relaxed)
+    }
+
+    /// every video stream in the container, decodable or not
+    pub fn video_streams(&self) -> Vec<VideoStreamInfo> {
+        let format_ctx = self.format_context.lock().unwrap();
+        let active = self.video_stream_index();
+        let streams = match unsafe { format_ctx.ptr.as_ref() } {
+            Some(ctx) if !ctx.streams.is_null() => unsafe {
+                std::slice::from_raw_parts(ctx.streams, ctx.nb_streams as usize)
+            },
+            _ => return vec![],
+        };
+        streams.iter().enumerate().filter_map(|(index, stream)| unsafe {
+            let params = (**stream).codecpar.as_ref()?;
+            if params.codec_type != ffi::AVMediaType_AVMEDIA_TYPE_VIDEO {
+                return None;
+            }
+            let codec_name = CStr::from_ptr(ffi::avcodec_get_name(params.codec_id))
+                .to_string_lossy()
+                .into_owned();
+            Some(VideoStreamInfo {
+                index,
+                codec_name,
+                width: params.width,
+                height: params.height,
+                frame_rate: ffi::av_guess_frame_rate(format_ctx.ptr, *stream, std::ptr::null_mut()),
+                decodable: !ffi::avcodec_find_decoder(params.codec_id).is_null(),
+                active: index as i64 == active,
+            })
+        }).collect()
+    }
+
+    /// switch to another video stream. the packet thread swaps the decoder,
+    /// resumes at the current position and reports `report` back.
+    pub fn select_video_stream(&self, index: usize, report: Option<Command>) -> Result<(), MovieError> {
+        match self.video_streams().iter().find(|s| s.index == index) {
+            Some(info) if info.decodable => {},
+            _ => return Err(MovieError::InvalidVideoStream(index)),
+        }
+        if index as i64 == self.video_stream_index() {
+            return Ok(());
+        }
+        let position = self.position();
+        let mut video_stream_request = self.video_stream_request.lock().unwrap();
+        // hold back presentation until the new stream is flowing
+        self.seek_pending.store(true, Ordering::Relaxed);
+        self.resync.store(true, Ordering::Relaxed);
+        *video_stream_request = Some(VideoStreamRequest { index, position, report });
+        Ok(())
+    }
+
+    pub fn take_video_stream_request(&self) -> Option<VideoStreamRequest> {
+        self.video_stream_request.lock().unwrap().take()
+    }
+
+    /// replace the video decoder with one for the requested stream and seek
+    /// the new stream to where the old one was. runs on the packet thread.
+    pub unsafe fn execute_video_stream_switch(&self, request: VideoStreamRequest) -> Result<(), MovieError> {
+        let stream = {
+            let format_ctx = self.format_context.lock().unwrap();
+            let ctx = format_ctx.ptr.as_ref().unwrap();
+            if request.index >= ctx.nb_streams as usize {
+                self.clear_seek_pending();
+                return Err(MovieError::InvalidVideoStream(request.index));
+            }
+            *ctx.streams.add(request.index)
+        };
+        let decoder = self.decoder_options.lock().unwrap().clone();
+        let opened = match open_video_codec(stream, &decoder) {
+            // the named decoder was picked for the old stream's codec
+            Err(MovieError::UnsupportedDecoder(name)) => {
+                info!("📽📽  {} cannot decode stream {}, using the default decoder", name, request.index);
+                open_video_codec(stream, &DecoderOptions { name: None, ..decoder })
+            }
+            result => result,
+        };
+        let codec_ctx = match opened {
+            Ok((codec_ctx, _)) => codec_ctx,
+            Err(e) => {
+                self.clear_seek_pending();
+                return Err(e);
+            }
+        };
+        {
+            // holding the packet queue keeps the decode thread away from the codec
+            let mut vq = self.videoqueue.lock().unwrap();
+            vq.iter_mut().for_each(|p| {
+                ffi::av_packet_free(&mut p.ptr as *mut *mut _);
+            });
+            vq.clear();
+            let mut video_ctx = self.video_ctx.lock().unwrap();
+            ffi::avcodec_free_context(&mut video_ctx.ptr as *mut *mut _);
+            video_ctx.ptr = codec_ctx;
+            self.video_stream.lock().unwrap().ptr = stream;
+            self.video_stream_idx.store(request.index as i64, Ordering::Relaxed);
+            *self.video_frame_rate.lock().unwrap() = ffi::av_guess_frame_rate(
+                self.format_context.lock().unwrap().ptr,
+                stream,
+                std::ptr::null_mut(),
+            );
+        }
+        self.clear_frame_queue().unwrap();
+        self.gop_cache.lock().unwrap().clear();
+        // the new stream may differ in size and pixel format
+        self.reset_filter_graph();
+        info!("📽📽  switched to video stream {}", request.index);
+
+        let pts = self.position_to_pts(request.position);
+        if let Err(e) = self.execute_seek(SeekRequest { pts, mode: SeekMode::Keyframe, report: None }) {
+            // the new stream still plays, just from wherever the demuxer is
+            error!("📽📽  failed to seek the new video stream: {}", e);
+        }
+        Ok(())
+    }
+
+    /// container index of the subtitle stream being decoded, None when subtitles are off
+    pub fn subtitle_stream_index(&self) -> Option<usize> {
+        match self.subtitle_stream_idx.load(Ordering::Relaxed) {
+            index if index >= 0 => Some(index as usize),
+            _ => None,
+        }
+    }
+
+    /// every subtitle stream in the container we have a decoder for
+    pub fn subtitle_streams(&self) -> Vec<SubtitleStreamInfo> {
+        let format_ctx = self.format_context.lock().unwrap();
+        let active = self.subtitle_stream_index();
+        let streams = match unsafe { format_ctx.ptr.as_ref() } {
+            Some(ctx) if !ctx.streams.is_null() => unsafe {
+                std::slice::from_raw_parts(ctx.streams, ctx.nb_streams as usize)
+            },
+            _ => return vec![],
+        };
+        streams.iter().enumerate().filter_map(|(index, stream)| unsafe {
+            let params = (**stream).codecpar.as_ref()?;
+            if params.codec_type != ffi::AVMediaType_AVMEDIA_TYPE_SUBTITLE
+                || ffi::avcodec_find_decoder(params.codec_id).is_null() {
+                return None;
+            }
+            let codec_name = CStr::from_ptr(ffi::avcodec_get_name(params.codec_id))
+                .to_string_lossy()
+                .into_owned();
+            let key = std::ffi::CString::new("language").unwrap();
+            let language = ffi::av_dict_get((**stream).metadata, key.as_ptr(), std::ptr::null(), 0)
+                .as_ref()
+                .map(|tag| CStr::from_ptr(tag.value).to_string_lossy().into_owned());
+            let descriptor = ffi::avcodec_descriptor_get(params.codec_id).as_ref();
+            Some(SubtitleStreamInfo {
+                index,
+                codec_name,
+                language,
+                bitmap: descriptor.map_or(false, |d| d.props & ffi::AV_CODEC_PROP_BITMAP_SUB as i32 != 0),
+                active: Some(index) == active,
+            })
+        }).collect()
+    }
+
+    /// decode the subtitle stream at `index`, or turn subtitles off with None.
+    /// subtitles show up from the next packet the demuxer reads.
+    pub fn select_subtitle_stream(&self, index: Option<usize>) -> Result<(), MovieError> {
+        let codec_ctx = match index {
+            Some(index) => {
+                let stream = {
+                    let format_ctx = self.format_context.lock().unwrap();
+                    match unsafe { format_ctx.ptr.as_ref() } {
+                        Some(ctx) if index < ctx.nb_streams as usize => unsafe { *ctx.streams.add(index) },
+                        _ => return Err(MovieError::InvalidSubtitleStream(index)),
+                    }
+                };
+                unsafe { open_subtitle_codec(stream)? }
+            }
+            None => std::ptr::null_mut(),
+        };
+        let mut subtitle_ctx = self.subtitle_ctx.lock().unwrap();
+        unsafe { ffi::avcodec_free_context(&mut subtitle_ctx.ptr as *mut *mut _) };
+        subtitle_ctx.ptr = codec_ctx;
+        self.subtitle_stream_idx.store(index.map_or(-1, |i| i as i64), Ordering::Relaxed);
+        self.subtitles.clear();
+        Ok(())
+    }
+
+    /// read a SubRip file to show alongside the movie, replacing any loaded before.
+    /// returns the number of subtitles found.
+    pub fn load_external_subtitles(&self, path: &Path) -> std::io::Result<usize> {
+        let contents = std::fs::read(path)?;
+        let subtitles = parse_srt(&String::from_utf8_lossy(&contents));
+        let count = subtitles.len();
+        *self.external_subtitles.lock().unwrap() = subtitles;
+        info!("📽📽  loaded {} subtitles from {}", count, path.display());
+        Ok(count)
+    }
+
+    /// the subtitles to draw over the frame currently on screen
+    pub fn active_subtitles(&self) -> Vec<Subtitle> {
+        let position = self.position();
+        let mut active = self.subtitles.visible_at(position);
+        active.extend(self.external_subtitles.lock().unwrap()
+            .iter()
+            .filter(|s| s.is_visible_at(position))
+            .cloned());
+        active
+    }
+
+    /// decode a packet of the selected subtitle stream. runs on the packet thread.
+    pub unsafe fn decode_subtitle_packet(&self, packet: *mut ffi::AVPacket) {
+        let subtitle_ctx = self.subtitle_ctx.lock().unwrap();
+        if subtitle_ctx.is_null() {
+            return;
+        }
+        let time_base = (*subtitle_ctx.ptr).pkt_timebase;
+        let to_seconds = |ts: i64| ts as f64 * time_base.num as f64 / time_base.den as f64;
+        let (video_time_base, video_start) = self.video_time_base_and_start();
+        let movie_start = video_start as f64 * video_time_base.num as f64 / video_time_base.den as f64;
+        let packet_time = match (*packet).pts {
+            ffi::AV_NOPTS_VALUE => self.position(),
+            pts => to_seconds(pts) - movie_start,
+        };
+        if let Some(mut subtitle) = decode_subtitle(subtitle_ctx.ptr, packet, packet_time, to_seconds((*packet).duration)) {
+            // DVD and DVB decoders may not know the picture size, it is the video's then
+            if let SubtitleContent::Bitmap { canvas, .. } = &mut subtitle.content {
+                if canvas.0 <= 0 || canvas.1 <= 0 {
+                    let video_stream = self.video_stream.lock().unwrap();
+                    if let Some(params) = video_stream.ptr.as_ref().and_then(|s| s.codecpar.as_ref()) {
+                        *canvas = (params.width, params.height);
+                    }
+                }
+            }
+            self.subtitles.push(subtitle);
+        }
+    }
+
+    /// forget decoded subtitles, after a seek or a rewind
+    fn flush_subtitles(&self) {
+        self.subtitles.clear();
+        let subtitle_ctx = self.subtitle_ctx.lock().unwrap();
+        if !subtitle_ctx.is_null() {
+            unsafe { ffi::avcodec_flush_buffers(subtitle_ctx.ptr) };
+        }
+    }
+
+    /// drop the filter graph so it is rebuilt from the next frame
+    pub fn reset_filter_graph(&self) {
+        let mut in_vfilter = self.in_vfilter.lock().unwrap();
+        let mut out_vfilter = self.out_vfilter.lock().unwrap();
+        let mut vgraph = self.vgraph.lock().unwrap();
+        unsafe { ffi::avfilter_graph_free(&mut vgraph.ptr as *mut *mut _) };
+        in_vfilter.ptr = std::ptr::null_mut();
+        out_vfilter.ptr = std::ptr::null_mut();
+        drop((in_vfilter, out_vfilter, vgraph));
+        // what the old graph gave out is not shown
+        self.clear_filtered_queue();
+    }
+
+    /// seconds from the start of the movie to video stream pts
+    pub fn position_to_pts(&self, position: f64) -> i64 {
+        let (time_base, start_time) = self.video_time_base_and_start();
+        start_time + (position * time_base.den as f64 / time_base.num as f64).round() as i64
+    }
+
+    /// video stream pts to seconds from the start of the movie
+    pub fn pts_to_position(&self, pts: i64) -> f64 {
+        let (time_base, start_time) = self.video_time_base_and_start();
+        (pts - start_time) as f64 * time_base.num as f64 / time_base.den as f64
+    }
+
+    /// seconds from the start of the movie of the last displayed frame
+    pub fn position(&self) -> f64 {
+        self.displayed_pts().map_or(0., |pts| self.pts_to_position(pts))
+    }
+
+    /// audio stream pts to seconds from the start of the movie, measured like `pts_to_position`
+    pub fn audio_pts_to_position(&self, pts: i64) -> Option<f64> {
+        let time_base = unsafe { self.audio_stream.lock().unwrap().ptr.as_ref()?.time_base };
+        let (video_time_base, start_time) = self.video_time_base_and_start();
+        let start = start_time as f64 * video_time_base.num as f64 / video_time_base.den as f64;
+        Some(pts as f64 * time_base.num as f64 / time_base.den as f64 - start)
+    }
+
+    /// seconds from the start of the movie the video is at right now, going by the
+    /// presentation anchor. None while nothing plays forwards to follow.
+    pub fn video_clock(&self) -> Option<f64> {
+        if self.last_pts.load(Ordering::Relaxed) == ffi::AV_NOPTS_VALUE
+            || self.is_paused()
+            || self.is_seeking()
+            || self.is_playing_backwards()
+        {
+            return None;
+        }
+        let now = unsafe { ffi::av_gettime_relative() } as f64 / 1_000_000.;
+        Some(self.last_pts_time.load() + (now - self.last_display_time.load()) * self.speed() as f64)
+    }
+
+    pub fn time_offset(&self) -> f64 {
+        self.time_offset.load()
+    }
+
+    /// with an offset of 2.0 this movie shows its frame at 12s while the others show 10s
+    pub fn set_time_offset(&self, offset: f64) {
+        self.time_offset.store(offset);
+        self.resync.store(true, Ordering::Relaxed);
+    }
+
+    pub fn speed(&self) -> f32 {
+        self.speed.load()
+    }
+
+    pub fn has_threads(&self) -> bool {
+        !self.threads.lock().unwrap().is_empty()
+    }
+
+    /// stop the packet and decode threads and wait for them
+    pub fn stop_threads(&self) {
+        self.keep_running.store(false, Ordering::Relaxed);
+        let threads: Vec<_> = self.threads.lock().unwrap().drain(..).collect();
+        for thread in threads {
+            if thread.join().is_err() {
+                error!("📽📽  a thread of movie {} panicked", self.movie_index.load(Ordering::Relaxed));
+            }
+        }
+    }
+
+    /// the buffered audio was stretched for the old rate and is dropped
+    pub fn set_speed(&self, speed: f32) {
+        self.speed.store(speed);
+        self.audio_samples.clear();
+        self.resync.store(true, Ordering::Relaxed);
+    }
+
+    /// how long the decoder waits for room in the frame queue, shorter when
+    /// playing fast so it keeps up with the frames being skipped
+    pub(crate) fn frame_queue_wait(&self) -> std::time::Duration {
+        std::time::Duration::from_secs_f32(0.07 / self.speed().clamp(1., 14.))
+    }
+
+    pub fn end_behavior(&self) -> EndBehavior {
+        *self.end_behavior.lock().unwrap()
+    }
+
+    pub fn set_end_behavior(&self, behavior: EndBehavior) {
+        *self.end_behavior.lock().unwrap() = behavior;
+    }
+
+    /// true once the movie stopped at the end of the file
+    pub fn has_ended(&self) -> bool {
+        self.ended.load(Ordering::Relaxed)
+    }
+
+    pub fn is_closed(&self) -> bool {
+        self.closed.load(Ordering::Relaxed)
+    }
+
+    pub fn is_reversing(&self) -> bool {
+        self.reverse_origin.load(Ordering::Relaxed) != ffi::AV_NOPTS_VALUE
+    }
+
+    /// true after `play_backwards` until the movie plays forwards again
+    pub fn is_playing_backwards(&self) -> bool {
+        self.backwards.load(Ordering::Relaxed)
+    }
+
+    /// pts of the frame on screen, in the stream's own time even while reversing
+    pub fn displayed_pts(&self) -> Option<i64> {
+        let last_pts = self.last_pts.load(Ordering::Relaxed);
+        if last_pts == ffi::AV_NOPTS_VALUE {
+            return None;
+        }
+        match self.reverse_origin.load(Ordering::Relaxed) {
+            ffi::AV_NOPTS_VALUE => Some(last_pts),
+            origin => Some(2 * origin - last_pts),
+        }
     }
 
-    pub fn step_force(&mut self) {
-        self.step = true;
+    /// play backwards from the frame with `from_pts`, normally the one on screen
+    pub fn play_backwards(&self, from_pts: i64, report: Option<Command>) {
+        self.backwards.store(true, Ordering::Relaxed);
+        self.request_playback(PlaybackRequest::Reverse { from_pts, report });
     }
 
-    pub fn update_last_pts_time(&mut self, pts: i64) {
+    /// play forwards again from the frame with `from_pts`
+    pub fn play_forwards(&self, from_pts: i64, report: Option<Command>) {
+        self.backwards.store(false, Ordering::Relaxed);
+        self.seek_pts(from_pts, SeekMode::Accurate, report);
+        if self.is_paused() {
+            self.step_force();
+        }
+    }
+
+    /// show the newest frame before `before_pts`, only while paused
+    pub fn step_back(&self, before_pts: i64, report: Option<Command>) -> Result<(), MovieError> {
+        if !self.is_paused() {
+            return Err(MovieError::NotPaused);
+        }
+        self.request_playback(PlaybackRequest::StepBack { before_pts, report });
+        self.step_force();
+        Ok(())
+    }
+
+    fn request_playback(&self, request: PlaybackRequest) {
+        let mut playback_request = self.playback_request.lock().unwrap();
+        // nothing queued is shown until the packet thread is done
+        self.seek_pending.store(true, Ordering::Relaxed);
+        self.resync.store(true, Ordering::Relaxed);
+        *playback_request = Some(request);
+    }
+
+    pub fn take_playback_request(&self) -> Option<PlaybackRequest> {
+        self.playback_request.lock().unwrap().take()
+    }
+
+    /// throw away what is queued and mirror the pts of the frames to come
+    /// around `from_pts`. the packet thread then queues GOPs newest first.
+    pub unsafe fn start_reverse(&self, from_pts: i64) {
+        {
+            let mut vq = self.videoqueue.lock().unwrap();
+            vq.iter_mut().for_each(|p| {
+                ffi::av_packet_free(&mut p.ptr as *mut *mut _);
+            });
+            vq.clear();
+        }
+        self.clear_audio_queue().unwrap();
+        self.clear_frame_queue().unwrap();
+        self.flush_subtitles();
+        // temporal filters would blend in frames played the other way
+        self.reset_filter_graph();
+        self.seek_target.store(ffi::AV_NOPTS_VALUE, Ordering::Relaxed);
+        self.reverse_origin.store(from_pts, Ordering::Relaxed);
+        self.ended.store(false, Ordering::Relaxed);
+        self.clear_seek_pending();
+    }
+
+    /// queue the newest frame before `before_pts` and set the demuxer up to
+    /// continue forwards after it. the GOP is decoded once and cached, so the
+    /// frame to show comes from the cache while stepping back through it. the
+    /// accurate seek after it still decodes forwards from the keyframe on
+    /// every step, so playing on resumes after the frame shown.
+    pub unsafe fn execute_step_back(&self, before_pts: i64) -> Result<(), AvError> {
+        let cached = self.gop_cache.lock().unwrap().frame_before(before_pts);
+        let frame = match cached {
+            Some(frame) => frame,
+            None => {
+                let frames = match self.decode_gop_before(before_pts) {
+                    Ok(frames) => frames,
+                    Err(e) => {
+                        self.step.store(false, Ordering::Relaxed);
+                        self.clear_seek_pending();
+                        return Err(e);
+                    }
+                };
+                let mut cache = self.gop_cache.lock().unwrap();
+                cache.replace(before_pts, frames);
+                match cache.frame_before(before_pts) {
+                    Some(frame) => frame,
+                    // already on the first frame, the frame on screen stays
+                    None => {
+                        self.step.store(false, Ordering::Relaxed);
+                        self.clear_seek_pending();
+                        return Ok(());
+                    }
+                }
+            }
+        };
+        let pts = (*frame.ptr).pts;
+        // frames up to and including this one are dropped on the way forwards
+        let result = self.execute_seek(SeekRequest { pts: pts + 1, mode: SeekMode::Accurate, report: None });
+        // nothing is queued, the decode thread waits for the packets we have yet to read
+        if self.enqueue_frame(frame.ptr, FrameInfo::from_frame(frame.ptr)).is_err() {
+            let mut frame = frame;
+            ffi::av_frame_free(&mut frame.ptr);
+        }
+        result
+    }
+
+    /// jump the demuxer back to the first frame without touching the queues,
+    /// whatever is queued still plays. runs on the packet thread.
+    pub unsafe fn rewind(&self) -> Result<(), AvError> {
+        self.reverse_origin.store(ffi::AV_NOPTS_VALUE, Ordering::Relaxed);
+        // they are decoded again on the next pass
+        self.flush_subtitles();
+        let ret = ffi::av_seek_frame(
+            self.format_context.lock().unwrap().ptr,
+            self.video_stream_index() as i32,
+            self.position_to_pts(0.),
+            ffi::AVSEEK_FLAG_BACKWARD as i32,
+        );
+        if ret < 0 {
+            return Err(AvError::from_code(ret));
+        }
+        Ok(())
+    }
+
+    /// decode every frame of the GOP that ends just before `end_pts`, newest first.
+    /// an empty list means `end_pts` is the first frame of the movie.
+    /// takes over the demuxer and the video decoder, runs on the packet thread.
+    pub unsafe fn decode_gop_before(&self, end_pts: i64) -> Result<Vec<FrameWrapper>, AvError> {
+        if end_pts <= self.position_to_pts(0.) {
+            return Ok(vec![]);
+        }
+        // holding the packet queue keeps the decode thread away from the codec
+        let mut vq = self.videoqueue.lock().unwrap();
+        vq.iter_mut().for_each(|p| {
+            ffi::av_packet_free(&mut p.ptr as *mut *mut _);
+        });
+        vq.clear();
+        let format_ctx = self.format_context.lock().unwrap().ptr;
+        let video_ctx = self.video_ctx.lock().unwrap();
+        let stream_index = self.video_stream_index();
+
+        let ret = ffi::av_seek_frame(format_ctx, stream_index as i32, end_pts - 1, ffi::AVSEEK_FLAG_BACKWARD as i32);
+        if ret < 0 {
+            return Err(AvError::from_code(ret));
+        }
+        ffi::avcodec_flush_buffers(video_ctx.ptr);
+
+        let mut packet = ffi::av_packet_alloc();
+        let mut frame = ffi::av_frame_alloc();
+        let mut frames: Vec<FrameWrapper> = vec![];
+        let receive_frames = |frames: &mut Vec<FrameWrapper>| {
+            while ffi::avcodec_receive_frame(video_ctx.ptr, frame) >= 0 {
+                if (*frame).pts != ffi::AV_NOPTS_VALUE && (*frame).pts < end_pts {
+                    (*frame).format = (*video_ctx.ptr).pix_fmt;
+                    frames.push(FrameWrapper { ptr: ffi::av_frame_clone(frame) });
+                }
+                ffi::av_frame_unref(frame);
+            }
+        };
+        while ffi::av_read_frame(format_ctx, packet) >= 0 {
+            if (*packet).stream_index as i64 != stream_index {
+                ffi::av_packet_unref(packet);
+                continue;
+            }
+            // dts only grows, nothing after this can land before end_pts
+            if (*packet).dts != ffi::AV_NOPTS_VALUE && (*packet).dts >= end_pts {
+                ffi::av_packet_unref(packet);
+                break;
+            }
+            let ret = ffi::avcodec_send_packet(video_ctx.ptr, packet);
+            ffi::av_packet_unref(packet);
+            if ret < 0 {
+                error!("Error while sending a packet to the decoder. {:?}", ffi::av_err2str(ret));
+                continue;
+            }
+            receive_frames(&mut frames);
+        }
+        ffi::avcodec_send_packet(video_ctx.ptr, std::ptr::null());
+        receive_frames(&mut frames);
+        ffi::avcodec_flush_buffers(video_ctx.ptr);
+        ffi::av_packet_free(&mut packet);
+        ffi::av_frame_free(&mut frame);
+        drop(vq);
+
+        frames.sort_by_key(|f| std::cmp::Reverse((*f.ptr).pts));
+        Ok(frames)
+    }
+
+    fn video_time_base_and_start(&self) -> (ffi::AVRational, i64) {
+        let stream = self.video_stream.lock().unwrap();
+        match unsafe { stream.ptr.as_ref() } {
+            Some(stream) => {
+                let start_time = match stream.start_time {
+                    ffi::AV_NOPTS_VALUE => 0,
+                    start_time => start_time,
+                };
+                (stream.time_base, start_time)
+            }
+            None => (ffi::AVRational { num: 1, den: ffi::AV_TIME_BASE as i32 }, 0),
+        }
+    }
+
+    pub fn update_last_pts_time(&self, pts: i64) {
         let time_base = unsafe {(*(self.video_stream.lock().unwrap()).ptr).time_base};
-        self.last_pts_time = pts as f64  * time_base.num as f64 / time_base.den as f64;
+        self.last_pts_time.store(pts as f64  * time_base.num as f64 / time_base.den as f64);
     }
 
-    pub fn update_last_time(&mut self, t: i64) {
-        self.last_pts_time = (t as f64) / 1_000_000.;
+    pub fn update_last_time(&self, t: i64) {
+        self.last_pts_time.store((t as f64) / 1_000_000.);
     }
 
 }
+/// allocate and open a decoder for a video stream.
+/// also returns the decoder options `avcodec_open2` did not consume.
+pub(crate) unsafe fn open_video_codec(
+    stream: *mut ffi::AVStream,
+    decoder: &DecoderOptions,
+) -> Result<(*mut ffi::AVCodecContext, Vec<String>), MovieError> {
+    let index = (*stream).index as usize;
+    let params = (*stream).codecpar.as_ref().ok_or(MovieError::InvalidVideoStream(index))?;
+    if params.codec_type != ffi::AVMediaType_AVMEDIA_TYPE_VIDEO {
+        return Err(MovieError::InvalidVideoStream(index));
+    }
+    let codec = match &decoder.name {
+        Some(name) => {
+            let c_name = CString::new(name.as_str()).map_err(|_| MovieError::UnsupportedDecoder(name.clone()))?;
+            let codec = ffi::avcodec_find_decoder_by_name(c_name.as_ptr());
+            match codec.as_ref() {
+                Some(c) if c.id == params.codec_id => codec,
+                _ => return Err(MovieError::UnsupportedDecoder(name.clone())),
+            }
+        }
+        None => ffi::avcodec_find_decoder(params.codec_id),
+    };
+    if codec.is_null() {
+        return Err(MovieError::InvalidVideoStream(index));
+    }
+    let mut video_ctx = ffi::avcodec_alloc_context3(codec);
+    let ret = ffi::avcodec_parameters_to_context(video_ctx, params);
+    if ret < 0 {
+        ffi::avcodec_free_context(&mut video_ctx);
+        return Err(MovieError::CodecParameters(AvError::from_code(ret)));
+    }
+    (*video_ctx).pkt_timebase = (*stream).time_base;
+    decoder.apply_threading(video_ctx);
+    let mut options = Dictionary::from_pairs(&decoder.options);
+    let ret = ffi::avcodec_open2(video_ctx, codec, &mut options.ptr);
+    if ret < 0 {
+        ffi::avcodec_free_context(&mut video_ctx);
+        return Err(MovieError::CodecOpen(AvError::from_code(ret)));
+    }
+    Ok((video_ctx, options.keys()))
+}
+
 pub fn movie_state_enqueue_packet(videoqueue: &Mutex<VecDeque<PacketWrapper>>, packet: *mut ffi::AVPacket) -> Result<(), ()> {
     let mut vq = videoqueue.lock().unwrap();
     if vq.len() >= PACKET_QUEUE_SIZE {
@@ -232,7 +1361,15 @@ pub fn movie_state_enqueue_packet(videoqueue: &Mutex<VecDeque<PacketWrapper>>, p
     vq.push_back(PacketWrapper{ptr:packet});
     return Ok(());
 }
-pub fn movie_state_enqueue_frame(picq: &Mutex<VecDeque<FrameWrapper>>, frame: *mut ffi::AVFrame) -> Result<(), ()> {
+pub fn movie_state_enqueue_audio_packet(audioqueue: &Mutex<VecDeque<PacketWrapper>>, packet: *mut ffi::AVPacket) -> Result<(), ()> {
+    let mut aq = audioqueue.lock().unwrap();
+    if aq.len() >= AUDIO_PACKET_QUEUE_SIZE {
+        return Err(());
+    }
+    aq.push_back(PacketWrapper{ptr:packet});
+    return Ok(());
+}
+pub fn movie_state_enqueue_frame(picq: &Mutex<VecDeque<DecodedFrame>>, frame: *mut ffi::AVFrame) -> Result<(), ()> {
 
     let mut pq = picq.lock().unwrap();
     if pq.len() >= 4 {
@@ -240,13 +1377,50 @@ pub fn movie_state_enqueue_frame(picq: &Mutex<VecDeque<FrameWrapper>>, frame: *m
         return Err(());
     }
     unsafe {
+        let info = FrameInfo::from_frame(frame);
         let clone_frame = ffi::av_frame_clone(frame);
         unsafe { ffi::av_frame_unref(frame as *mut _); }
-        pq.push_back(FrameWrapper{ptr:clone_frame});
+        pq.push_back(DecodedFrame { frame: FrameWrapper{ptr:clone_frame}, info });
     }
     return Ok(());
 }
 
+/// an f64 stored as its bits, for the times the UI and control threads both read
+#[derive(Debug, Default)]
+pub struct AtomicF64(AtomicU64);
+
+impl AtomicF64 {
+    pub fn new(value: f64) -> AtomicF64 {
+        AtomicF64(AtomicU64::new(value.to_bits()))
+    }
+
+    pub fn load(&self) -> f64 {
+        f64::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    pub fn store(&self, value: f64) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+}
+
+/// the same for an f32
+#[derive(Debug, Default)]
+pub struct AtomicF32(AtomicU32);
+
+impl AtomicF32 {
+    pub fn new(value: f32) -> AtomicF32 {
+        AtomicF32(AtomicU32::new(value.to_bits()))
+    }
+
+    pub fn load(&self) -> f32 {
+        f32::from_bits(self.0.load(Ordering::Relaxed))
+    }
+
+    pub fn store(&self, value: f32) {
+        self.0.store(value.to_bits(), Ordering::Relaxed);
+    }
+}
+
 pub struct FilterGraphWrapper {
     pub ptr: *mut ffi::AVFilterGraph,
 }
@@ -320,3 +1494,17 @@ impl Deref for FrameWrapper {
         &self.ptr
     }
 }
+/// a decoded frame waiting in `picq`, along with what the decoder said about it
+pub struct DecodedFrame {
+    pub frame: FrameWrapper,
+    pub info: FrameInfo,
+}
+
+/// a frame the filter graph gave out, waiting in `filtered_queue`
+pub struct FilteredFrame {
+    pub frame: FrameWrapper,
+    /// what the decoder said about the last frame that went in
+    pub info: FrameInfo,
+    /// the graph's pts in video stream time_base, when the frame is due
+    pub pts: i64,
+}
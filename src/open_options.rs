@@ -0,0 +1,179 @@
+use std::ffi::{CStr, CString};
+use std::path::PathBuf;
+
+use rusty_ffmpeg::ffi;
+
+use crate::movie_state::EndBehavior;
+
+/// which video stream of a multi-stream file gets decoded
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum StreamSelection {
+    /// the first video stream we have a decoder for
+    #[default]
+    First,
+    /// the stream at this index in the container
+    Index(usize),
+    /// whatever `av_find_best_stream` picks
+    Best,
+}
+
+/// how the decoder spreads work over its threads, `AVCodecContext.thread_type`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ThreadType {
+    Frame,
+    Slice,
+    FrameAndSlice,
+}
+
+impl ThreadType {
+    fn flags(self) -> i32 {
+        match self {
+            ThreadType::Frame => ffi::FF_THREAD_FRAME as i32,
+            ThreadType::Slice => ffi::FF_THREAD_SLICE as i32,
+            ThreadType::FrameAndSlice => (ffi::FF_THREAD_FRAME | ffi::FF_THREAD_SLICE) as i32,
+        }
+    }
+}
+
+/// how the video decoder is picked and set up. kept on the movie so a
+/// stream switch opens the new decoder the same way.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct DecoderOptions {
+    /// a decoder by name, "libdav1d" instead of "av1". when None ffmpeg picks one
+    pub name: Option<String>,
+    /// passed to `avcodec_open2`, e.g. ("flags2", "+export_mvs") or ("skip_loop_filter", "all")
+    pub options: Vec<(String, String)>,
+    /// `AVCodecContext.thread_count`, 0 lets ffmpeg decide
+    pub threads: Option<i32>,
+    pub thread_type: Option<ThreadType>,
+}
+
+impl DecoderOptions {
+    /// set the thread fields on a codec context before it is opened
+    pub(crate) unsafe fn apply_threading(&self, codec_ctx: *mut ffi::AVCodecContext) {
+        if let Some(threads) = self.threads {
+            (*codec_ctx).thread_count = threads;
+        }
+        if let Some(thread_type) = self.thread_type {
+            (*codec_ctx).thread_type = thread_type.flags();
+        }
+    }
+}
+
+/// settings for `open_movie_with_options`
+#[derive(Debug, Clone, Default)]
+pub struct OpenOptions {
+    pub video_stream: StreamSelection,
+    pub end_behavior: EndBehavior,
+    /// subtitle stream to decode, subtitles are off when None
+    pub subtitle_stream: Option<StreamSelection>,
+    /// a .srt file to show over the movie
+    pub external_subtitles: Option<PathBuf>,
+    /// passed to `avformat_open_input`, e.g. ("probesize", "32") or ("rtsp_transport", "tcp")
+    pub format_options: Vec<(String, String)>,
+    pub decoder: DecoderOptions,
+}
+
+impl OpenOptions {
+    pub fn new() -> OpenOptions {
+        OpenOptions::default()
+    }
+
+    pub fn video_stream(mut self, selection: StreamSelection) -> OpenOptions {
+        self.video_stream = selection;
+        self
+    }
+
+    pub fn end_behavior(mut self, behavior: EndBehavior) -> OpenOptions {
+        self.end_behavior = behavior;
+        self
+    }
+
+    pub fn subtitle_stream(mut self, selection: StreamSelection) -> OpenOptions {
+        self.subtitle_stream = Some(selection);
+        self
+    }
+
+    pub fn external_subtitles(mut self, path: impl Into<PathBuf>) -> OpenOptions {
+        self.external_subtitles = Some(path.into());
+        self
+    }
+
+    pub fn format_option(mut self, key: &str, value: &str) -> OpenOptions {
+        self.format_options.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn decoder_name(mut self, name: &str) -> OpenOptions {
+        self.decoder.name = Some(name.to_string());
+        self
+    }
+
+    pub fn decoder_option(mut self, key: &str, value: &str) -> OpenOptions {
+        self.decoder.options.push((key.to_string(), value.to_string()));
+        self
+    }
+
+    pub fn threads(mut self, count: i32) -> OpenOptions {
+        self.decoder.threads = Some(count);
+        self
+    }
+
+    pub fn thread_type(mut self, thread_type: ThreadType) -> OpenOptions {
+        self.decoder.thread_type = Some(thread_type);
+        self
+    }
+}
+
+/// what `open_movie_with_options` ended up doing with the options
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct OpenReport {
+    pub video_stream: usize,
+    /// name of the decoder that was opened, "h264", "libdav1d"
+    pub decoder: String,
+    /// demuxer options `avformat_open_input` did not recognise
+    pub unused_format_options: Vec<String>,
+    /// decoder options `avcodec_open2` did not recognise
+    pub unused_decoder_options: Vec<String>,
+}
+
+/// an `AVDictionary` built from key value pairs, freed on drop.
+/// ffmpeg removes the entries it consumes, what is left over was not understood.
+pub(crate) struct Dictionary {
+    pub ptr: *mut ffi::AVDictionary,
+}
+
+impl Dictionary {
+    pub fn from_pairs(pairs: &[(String, String)]) -> Dictionary {
+        let mut dict = Dictionary { ptr: std::ptr::null_mut() };
+        for (key, value) in pairs {
+            // ffmpeg can't take a NUL byte, such a pair is dropped
+            if let (Ok(key), Ok(value)) = (CString::new(key.as_str()), CString::new(value.as_str())) {
+                unsafe { ffi::av_dict_set(&mut dict.ptr, key.as_ptr(), value.as_ptr(), 0) };
+            }
+        }
+        dict
+    }
+
+    pub fn keys(&self) -> Vec<String> {
+        let mut keys = vec![];
+        let empty = CString::new("").unwrap();
+        let mut entry: *const ffi::AVDictionaryEntry = std::ptr::null();
+        unsafe {
+            loop {
+                entry = ffi::av_dict_get(self.ptr, empty.as_ptr(), entry, ffi::AV_DICT_IGNORE_SUFFIX as i32);
+                match entry.as_ref() {
+                    Some(e) => keys.push(CStr::from_ptr(e.key).to_string_lossy().into_owned()),
+                    None => break,
+                }
+            }
+        }
+        keys
+    }
+}
+
+impl Drop for Dictionary {
+    fn drop(&mut self) {
+        unsafe { ffi::av_dict_free(&mut self.ptr) };
+    }
+}
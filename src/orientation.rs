@@ -0,0 +1,159 @@
+//! which way up a movie is meant to be shown. phones record with the sensor
+//! sideways and note the turn in a display matrix on the video stream, older
+//! files carry a `rotate` tag instead. the filter graph puts the picture
+//! upright with transpose, hflip and vflip, the way ffplay does.
+use std::ffi::{CStr, CString};
+
+use rusty_ffmpeg::ffi;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub struct Orientation {
+    /// degrees clockwise the decoded picture is turned for display, 0, 90, 180 or 270
+    pub rotation: i32,
+    /// mirrored left to right after the turn
+    pub flip: bool,
+}
+
+impl Orientation {
+    /// `rotation` in any number of degrees clockwise, snapped to the nearest right angle
+    pub fn new(rotation: f64, flip: bool) -> Orientation {
+        let quarters = if rotation.is_finite() { (rotation / 90.).round() as i64 } else { 0 };
+        Orientation { rotation: (quarters.rem_euclid(4) * 90) as i32, flip }
+    }
+
+    pub fn is_upright(&self) -> bool {
+        *self == Orientation::default()
+    }
+
+    /// turned a further `degrees` clockwise
+    pub fn rotated(self, degrees: i32) -> Orientation {
+        Orientation::new((self.rotation + degrees) as f64, self.flip)
+    }
+
+    /// the 3x3 display matrix of `AV_PKT_DATA_DISPLAYMATRIX`. turns that are
+    /// not a right angle are snapped to the nearest one.
+    pub fn from_display_matrix(matrix: &[i32; 9]) -> Orientation {
+        let rotation = unsafe { ffi::av_display_rotation_get(matrix.as_ptr()) };
+        if rotation.is_nan() {
+            return Orientation::default();
+        }
+        // clockwise in [0, 360), as ffplay's get_rotation
+        let mut theta = -rotation.round();
+        theta -= 360. * (theta / 360. + 0.9 / 360.).floor();
+        let near = |angle: f64| (theta - angle).abs() < 1.;
+        if near(90.) {
+            Orientation { rotation: 90, flip: matrix[3] > 0 }
+        } else if near(180.) {
+            match (matrix[0] < 0, matrix[4] < 0) {
+                (true, true) => Orientation { rotation: 180, flip: false },
+                (true, false) => Orientation { rotation: 0, flip: true },
+                (false, true) => Orientation { rotation: 180, flip: true },
+                (false, false) => Orientation::default(),
+            }
+        } else if near(270.) {
+            Orientation { rotation: 270, flip: matrix[3] < 0 }
+        } else if theta.abs() > 1. && (theta - 360.).abs() > 1. {
+            Orientation::new(theta, false)
+        } else if matrix[4] < 0 {
+            // upside down and mirrored is a vertical flip
+            Orientation { rotation: 180, flip: true }
+        } else {
+            Orientation::default()
+        }
+    }
+
+    /// the legacy `rotate` metadata tag, degrees clockwise
+    pub fn from_rotate_tag(value: &str) -> Option<Orientation> {
+        value.trim().parse::<f64>().ok().map(|rotation| Orientation::new(rotation, false))
+    }
+
+    /// what a video stream asks for, from its display matrix or else its
+    /// `rotate` tag, upright when it has neither
+    pub unsafe fn of_stream(stream: *const ffi::AVStream) -> Orientation {
+        let stream = match stream.as_ref() {
+            Some(stream) => stream,
+            None => return Orientation::default(),
+        };
+        let mut size = 0;
+        let matrix = ffi::av_stream_get_side_data(
+            stream,
+            ffi::AVPacketSideDataType_AV_PKT_DATA_DISPLAYMATRIX,
+            &mut size,
+        );
+        if !matrix.is_null() && size >= std::mem::size_of::<[i32; 9]>() as _ {
+            return Orientation::from_display_matrix(&*(matrix as *const [i32; 9]));
+        }
+        let key = CString::new("rotate").unwrap();
+        let tag = ffi::av_dict_get(stream.metadata, key.as_ptr(), std::ptr::null(), 0);
+        if !tag.is_null() {
+            if let Some(orientation) = CStr::from_ptr((*tag).value).to_str().ok().and_then(Orientation::from_rotate_tag) {
+                return orientation;
+            }
+        }
+        Orientation::default()
+    }
+
+    /// the filters that show the decoded picture this way up, empty when upright
+    pub fn filters(&self) -> &'static str {
+        match (self.rotation, self.flip) {
+            (90, false) => "transpose=clock",
+            (90, true) => "transpose=cclock_flip",
+            (180, false) => "hflip,vflip",
+            (180, true) => "vflip",
+            (270, false) => "transpose=cclock",
+            (270, true) => "transpose=clock_flip",
+            (_, true) => "hflip",
+            (_, false) => "",
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// the display matrix for a picture shown `clockwise` degrees turned,
+    /// 90 is what phones write for portrait video
+    fn turned(clockwise: f64) -> [i32; 9] {
+        let (sin, cos) = clockwise.to_radians().sin_cos();
+        let fixed = |v: f64| (v * 65536.).round() as i32;
+        [fixed(cos), fixed(sin), 0, fixed(-sin), fixed(cos), 0, 0, 0, 1 << 30]
+    }
+
+    fn flipped(mut matrix: [i32; 9], hflip: bool, vflip: bool) -> [i32; 9] {
+        unsafe { ffi::av_display_matrix_flip(matrix.as_mut_ptr(), hflip as i32, vflip as i32) };
+        matrix
+    }
+
+    #[test]
+    fn test_display_matrix_rotation() {
+        assert_eq!(turned(90.), [0, 65536, 0, -65536, 0, 0, 0, 0, 1 << 30]);
+        assert_eq!(Orientation::from_display_matrix(&turned(0.)), Orientation::default());
+        assert_eq!(Orientation::from_display_matrix(&turned(90.)), Orientation { rotation: 90, flip: false });
+        assert_eq!(Orientation::from_display_matrix(&turned(180.)), Orientation { rotation: 180, flip: false });
+        assert_eq!(Orientation::from_display_matrix(&turned(-90.)), Orientation { rotation: 270, flip: false });
+        // snapped to a right angle
+        assert_eq!(Orientation::from_display_matrix(&turned(80.)), Orientation { rotation: 90, flip: false });
+        assert!(Orientation::from_display_matrix(&[0; 9]).is_upright());
+    }
+
+    #[test]
+    fn test_display_matrix_flips() {
+        let upright = turned(0.);
+        assert_eq!(Orientation::from_display_matrix(&flipped(upright, true, false)), Orientation { rotation: 0, flip: true });
+        assert_eq!(Orientation::from_display_matrix(&flipped(upright, false, true)), Orientation { rotation: 180, flip: true });
+        assert_eq!(Orientation::from_display_matrix(&flipped(upright, true, true)), Orientation { rotation: 180, flip: false });
+        assert_eq!(Orientation::from_display_matrix(&flipped(turned(90.), true, false)), Orientation { rotation: 90, flip: true });
+    }
+
+    #[test]
+    fn test_rotate_tag_and_filters() {
+        assert_eq!(Orientation::from_rotate_tag("90"), Some(Orientation { rotation: 90, flip: false }));
+        assert_eq!(Orientation::from_rotate_tag("-90"), Some(Orientation { rotation: 270, flip: false }));
+        assert_eq!(Orientation::from_rotate_tag("sideways"), None);
+        assert_eq!(Orientation::default().filters(), "");
+        assert_eq!(Orientation { rotation: 90, flip: false }.filters(), "transpose=clock");
+        assert_eq!(Orientation::default().rotated(90).rotated(270), Orientation::default());
+        assert_eq!(Orientation { rotation: 270, flip: true }.rotated(180).filters(), "transpose=cclock_flip");
+    }
+}
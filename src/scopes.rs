@@ -0,0 +1,353 @@
+//! signal scopes of a decoded frame: luma waveform, RGB parade, vectorscope
+//! and per-plane histograms. everything is a plain count array so it can be
+//! checked headless or drawn by a frontend.
+use rusty_ffmpeg::ffi;
+
+use crate::error::AvError;
+use crate::metrics::{yuv420_planes, Plane, Yuv420Frame};
+
+/// 8 bit code values, also the vectorscope's resolution whatever the depth
+pub const LEVELS: usize = 256;
+
+/// how many samples of a frame column landed on each level
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Waveform {
+    pub columns: usize,
+    /// code values at the frame's depth, 1024 for 10 bit
+    pub levels: usize,
+    /// `levels` rows of `columns` counts, row 0 is level 0
+    pub counts: Vec<u32>,
+}
+
+impl Waveform {
+    pub fn new(columns: usize, levels: usize) -> Waveform {
+        Waveform { columns, levels, counts: vec![0; columns * levels] }
+    }
+
+    pub fn get(&self, column: usize, level: usize) -> u32 {
+        self.counts[level * self.columns + column]
+    }
+
+    pub fn max(&self) -> u32 {
+        self.counts.iter().copied().max().unwrap_or(0)
+    }
+
+    fn add(&mut self, column: usize, level: usize) {
+        self.counts[level * self.columns + column] += 1;
+    }
+}
+
+/// chroma samples on a U/V plane, 128/128 is neutral. deeper samples are
+/// binned to 8 bits.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Vectorscope {
+    /// `LEVELS` rows of V, each `LEVELS` counts of U
+    pub counts: Vec<u32>,
+}
+
+impl Vectorscope {
+    pub fn get(&self, u: u8, v: u8) -> u32 {
+        self.counts[v as usize * LEVELS + u as usize]
+    }
+
+    pub fn max(&self) -> u32 {
+        self.counts.iter().copied().max().unwrap_or(0)
+    }
+}
+
+impl Default for Vectorscope {
+    fn default() -> Self {
+        Vectorscope { counts: vec![0; LEVELS * LEVELS] }
+    }
+}
+
+/// samples per level of each plane, as many levels as the frame's depth has
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Histogram {
+    pub y: Vec<u32>,
+    pub u: Vec<u32>,
+    pub v: Vec<u32>,
+}
+
+impl Histogram {
+    pub fn new(levels: usize) -> Histogram {
+        Histogram { y: vec![0; levels], u: vec![0; levels], v: vec![0; levels] }
+    }
+
+    pub fn max(&self) -> u32 {
+        self.y.iter().chain(self.u.iter()).chain(self.v.iter()).copied().max().unwrap_or(0)
+    }
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Histogram::new(LEVELS)
+    }
+}
+
+/// YUV to RGB for the parade, BT.601 or BT.709 coefficients in limited or full range
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct YuvToRgb {
+    pub kr: f32,
+    pub kb: f32,
+    pub full_range: bool,
+}
+
+impl YuvToRgb {
+    pub const BT601: YuvToRgb = YuvToRgb { kr: 0.299, kb: 0.114, full_range: false };
+    pub const BT709: YuvToRgb = YuvToRgb { kr: 0.2126, kb: 0.0722, full_range: false };
+
+    /// the matrix a frame says it uses. untagged frames are guessed from
+    /// their height the way players do, HD is BT.709.
+    pub unsafe fn for_frame(frame: *const ffi::AVFrame) -> YuvToRgb {
+        let f = &*frame;
+        let mut conversion = match f.colorspace {
+            ffi::AVColorSpace_AVCOL_SPC_BT709 => YuvToRgb::BT709,
+            ffi::AVColorSpace_AVCOL_SPC_UNSPECIFIED if f.height > 576 => YuvToRgb::BT709,
+            _ => YuvToRgb::BT601,
+        };
+        conversion.full_range = f.color_range == ffi::AVColorRange_AVCOL_RANGE_JPEG;
+        conversion
+    }
+
+    pub fn rgb(&self, y: u8, u: u8, v: u8) -> [u8; 3] {
+        self.rgb_at_depth(y as u16, u as u16, v as u16, 8).map(|c| c as u8)
+    }
+
+    /// `rgb` of `depth` bit code values, the result has the same depth
+    pub fn rgb_at_depth(&self, y: u16, u: u16, v: u16, depth: u32) -> [u16; 3] {
+        let scale = (1u32 << (depth - 8)) as f32;
+        let (y, u, v) = (y as f32 / scale, u as f32 / scale, v as f32 / scale);
+        let (y, cb, cr) = match self.full_range {
+            true => (y, u - 128., v - 128.),
+            false => ((y - 16.) * 255. / 219., (u - 128.) * 255. / 224., (v - 128.) * 255. / 224.),
+        };
+        let kg = 1. - self.kr - self.kb;
+        let r = y + 2. * (1. - self.kr) * cr;
+        let b = y + 2. * (1. - self.kb) * cb;
+        let g = (y - self.kr * r - self.kb * b) / kg;
+        let max = ((1u32 << depth) - 1) as f32;
+        [r, g, b].map(|c| (c * max / 255.).round().clamp(0., max) as u16)
+    }
+}
+
+/// one plane of a frame as it is stored, one byte a sample up to 8 bits and
+/// two little endian bytes above
+#[derive(Debug, Clone, Copy)]
+pub struct SamplePlane<'a> {
+    pub data: &'a [u8],
+    pub width: usize,
+    pub height: usize,
+    /// bytes per row
+    pub stride: usize,
+    pub bytes_per_sample: usize,
+}
+
+impl SamplePlane<'_> {
+    fn get(&self, x: usize, y: usize) -> u16 {
+        let i = y * self.stride + x * self.bytes_per_sample;
+        match self.bytes_per_sample {
+            1 => self.data[i] as u16,
+            _ => u16::from_le_bytes([self.data[i], self.data[i + 1]]),
+        }
+    }
+}
+
+impl<'a> From<&Plane<'a>> for SamplePlane<'a> {
+    fn from(plane: &Plane<'a>) -> SamplePlane<'a> {
+        SamplePlane { data: plane.data, width: plane.width, height: plane.height, stride: plane.stride, bytes_per_sample: 1 }
+    }
+}
+
+/// how a planar YUV frame is laid out, `None` for anything else
+struct Layout {
+    depth: u32,
+    /// log2 of the chroma subsampling, across and down
+    chroma_shift: (u32, u32),
+}
+
+unsafe fn planar_yuv_layout(frame: &ffi::AVFrame) -> Option<Layout> {
+    let desc = ffi::av_pix_fmt_desc_get(frame.format).as_ref()?;
+    let unsupported = (ffi::AV_PIX_FMT_FLAG_BE | ffi::AV_PIX_FMT_FLAG_PAL | ffi::AV_PIX_FMT_FLAG_BITSTREAM
+        | ffi::AV_PIX_FMT_FLAG_HWACCEL | ffi::AV_PIX_FMT_FLAG_RGB | ffi::AV_PIX_FMT_FLAG_FLOAT) as u64;
+    if desc.flags & ffi::AV_PIX_FMT_FLAG_PLANAR as u64 == 0 || desc.flags & unsupported != 0 || desc.nb_components < 3 {
+        return None;
+    }
+    let depth = desc.comp[0].depth as u32;
+    let step = if depth > 8 { 2 } else { 1 };
+    let native = desc.comp[..3].iter().enumerate().all(|(plane, comp)| {
+        comp.plane as usize == plane && comp.depth as u32 == depth && comp.step == step && comp.offset == 0 && comp.shift == 0
+    });
+    if !native || !(8..=16).contains(&depth) || frame.linesize[..3].iter().any(|l| *l <= 0) {
+        return None;
+    }
+    Some(Layout { depth, chroma_shift: (desc.log2_chroma_w as u32, desc.log2_chroma_h as u32) })
+}
+
+/// every scope of one frame
+#[derive(Debug, Clone, PartialEq)]
+pub struct Scopes {
+    pub pts: i64,
+    /// bits per sample of the frame, the waveforms and histogram have a level for each code value
+    pub depth: u32,
+    pub luma: Waveform,
+    /// red, green and blue waveforms
+    pub parade: [Waveform; 3],
+    pub vectorscope: Vectorscope,
+    pub histogram: Histogram,
+}
+
+impl Scopes {
+    /// scopes of 8 bit 4:2:0 planes, the waveforms are `columns` wide whatever the picture width
+    pub fn from_planes(planes: &[Plane; 3], columns: usize, conversion: YuvToRgb) -> Scopes {
+        let planes = [SamplePlane::from(&planes[0]), SamplePlane::from(&planes[1]), SamplePlane::from(&planes[2])];
+        Scopes::from_samples(&planes, 8, (1, 1), columns, conversion)
+    }
+
+    /// scopes of `depth` bit planes, chroma subsampled by `chroma_shift` as in `AVPixFmtDescriptor`
+    pub fn from_samples(
+        planes: &[SamplePlane; 3],
+        depth: u32,
+        chroma_shift: (u32, u32),
+        columns: usize,
+        conversion: YuvToRgb,
+    ) -> Scopes {
+        let [y_plane, u_plane, v_plane] = planes;
+        let levels = 1 << depth;
+        let columns = columns.clamp(1, y_plane.width.max(1));
+        let mut luma = Waveform::new(columns, levels);
+        let mut parade = [Waveform::new(columns, levels), Waveform::new(columns, levels), Waveform::new(columns, levels)];
+        let mut vectorscope = Vectorscope::default();
+        let mut histogram = Histogram::new(levels);
+
+        for y in 0..y_plane.height {
+            let chroma_y = (y >> chroma_shift.1).min(u_plane.height.saturating_sub(1));
+            for x in 0..y_plane.width {
+                let level = y_plane.get(x, y);
+                let column = x * columns / y_plane.width;
+                let chroma_x = (x >> chroma_shift.0).min(u_plane.width - 1);
+                luma.add(column, level as usize);
+                histogram.y[level as usize] += 1;
+                let rgb = conversion.rgb_at_depth(level, u_plane.get(chroma_x, chroma_y), v_plane.get(chroma_x, chroma_y), depth);
+                for (waveform, c) in parade.iter_mut().zip(rgb) {
+                    waveform.add(column, c as usize);
+                }
+            }
+        }
+        // chroma is counted once per sample, not once per luma pixel
+        for y in 0..u_plane.height {
+            for x in 0..u_plane.width {
+                let (u, v) = (u_plane.get(x, y), v_plane.get(x, y));
+                let (u_bin, v_bin) = ((u >> (depth - 8)) as usize, (v >> (depth - 8)) as usize);
+                vectorscope.counts[v_bin * LEVELS + u_bin] += 1;
+                histogram.u[u as usize] += 1;
+                histogram.v[v as usize] += 1;
+            }
+        }
+        Scopes { pts: ffi::AV_NOPTS_VALUE, depth, luma, parade, vectorscope, histogram }
+    }
+}
+
+/// scopes of a decoded frame. planar YUV is read as it is, at its own depth and
+/// chroma subsampling, anything else is converted to 8 bit 4:2:0 first.
+pub unsafe fn compute_scopes(frame: *const ffi::AVFrame, columns: usize) -> Result<Scopes, AvError> {
+    if frame.is_null() || (*frame).width <= 0 || (*frame).height <= 0 {
+        return Err(AvError::from_code(ffi::AVERROR(ffi::EINVAL)));
+    }
+    let f = &*frame;
+    let mut scopes = match planar_yuv_layout(f) {
+        Some(layout) => {
+            let (shift_w, shift_h) = layout.chroma_shift;
+            let bytes_per_sample = if layout.depth > 8 { 2 } else { 1 };
+            let plane = |i: usize| {
+                // chroma sizes round up like AV_CEIL_RSHIFT
+                let (shift_w, shift_h) = if i == 0 { (0, 0) } else { (shift_w, shift_h) };
+                let width = (f.width as usize + (1 << shift_w) - 1) >> shift_w;
+                let height = (f.height as usize + (1 << shift_h) - 1) >> shift_h;
+                let stride = f.linesize[i] as usize;
+                let data = std::slice::from_raw_parts(f.data[i], stride * (height - 1) + width * bytes_per_sample);
+                SamplePlane { data, width, height, stride, bytes_per_sample }
+            };
+            Scopes::from_samples(&[plane(0), plane(1), plane(2)], layout.depth, layout.chroma_shift, columns, YuvToRgb::for_frame(frame))
+        }
+        None => {
+            let yuv = Yuv420Frame::new(frame, f.width, f.height)?;
+            Scopes::from_planes(&yuv420_planes(&*yuv.ptr), columns, YuvToRgb::for_frame(frame))
+        }
+    };
+    scopes.pts = f.pts;
+    Ok(scopes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn planes<'a>(y: &'a [u8], u: &'a [u8], v: &'a [u8], width: usize) -> [Plane<'a>; 3] {
+        let chroma = (width + 1) / 2;
+        [
+            Plane { data: y, width, height: y.len() / width, stride: width },
+            Plane { data: u, width: chroma, height: u.len() / chroma, stride: chroma },
+            Plane { data: v, width: chroma, height: v.len() / chroma, stride: chroma },
+        ]
+    }
+
+    #[test]
+    fn test_flat_grey_frame() {
+        let (y, c) = (vec![126u8; 16 * 8], vec![128u8; 8 * 4]);
+        let scopes = Scopes::from_planes(&planes(&y, &c, &c, 16), 4, YuvToRgb::BT709);
+        assert_eq!(scopes.histogram.y[126], 128);
+        assert_eq!(scopes.histogram.u[128], 32);
+        assert_eq!(scopes.vectorscope.get(128, 128), 32);
+        assert_eq!(scopes.vectorscope.max(), 32);
+        // four columns of four pixels, eight rows
+        assert!((0..4).all(|column| scopes.luma.get(column, 126) == 32));
+        assert_eq!(scopes.luma.max(), 32);
+        // grey is grey in every channel
+        let grey = YuvToRgb::BT709.rgb(126, 128, 128)[0];
+        assert!(scopes.parade.iter().all(|w| w.get(0, grey as usize) == 32));
+    }
+
+    #[test]
+    fn test_waveform_follows_a_ramp() {
+        let y: Vec<u8> = (0..4).flat_map(|_| (0..=255u8).step_by(16)).collect();
+        let c = vec![128u8; 8 * 2];
+        let scopes = Scopes::from_planes(&planes(&y, &c, &c, 16), 16, YuvToRgb::BT601);
+        for column in 0..16 {
+            assert_eq!(scopes.luma.get(column, column * 16), 4);
+        }
+    }
+
+    #[test]
+    fn test_ten_bit_422_is_read_as_it_is() {
+        // 4x2 luma at two neighbouring 10 bit levels, 2x2 chroma slightly off neutral
+        let samples = |values: &[u16]| values.iter().flat_map(|v| v.to_le_bytes()).collect::<Vec<u8>>();
+        let y = samples(&[401, 402, 401, 402, 401, 402, 401, 402]);
+        let (u, v) = (samples(&[513, 513, 513, 513]), samples(&[510, 510, 510, 510]));
+        let planes = [
+            SamplePlane { data: &y, width: 4, height: 2, stride: 8, bytes_per_sample: 2 },
+            SamplePlane { data: &u, width: 2, height: 2, stride: 4, bytes_per_sample: 2 },
+            SamplePlane { data: &v, width: 2, height: 2, stride: 4, bytes_per_sample: 2 },
+        ];
+        let scopes = Scopes::from_samples(&planes, 10, (1, 0), 4, YuvToRgb::BT709);
+        assert_eq!(scopes.luma.levels, 1024);
+        assert_eq!((scopes.histogram.y[401], scopes.histogram.y[402]), (4, 4));
+        // one chroma sample per two luma samples on every row
+        assert_eq!((scopes.histogram.u[513], scopes.histogram.v[510]), (4, 4));
+        assert_eq!(scopes.vectorscope.get(128, 127), 4);
+        assert_eq!(YuvToRgb::BT709.rgb_at_depth(940, 512, 512, 10), [1023, 1023, 1023]);
+    }
+
+    #[test]
+    fn test_limited_range_extremes() {
+        for conversion in [YuvToRgb::BT601, YuvToRgb::BT709] {
+            assert_eq!(conversion.rgb(235, 128, 128), [255, 255, 255]);
+            assert_eq!(conversion.rgb(16, 128, 128), [0, 0, 0]);
+        }
+        let full = YuvToRgb { full_range: true, ..YuvToRgb::BT709 };
+        assert_eq!(full.rgb(255, 128, 128), [255, 255, 255]);
+        // pure red in BT.709 limited range
+        let [r, g, b] = YuvToRgb::BT709.rgb(63, 102, 240);
+        assert!(r > 250 && g < 5 && b < 5);
+    }
+}
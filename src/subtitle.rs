@@ -0,0 +1,312 @@
+use std::{ffi::CStr, sync::Mutex};
+
+use log::{debug, error};
+use rusty_ffmpeg::ffi;
+
+use crate::error::{AvError, MovieError};
+
+/// a bitmap subtitle rect converted from the palettized image to RGBA bytes
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubtitleBitmap {
+    pub x: i32,
+    pub y: i32,
+    pub width: i32,
+    pub height: i32,
+    pub rgba: Vec<u8>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum SubtitleContent {
+    Text(String),
+    /// rect positions are relative to a `canvas` sized picture, usually the video size
+    Bitmap { rects: Vec<SubtitleBitmap>, canvas: (i32, i32) },
+}
+
+impl SubtitleContent {
+    pub fn is_empty(&self) -> bool {
+        match self {
+            SubtitleContent::Text(text) => text.trim().is_empty(),
+            SubtitleContent::Bitmap { rects, .. } => rects.is_empty(),
+        }
+    }
+}
+
+/// a subtitle with its display window in seconds from the start of the movie
+#[derive(Debug, Clone, PartialEq)]
+pub struct Subtitle {
+    pub start: f64,
+    /// `f64::INFINITY` until the next subtitle replaces it (PGS, DVB)
+    pub end: f64,
+    pub content: SubtitleContent,
+}
+
+impl Subtitle {
+    pub fn is_visible_at(&self, position: f64) -> bool {
+        self.start <= position && position < self.end
+    }
+}
+
+/// one subtitle stream of the container, as listed by `MovieState::subtitle_streams`
+#[derive(Debug, Clone, PartialEq)]
+pub struct SubtitleStreamInfo {
+    pub index: usize,
+    pub codec_name: String,
+    /// the `language` metadata tag
+    pub language: Option<String>,
+    /// PGS, DVB and other picture based subtitles
+    pub bitmap: bool,
+    pub active: bool,
+}
+
+/// decoded subtitles waiting for their display window
+pub struct SubtitleQueue {
+    items: Mutex<Vec<Subtitle>>,
+}
+
+impl SubtitleQueue {
+    pub fn new() -> SubtitleQueue {
+        SubtitleQueue { items: Mutex::new(vec![]) }
+    }
+
+    /// add a subtitle. open ended subtitles that started earlier end where this one starts,
+    /// an empty subtitle only does that.
+    pub fn push(&self, subtitle: Subtitle) {
+        let mut items = self.items.lock().unwrap();
+        items.iter_mut()
+            .filter(|s| s.end == f64::INFINITY && s.start <= subtitle.start)
+            .for_each(|s| s.end = subtitle.start);
+        if !subtitle.content.is_empty() {
+            items.push(subtitle);
+        }
+    }
+
+    /// everything visible at `position`, forgetting what has already ended
+    pub fn visible_at(&self, position: f64) -> Vec<Subtitle> {
+        let mut items = self.items.lock().unwrap();
+        items.retain(|s| s.end > position);
+        items.iter().filter(|s| s.is_visible_at(position)).cloned().collect()
+    }
+
+    pub fn len(&self) -> usize {
+        self.items.lock().unwrap().len()
+    }
+
+    pub fn clear(&self) {
+        self.items.lock().unwrap().clear();
+    }
+}
+
+/// parse the contents of a SubRip file. malformed cues are skipped.
+pub fn parse_srt(contents: &str) -> Vec<Subtitle> {
+    let contents = contents.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+    contents
+        .split("\n\n")
+        .filter_map(|cue| {
+            let mut lines = cue.lines().skip_while(|l| !l.contains("-->"));
+            let (start, end) = lines.next()?.split_once("-->")?;
+            let start = parse_srt_time(start)?;
+            // positions like "X1:40" may follow the end time
+            let end = parse_srt_time(end.split_whitespace().next()?)?;
+            let text = lines.map(strip_tags).collect::<Vec<_>>().join("\n");
+            if text.trim().is_empty() {
+                return None;
+            }
+            Some(Subtitle { start, end, content: SubtitleContent::Text(text) })
+        })
+        .collect()
+}
+
+/// "01:02:03,456" to seconds
+fn parse_srt_time(time: &str) -> Option<f64> {
+    let (hms, millis) = time.trim().split_once([',', '.'])?;
+    let mut parts = hms.split(':').map(|p| p.parse::<f64>());
+    let (h, m, s) = (parts.next()?.ok()?, parts.next()?.ok()?, parts.next()?.ok()?);
+    let millis = millis.parse::<f64>().ok()?;
+    Some(h * 3600. + m * 60. + s + millis / 1000.)
+}
+
+/// drop html style tags, `<i>` and `<font color=..>` mostly
+fn strip_tags(line: &str) -> String {
+    let mut out = String::with_capacity(line.len());
+    let mut in_tag = false;
+    for c in line.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out
+}
+
+/// the plain text of a decoded ASS event line:
+/// "ReadOrder,Layer,Style,Name,MarginL,MarginR,MarginV,Effect,Text"
+pub fn ass_to_text(ass: &str) -> String {
+    let text = ass.splitn(9, ',').nth(8).unwrap_or(ass);
+    let mut out = String::with_capacity(text.len());
+    let mut in_override = false;
+    let mut chars = text.chars().peekable();
+    while let Some(c) = chars.next() {
+        match c {
+            '{' => in_override = true,
+            '}' if in_override => in_override = false,
+            _ if in_override => {}
+            '\\' => match chars.peek() {
+                Some('N') | Some('n') => {
+                    chars.next();
+                    out.push('\n');
+                }
+                Some('h') => {
+                    chars.next();
+                    out.push(' ');
+                }
+                _ => out.push(c),
+            },
+            _ => out.push(c),
+        }
+    }
+    out.trim().to_string()
+}
+
+/// allocate and open a decoder for a subtitle stream
+pub(crate) unsafe fn open_subtitle_codec(stream: *mut ffi::AVStream) -> Result<*mut ffi::AVCodecContext, MovieError> {
+    let index = (*stream).index as usize;
+    let params = (*stream).codecpar.as_ref().ok_or(MovieError::InvalidSubtitleStream(index))?;
+    if params.codec_type != ffi::AVMediaType_AVMEDIA_TYPE_SUBTITLE {
+        return Err(MovieError::InvalidSubtitleStream(index));
+    }
+    let codec = ffi::avcodec_find_decoder(params.codec_id);
+    if codec.is_null() {
+        return Err(MovieError::InvalidSubtitleStream(index));
+    }
+    let mut subtitle_ctx = ffi::avcodec_alloc_context3(codec);
+    let ret = ffi::avcodec_parameters_to_context(subtitle_ctx, params);
+    if ret < 0 {
+        ffi::avcodec_free_context(&mut subtitle_ctx);
+        return Err(MovieError::CodecParameters(AvError::from_code(ret)));
+    }
+    (*subtitle_ctx).pkt_timebase = (*stream).time_base;
+    let ret = ffi::avcodec_open2(subtitle_ctx, codec, std::ptr::null_mut());
+    if ret < 0 {
+        ffi::avcodec_free_context(&mut subtitle_ctx);
+        return Err(MovieError::CodecOpen(AvError::from_code(ret)));
+    }
+    Ok(subtitle_ctx)
+}
+
+/// decode one subtitle packet. `packet_time` is the packet pts in seconds from
+/// the start of the movie, display times in the subtitle are relative to it.
+pub(crate) unsafe fn decode_subtitle(
+    subtitle_ctx: *mut ffi::AVCodecContext,
+    packet: *mut ffi::AVPacket,
+    packet_time: f64,
+    packet_duration: f64,
+) -> Option<Subtitle> {
+    let mut sub: ffi::AVSubtitle = std::mem::zeroed();
+    let mut got_subtitle = 0;
+    let ret = ffi::avcodec_decode_subtitle2(subtitle_ctx, &mut sub, &mut got_subtitle, packet);
+    if ret < 0 {
+        error!("Error while decoding a subtitle. {:?}", ffi::av_err2str(ret));
+        return None;
+    }
+    if got_subtitle == 0 {
+        return None;
+    }
+
+    let start = packet_time + sub.start_display_time as f64 / 1000.;
+    let end = if sub.end_display_time > sub.start_display_time && sub.end_display_time != u32::MAX {
+        packet_time + sub.end_display_time as f64 / 1000.
+    } else if packet_duration > 0. {
+        start + packet_duration
+    } else {
+        f64::INFINITY
+    };
+
+    let rects: &[*mut ffi::AVSubtitleRect] = match sub.rects.is_null() {
+        true => &[],
+        false => std::slice::from_raw_parts(sub.rects, sub.num_rects as usize),
+    };
+    let mut text: Vec<String> = vec![];
+    let mut bitmaps: Vec<SubtitleBitmap> = vec![];
+    for rect in rects.iter().filter_map(|r| r.as_ref()) {
+        match rect.type_ {
+            ffi::AVSubtitleType_SUBTITLE_BITMAP => bitmaps.push(bitmap_to_rgba(rect)),
+            ffi::AVSubtitleType_SUBTITLE_ASS if !rect.ass.is_null() => {
+                text.push(ass_to_text(&CStr::from_ptr(rect.ass).to_string_lossy()));
+            }
+            ffi::AVSubtitleType_SUBTITLE_TEXT if !rect.text.is_null() => {
+                text.push(CStr::from_ptr(rect.text).to_string_lossy().into_owned());
+            }
+            _ => {}
+        }
+    }
+    ffi::avsubtitle_free(&mut sub);
+
+    let content = match bitmaps.is_empty() {
+        true => SubtitleContent::Text(text.join("\n")),
+        false => SubtitleContent::Bitmap {
+            rects: bitmaps,
+            canvas: ((*subtitle_ctx).width, (*subtitle_ctx).height),
+        },
+    };
+    debug!("subtitle {:.3} - {:.3}", start, end);
+    Some(Subtitle { start, end, content })
+}
+
+/// expand the 8 bit palette indices of a bitmap rect to RGBA bytes
+unsafe fn bitmap_to_rgba(rect: &ffi::AVSubtitleRect) -> SubtitleBitmap {
+    let (width, height) = (rect.w.max(0) as usize, rect.h.max(0) as usize);
+    let mut rgba = Vec::with_capacity(width * height * 4);
+    if !rect.data[0].is_null() && !rect.data[1].is_null() {
+        // the palette is 256 native endian 0xAARRGGBB values
+        let palette = std::slice::from_raw_parts(rect.data[1] as *const u32, 256);
+        for y in 0..height {
+            let row = std::slice::from_raw_parts(rect.data[0].add(y * rect.linesize[0] as usize), width);
+            for index in row {
+                let argb = palette[*index as usize];
+                rgba.extend_from_slice(&[(argb >> 16) as u8, (argb >> 8) as u8, argb as u8, (argb >> 24) as u8]);
+            }
+        }
+    }
+    SubtitleBitmap { x: rect.x, y: rect.y, width: rect.w, height: rect.h, rgba }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_srt() {
+        let srt = "\u{feff}1\r\n00:00:01,000 --> 00:00:02,500\r\n<i>Hello</i>\r\nworld\r\n\r\n\
+                   2\r\n00:01:00.250 --> 00:01:01,000 X1:10 X2:20\r\nSecond\r\n\r\n\
+                   3\r\nnot a cue\r\n";
+        let subtitles = parse_srt(srt);
+        assert_eq!(subtitles.len(), 2);
+        assert_eq!(subtitles[0].start, 1.);
+        assert_eq!(subtitles[0].end, 2.5);
+        assert_eq!(subtitles[0].content, SubtitleContent::Text("Hello\nworld".to_string()));
+        assert_eq!(subtitles[1].start, 60.25);
+        assert_eq!(subtitles[1].end, 61.);
+    }
+
+    #[test]
+    fn test_ass_to_text() {
+        assert_eq!(ass_to_text("0,0,Default,,0,0,0,,{\\i1}Hello{\\i0}\\Nworld"), "Hello\nworld");
+        assert_eq!(ass_to_text("3,0,Default,,0,0,0,,a, b\\hc"), "a, b c");
+    }
+
+    #[test]
+    fn test_open_ended_subtitles_end_at_the_next_one() {
+        let queue = SubtitleQueue::new();
+        let text = |t: &str| SubtitleContent::Text(t.to_string());
+        queue.push(Subtitle { start: 1., end: f64::INFINITY, content: text("first") });
+        assert_eq!(queue.visible_at(5.).len(), 1);
+
+        // an empty subtitle only clears the screen
+        queue.push(Subtitle { start: 6., end: f64::INFINITY, content: text("") });
+        assert_eq!(queue.visible_at(5.).len(), 1);
+        assert_eq!(queue.visible_at(6.).len(), 0);
+        assert_eq!(queue.len(), 0);
+    }
+}
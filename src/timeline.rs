@@ -0,0 +1,244 @@
+//! what the demuxer read, packet by packet. the packet thread notes every
+//! packet it reads and the decode thread adds the picture type once a frame
+//! comes out of it, so frame sizes can be graphed by I, P and B. a packet read
+//! again after a seek or a loop replaces the earlier note of itself.
+use std::collections::BTreeMap;
+
+use rusty_ffmpeg::ffi;
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct PacketRecord {
+    pub stream_index: usize,
+    /// bytes
+    pub size: usize,
+    /// pts, dts and duration are in the stream's time_base, pts and dts are
+    /// `AV_NOPTS_VALUE` when unknown
+    pub pts: i64,
+    pub dts: i64,
+    pub duration: i64,
+    pub keyframe: bool,
+    /// seconds from the start of the stream, from the dts or the pts when
+    /// there is no dts. lines up with `MovieState::position` for video.
+    pub time: f64,
+    /// 'I', 'P', 'B' and so on once a video frame was decoded from the packet
+    pub pict_type: Option<char>,
+}
+
+/// bits per second over the window ending at `time`
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct BitrateSample {
+    pub time: f64,
+    pub bits_per_second: f64,
+}
+
+struct StreamTimeline {
+    time_base: ffi::AVRational,
+    start_time: i64,
+    /// by dts, or pts when there is no dts
+    packets: BTreeMap<i64, PacketRecord>,
+}
+
+impl StreamTimeline {
+    fn seconds(&self, ts: i64) -> f64 {
+        (ts - self.start_time) as f64 * self.time_base.num as f64 / self.time_base.den.max(1) as f64
+    }
+
+    fn ticks(&self, seconds: f64) -> f64 {
+        self.start_time as f64 + seconds * self.time_base.den as f64 / self.time_base.num.max(1) as f64
+    }
+}
+
+#[derive(Default)]
+pub struct PacketTimeline {
+    streams: BTreeMap<usize, StreamTimeline>,
+}
+
+impl PacketTimeline {
+    pub fn new() -> PacketTimeline {
+        PacketTimeline::default()
+    }
+
+    /// the time_base and start of a stream, needed before its packets are recorded.
+    /// a start of `AV_NOPTS_VALUE` is taken as 0.
+    pub fn add_stream(&mut self, stream_index: usize, time_base: ffi::AVRational, start_time: i64) {
+        let start_time = if start_time == ffi::AV_NOPTS_VALUE { 0 } else { start_time };
+        self.streams
+            .entry(stream_index)
+            .or_insert_with(|| StreamTimeline { time_base, start_time, packets: BTreeMap::new() });
+    }
+
+    pub fn has_stream(&self, stream_index: usize) -> bool {
+        self.streams.contains_key(&stream_index)
+    }
+
+    /// note a packet. packets without any timestamp cannot be placed and are left out.
+    pub unsafe fn record_packet(&mut self, packet: *const ffi::AVPacket) {
+        let p = &*packet;
+        self.record(
+            p.stream_index as usize,
+            p.size.max(0) as usize,
+            p.pts,
+            p.dts,
+            p.duration,
+            p.flags & ffi::AV_PKT_FLAG_KEY as i32 != 0,
+        );
+    }
+
+    /// note a packet of a stream added with `add_stream`
+    pub fn record(
+        &mut self,
+        stream_index: usize,
+        size: usize,
+        pts: i64,
+        dts: i64,
+        duration: i64,
+        keyframe: bool,
+    ) {
+        let key = if dts != ffi::AV_NOPTS_VALUE { dts } else { pts };
+        if key == ffi::AV_NOPTS_VALUE {
+            return;
+        }
+        let stream = match self.streams.get_mut(&stream_index) {
+            Some(stream) => stream,
+            None => return,
+        };
+        let time = stream.seconds(key);
+        // a packet read again keeps the picture type it was decoded as
+        let pict_type = stream.packets.get(&key).and_then(|p| p.pict_type);
+        stream
+            .packets
+            .insert(key, PacketRecord { stream_index, size, pts, dts, duration, keyframe, time, pict_type });
+    }
+
+    /// the decoder made a frame of this picture type out of the packet with
+    /// this dts, `AVFrame.pkt_dts`
+    pub fn set_picture_type(&mut self, stream_index: usize, dts: i64, pict_type: char) {
+        if let Some(packet) = self.streams.get_mut(&stream_index).and_then(|s| s.packets.get_mut(&dts)) {
+            packet.pict_type = Some(pict_type);
+        }
+    }
+
+    pub fn len(&self) -> usize {
+        self.streams.values().map(|s| s.packets.len()).sum()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    pub fn clear(&mut self) {
+        self.streams.clear();
+    }
+
+    /// every packet of a stream in decode order
+    pub fn packets(&self, stream_index: usize) -> Vec<PacketRecord> {
+        self.streams
+            .get(&stream_index)
+            .map_or(vec![], |s| s.packets.values().copied().collect())
+    }
+
+    /// the packets of a stream from `from` up to `to` seconds
+    pub fn packets_between(&self, stream_index: usize, from: f64, to: f64) -> Vec<PacketRecord> {
+        match self.streams.get(&stream_index) {
+            Some(stream) if from <= to => stream
+                .packets
+                .range(stream.ticks(from).floor() as i64..=stream.ticks(to).ceil() as i64)
+                .map(|(_, p)| *p)
+                .filter(|p| p.time >= from && p.time <= to)
+                .collect(),
+            _ => vec![],
+        }
+    }
+
+    /// the bitrate of a stream over the `window` seconds before `time`
+    pub fn bitrate_at(&self, stream_index: usize, time: f64, window: f64) -> f64 {
+        if window <= 0. {
+            return 0.;
+        }
+        let bytes: usize = self
+            .packets_between(stream_index, time - window, time)
+            .iter()
+            .filter(|p| p.time > time - window)
+            .map(|p| p.size)
+            .sum();
+        bytes as f64 * 8. / window
+    }
+
+    /// the bitrate of a stream at every packet, each over the `window`
+    /// seconds before it
+    pub fn bitrate(&self, stream_index: usize, window: f64) -> Vec<BitrateSample> {
+        let packets = self.packets(stream_index);
+        if window <= 0. {
+            return vec![];
+        }
+        let mut first = 0;
+        let mut bytes = 0;
+        packets
+            .iter()
+            .map(|packet| {
+                bytes += packet.size;
+                while packets[first].time <= packet.time - window {
+                    bytes -= packets[first].size;
+                    first += 1;
+                }
+                BitrateSample { time: packet.time, bits_per_second: bytes as f64 * 8. / window }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    static MS: ffi::AVRational = ffi::AVRational { num: 1, den: 1000 };
+
+    /// one second of 10 fps video starting at 2000ms, a keyframe and 9 smaller frames of 1000 bytes
+    fn one_second() -> PacketTimeline {
+        let mut timeline = PacketTimeline::new();
+        timeline.add_stream(0, MS, 2000);
+        for i in 0..10 {
+            let size = if i == 0 { 5000 } else { 1000 };
+            timeline.record(0, size, 2000 + i * 100, 2000 + i * 100, 100, i == 0);
+        }
+        timeline
+    }
+
+    #[test]
+    fn test_packets_read_again_are_kept_once() {
+        let mut timeline = one_second();
+        timeline.set_picture_type(0, 2000, 'I');
+        // looped back to the start
+        timeline.record(0, 5000, 2000, 2000, 100, true);
+        // no timestamps, and a stream that was never added
+        timeline.add_stream(1, MS, ffi::AV_NOPTS_VALUE);
+        timeline.record(1, 300, ffi::AV_NOPTS_VALUE, ffi::AV_NOPTS_VALUE, 20, true);
+        timeline.record(2, 300, 0, 0, 20, true);
+        assert_eq!(timeline.len(), 10);
+        let first = timeline.packets(0)[0];
+        assert_eq!((first.size, first.keyframe, first.pict_type, first.time), (5000, true, Some('I'), 0.));
+        assert_eq!(timeline.packets(0)[1].pict_type, None);
+    }
+
+    #[test]
+    fn test_packets_between_times() {
+        let timeline = one_second();
+        let packets = timeline.packets_between(0, 0.25, 0.5);
+        assert_eq!(packets.iter().map(|p| p.pts).collect::<Vec<_>>(), vec![2300, 2400, 2500]);
+        assert!(timeline.packets_between(1, 0., 1.).is_empty());
+    }
+
+    #[test]
+    fn test_sliding_window_bitrate() {
+        let timeline = one_second();
+        // (5000 + 9 * 1000) bytes over the whole second
+        assert_eq!(timeline.bitrate_at(0, 0.9, 1.), 14000. * 8.);
+        // the keyframe has left a half second window
+        assert_eq!(timeline.bitrate_at(0, 0.9, 0.5), 5000. * 8. / 0.5);
+        let samples = timeline.bitrate(0, 0.5);
+        assert_eq!(samples.len(), 10);
+        assert_eq!(samples[0].bits_per_second, 5000. * 8. / 0.5);
+        assert_eq!(samples[9].bits_per_second, 5000. * 8. / 0.5);
+        assert_eq!(samples[4].bits_per_second, 9000. * 8. / 0.5);
+    }
+}